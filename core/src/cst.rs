@@ -0,0 +1,335 @@
+use std::fmt;
+
+use crate::iterator::{CharIndex, CharIndexer, CharIterator};
+use crate::lexer::{Token, TokenKind};
+use crate::parser::{Error, parse_with_tokens};
+use crate::result::*;
+use crate::span::{Span, WithSpan};
+
+
+type Object<Index> = WithSpan<ExpandedValue<Index>, Index>;
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NodeKind {
+    CompactEntry,
+    CompactList,
+    CompactMap,
+    Document,
+    // Line which does not fit in the structure of the document
+    Error,
+    List,
+    ListItem,
+    Map,
+    MapEntry,
+}
+
+
+#[derive(Debug)]
+pub enum SyntaxElement<Index: CharIndex> {
+    Node(SyntaxNode<Index>),
+    Token(Token<Index>),
+}
+
+impl<Index: CharIndex> SyntaxElement<Index> {
+    pub fn span(&self) -> Span<Index> {
+        match self {
+            SyntaxElement::Node(node) => node.span,
+            SyntaxElement::Token(token) => token.span,
+        }
+    }
+}
+
+
+// Node of the lossless syntax tree. Leading comments and blank lines belong to the entry or item which follows them.
+#[derive(Debug)]
+pub struct SyntaxNode<Index: CharIndex> {
+    pub children: Vec<SyntaxElement<Index>>,
+    pub kind: NodeKind,
+    pub span: Span<Index>,
+}
+
+impl<Index: CharIndex> SyntaxNode<Index> {
+    // Assumes that there is at least one child.
+    fn new(kind: NodeKind, children: Vec<SyntaxElement<Index>>) -> Self {
+        Self {
+            span: Span(children.first().unwrap().span().0, children.last().unwrap().span().1),
+            children,
+            kind,
+        }
+    }
+
+    // Returns all tokens in source order.
+    pub fn tokens(&self) -> Vec<&Token<Index>> {
+        let mut tokens = Vec::new();
+
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => tokens.extend(node.tokens()),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+
+        tokens
+    }
+}
+
+
+// Lossless syntax tree, built from the same parser as parse(): its tokens are those recognized by the parser and its
+// nodes follow the value returned by it.
+#[derive(Debug)]
+pub struct SyntaxTree<Index: CharIndex> {
+    pub errors: Vec<Error<Index>>,
+    pub root: SyntaxNode<Index>,
+    source: String,
+    value: Option<Object<Index>>,
+}
+
+impl<Index: CharIndex> SyntaxTree<Index> {
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn text(&self, span: &Span<Index>) -> &str {
+        &self.source[span.0.byte_offset..span.1.byte_offset]
+    }
+
+    // Typed view of the tree, as returned by parse()
+    pub fn value(&self) -> Option<&Object<Index>> {
+        self.value.as_ref()
+    }
+}
+
+impl<Index: CharIndex> fmt::Display for SyntaxTree<Index> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in self.root.tokens() {
+            write!(f, "{}", self.text(&token.span))?;
+        }
+
+        Ok(())
+    }
+}
+
+
+// Assigns the tokens to the entries and items of the parsed value, in source order. Lines which belong to no entry or
+// item become error nodes if they contain anything other than trivia.
+struct Builder<'a, Index: CharIndex> {
+    position: usize,
+    source: &'a str,
+    tokens: Vec<Token<Index>>,
+}
+
+impl<Index: CharIndex> Builder<'_, Index> {
+    fn line_start(&self, byte_offset: usize) -> usize {
+        self.source[..byte_offset].rfind('\n').map(|index| index + 1).unwrap_or(0)
+    }
+
+    fn take_until(&mut self, byte_offset: usize) -> Vec<Token<Index>> {
+        let end = self.tokens[self.position..]
+            .iter()
+            .position(|token| token.span.0.byte_offset >= byte_offset)
+            .map(|index| self.position + index)
+            .unwrap_or(self.tokens.len());
+
+        let tokens = self.tokens[self.position..end].to_vec();
+        self.position = end;
+        tokens
+    }
+
+    // Takes the tokens before the byte offset and the rest of the last line, including its line break.
+    fn take_lines_until(&mut self, byte_offset: usize) -> Vec<Token<Index>> {
+        let mut tokens = self.take_until(byte_offset);
+
+        while !matches!(tokens.last(), Some(Token { kind: TokenKind::Newline, .. })) && self.position < self.tokens.len() {
+            tokens.push(self.tokens[self.position].clone());
+            self.position += 1;
+        }
+
+        tokens
+    }
+
+    // Start of the first line of a list item, i.e. the next one starting with a dash at the indent of the item.
+    fn item_line_start(&self, indent: usize, value_start: usize) -> usize {
+        let mut line_start = true;
+
+        for token in &self.tokens[self.position..] {
+            let start = token.span.0.byte_offset;
+
+            if start >= value_start {
+                break;
+            }
+
+            if line_start && token.kind != TokenKind::Whitespace {
+                if token.kind == TokenKind::Dash && start - self.line_start(start) == indent {
+                    return self.line_start(start);
+                }
+
+                line_start = false;
+            }
+
+            if token.kind == TokenKind::Newline {
+                line_start = true;
+            }
+        }
+
+        self.line_start(value_start)
+    }
+
+    // An entry or an item, with the leading lines since the previous one and the value.
+    fn child(&mut self, kind: NodeKind, line_start: usize, value: &Object<Index>) -> SyntaxElement<Index> {
+        let mut children = lines(self.take_until(line_start));
+
+        match &value.value {
+            ExpandedValue::List { .. } | ExpandedValue::Map { .. } => {
+                let tokens = if self.line_start(value.span.0.byte_offset) > line_start {
+                    self.take_lines_until(line_start + 1)
+                } else {
+                    self.take_until(value.span.0.byte_offset)
+                };
+
+                children.extend(group_compact(tokens));
+                children.extend(self.collection(value));
+            },
+            ExpandedValue::Compact(_) | ExpandedValue::String { .. } => {
+                children.extend(group_compact(self.take_lines_until(value.span.1.byte_offset)));
+            },
+        }
+
+        SyntaxElement::Node(SyntaxNode::new(kind, children))
+    }
+
+    fn collection(&mut self, object: &Object<Index>) -> Option<SyntaxElement<Index>> {
+        let (kind, mut children) = match &object.value {
+            ExpandedValue::List { items, .. } => (NodeKind::List, items.iter().map(|item| {
+                let line_start = self.item_line_start(item.context.indent, item.value.span.0.byte_offset);
+                self.child(NodeKind::ListItem, line_start, &item.value)
+            }).collect::<Vec<_>>()),
+            ExpandedValue::Map { entries, .. } => (NodeKind::Map, entries.iter().map(|entry| {
+                let line_start = self.line_start(entry.key.span.0.byte_offset);
+                self.child(NodeKind::MapEntry, line_start, &entry.value)
+            }).collect()),
+            ExpandedValue::Compact(_) | ExpandedValue::String { .. } => return None,
+        };
+
+        // E.g. the dash of an empty list or a key without a value
+        if self.tokens.get(self.position).is_some_and(|token| token.span.0.byte_offset < object.span.1.byte_offset) {
+            children.extend(lines(self.take_lines_until(object.span.1.byte_offset)));
+        }
+
+        (!children.is_empty()).then(|| SyntaxElement::Node(SyntaxNode::new(kind, children)))
+    }
+}
+
+// Keeps the tokens of lines with only trivia, and turns the other lines into error nodes.
+fn lines<Index: CharIndex>(tokens: Vec<Token<Index>>) -> Vec<SyntaxElement<Index>> {
+    let mut elements = Vec::new();
+    let mut line = Vec::new();
+
+    for token in tokens {
+        let is_newline = token.kind == TokenKind::Newline;
+        line.push(token);
+
+        if is_newline {
+            push_line(&mut elements, std::mem::take(&mut line));
+        }
+    }
+
+    push_line(&mut elements, line);
+    elements
+}
+
+fn push_line<Index: CharIndex>(elements: &mut Vec<SyntaxElement<Index>>, line: Vec<Token<Index>>) {
+    if line.iter().all(|token| token.kind.is_trivia()) {
+        elements.extend(line.into_iter().map(SyntaxElement::Token));
+    } else {
+        elements.push(SyntaxElement::Node(SyntaxNode::new(NodeKind::Error, group_compact(line))));
+    }
+}
+
+
+// Groups the tokens of compact collections into nodes.
+fn group_compact<Index: CharIndex>(tokens: Vec<Token<Index>>) -> Vec<SyntaxElement<Index>> {
+    let mut tokens = tokens.into_iter().peekable();
+    let mut elements = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        elements.push(group_token(token, &mut tokens));
+    }
+
+    elements
+}
+
+fn group_token<Index: CharIndex>(token: Token<Index>, tokens: &mut std::iter::Peekable<std::vec::IntoIter<Token<Index>>>) -> SyntaxElement<Index> {
+    let (kind, close_kind) = match token.kind {
+        TokenKind::LeftBrace => (NodeKind::CompactMap, TokenKind::RightBrace),
+        TokenKind::LeftBracket => (NodeKind::CompactList, TokenKind::RightBracket),
+        _ => return SyntaxElement::Token(token),
+    };
+
+    let mut children = vec![SyntaxElement::Token(token)];
+    let mut entry = Vec::new();
+
+    // Unclosed collections end before the rest of the line.
+    while let Some(token) = tokens.next_if(|token| !matches!(token.kind, TokenKind::Comment | TokenKind::Error | TokenKind::Newline)) {
+        let token_kind = token.kind;
+        let element = group_token(token, tokens);
+
+        if kind == NodeKind::CompactMap && (token_kind == TokenKind::Key || !entry.is_empty()) && !matches!(token_kind, TokenKind::Comma | TokenKind::RightBrace) {
+            entry.push(element);
+            continue;
+        }
+
+        if !entry.is_empty() {
+            children.push(SyntaxElement::Node(SyntaxNode::new(NodeKind::CompactEntry, std::mem::take(&mut entry))));
+        }
+
+        children.push(element);
+
+        if token_kind == close_kind {
+            break;
+        }
+    }
+
+    if !entry.is_empty() {
+        children.push(SyntaxElement::Node(SyntaxNode::new(NodeKind::CompactEntry, entry)));
+    }
+
+    SyntaxElement::Node(SyntaxNode::new(kind, children))
+}
+
+
+pub fn parse_tree<Indexer: CharIndexer>(input: &str) -> SyntaxTree<Indexer::Index> {
+    let (result, tokens) = parse_with_tokens::<Indexer>(input);
+
+    let mut builder = Builder {
+        position: 0,
+        source: input,
+        tokens,
+    };
+
+    let mut children = Vec::new();
+
+    if let Some(object) = &result.object {
+        children.extend(builder.collection(object));
+    }
+
+    children.extend(lines(builder.take_until(input.len() + 1)));
+
+    // Only empty documents have no children.
+    let root = if children.is_empty() {
+        SyntaxNode {
+            children,
+            kind: NodeKind::Document,
+            span: Span::point(&CharIterator::<'_, Indexer>::new(input).marker()),
+        }
+    } else {
+        SyntaxNode::new(NodeKind::Document, children)
+    };
+
+    SyntaxTree {
+        errors: result.errors,
+        root,
+        source: input.to_string(),
+        value: result.object,
+    }
+}
@@ -0,0 +1,601 @@
+use std::fmt;
+use std::marker::PhantomData;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::forward_to_deserialize_any;
+
+use crate::indexers;
+use crate::iterator::{CharIndex, Marker};
+use crate::parser::parse;
+use crate::result::*;
+use crate::span::{Span, WithSpan};
+
+
+// Magic struct and field names used to pass the span of a value through serde to Spanned<T>.
+const SPANNED_NAME: &str = "$__pcrl_private_Spanned";
+const SPANNED_FIELDS: [&str; 3] = ["$__pcrl_private_start", "$__pcrl_private_end", "$__pcrl_private_value"];
+
+// Spans are reported with byte offsets as their index, as with indexers::Character.
+pub type Spanned<T> = WithSpan<T, usize>;
+
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PathItem {
+    ListIndex(usize),
+    MapKey(String),
+}
+
+#[derive(Debug)]
+pub struct Error<Index: CharIndex> {
+    pub message: String,
+    pub path: Vec<PathItem>,
+    pub span: Option<Span<Index>>,
+}
+
+impl<Index: CharIndex> Error<Index> {
+    fn new(message: String, span: Span<Index>) -> Self {
+        Self {
+            message,
+            path: Vec::new(),
+            span: Some(span),
+        }
+    }
+
+    fn expected(expected: &str, found: ValueRef<'_, Index>, span: Span<Index>) -> Self {
+        Self::new(format!("expected {}, found {}", expected, found.kind()), span)
+    }
+
+    fn or_span(mut self, span: Span<Index>) -> Self {
+        self.span.get_or_insert(span);
+        self
+    }
+
+    fn prepend(mut self, item: PathItem) -> Self {
+        self.path.insert(0, item);
+        self
+    }
+
+    pub fn path_string(&self) -> String {
+        let mut output = String::new();
+
+        for item in &self.path {
+            match item {
+                PathItem::ListIndex(index) => {
+                    output.push_str(&format!("[{}]", index));
+                },
+                PathItem::MapKey(key) => {
+                    if !output.is_empty() {
+                        output.push('.');
+                    }
+
+                    output.push_str(key);
+                },
+            }
+        }
+
+        output
+    }
+}
+
+impl<Index: CharIndex> fmt::Display for Error<Index> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{} at {}", self.message, self.path_string())
+        }
+    }
+}
+
+impl<Index: CharIndex> std::error::Error for Error<Index> {}
+
+impl<Index: CharIndex> de::Error for Error<Index> {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        Self {
+            message: message.to_string(),
+            path: Vec::new(),
+            span: None,
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, Debug)]
+enum ValueRef<'de, Index: CharIndex> {
+    Compact(&'de CompactValue<Index>),
+    Expanded(&'de ExpandedValue<Index>),
+    Null,
+}
+
+impl<'de, Index: CharIndex> ValueRef<'de, Index> {
+    fn resolve(self) -> Self {
+        match self {
+            ValueRef::Expanded(ExpandedValue::Compact(value)) => ValueRef::Compact(value),
+            _ => self,
+        }
+    }
+
+    fn kind(self) -> &'static str {
+        match self.resolve() {
+            ValueRef::Compact(CompactValue::Bool(_)) => "boolean",
+            ValueRef::Compact(CompactValue::Float(_)) => "float",
+            ValueRef::Compact(CompactValue::Integer(_)) => "integer",
+            ValueRef::Compact(CompactValue::List { .. }) | ValueRef::Expanded(ExpandedValue::List { .. }) => "list",
+            ValueRef::Compact(CompactValue::Map { .. }) | ValueRef::Expanded(ExpandedValue::Map { .. }) => "map",
+            ValueRef::Compact(CompactValue::Null) | ValueRef::Null => "null",
+            ValueRef::Compact(CompactValue::String(_)) | ValueRef::Expanded(ExpandedValue::String { .. }) => "string",
+            ValueRef::Expanded(ExpandedValue::Compact(_)) => unreachable!(),
+        }
+    }
+}
+
+
+pub struct Deserializer<'de, Index: CharIndex> {
+    span: Span<Index>,
+    value: ValueRef<'de, Index>,
+}
+
+impl<'de, Index: CharIndex> Deserializer<'de, Index> {
+    pub fn new(object: &'de WithSpan<ExpandedValue<Index>, Index>) -> Self {
+        Self {
+            span: object.span,
+            value: ValueRef::Expanded(&object.value),
+        }
+    }
+
+    fn compact(object: &'de WithSpan<CompactValue<Index>, Index>) -> Self {
+        Self {
+            span: object.span,
+            value: ValueRef::Compact(&object.value),
+        }
+    }
+
+    fn integer(&self) -> Result<i64, Error<Index>> {
+        match self.value.resolve() {
+            ValueRef::Compact(CompactValue::Integer(value)) => Ok(*value),
+            value => Err(Error::expected("integer", value, self.span)),
+        }
+    }
+
+    fn float(&self) -> Result<f64, Error<Index>> {
+        match self.value.resolve() {
+            ValueRef::Compact(CompactValue::Float(value)) => Ok(*value),
+            ValueRef::Compact(CompactValue::Integer(value)) => Ok(*value as f64),
+            value => Err(Error::expected("float", value, self.span)),
+        }
+    }
+}
+
+macro_rules! deserialize_integer {
+    ($method:ident) => {
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+            let span = self.span;
+            visitor.visit_i64(self.integer()?).map_err(|error: Error<Index>| error.or_span(span))
+        }
+    };
+}
+
+impl<'de, Index: CharIndex> de::Deserializer<'de> for Deserializer<'de, Index> {
+    type Error = Error<Index>;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let span = self.span;
+
+        match self.value.resolve() {
+            ValueRef::Compact(CompactValue::Bool(value)) => visitor.visit_bool(*value),
+            ValueRef::Compact(CompactValue::Float(value)) => visitor.visit_f64(*value),
+            ValueRef::Compact(CompactValue::Integer(value)) => visitor.visit_i64(*value),
+            ValueRef::Compact(CompactValue::Null) | ValueRef::Null => visitor.visit_unit(),
+            ValueRef::Compact(CompactValue::String(value)) => visitor.visit_borrowed_str(value),
+            ValueRef::Expanded(ExpandedValue::String { string, .. }) => visitor.visit_borrowed_str(string),
+            ValueRef::Compact(CompactValue::List { .. }) | ValueRef::Expanded(ExpandedValue::List { .. }) => self.deserialize_seq(visitor),
+            ValueRef::Compact(CompactValue::Map { .. }) | ValueRef::Expanded(ExpandedValue::Map { .. }) => self.deserialize_map(visitor),
+            ValueRef::Expanded(ExpandedValue::Compact(_)) => unreachable!(),
+        }.map_err(|error: Error<Index>| error.or_span(span))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value.resolve() {
+            ValueRef::Compact(CompactValue::Bool(value)) => visitor.visit_bool(*value).map_err(|error: Error<Index>| error.or_span(self.span)),
+            value => Err(Error::expected("boolean", value, self.span)),
+        }
+    }
+
+    deserialize_integer!(deserialize_i8);
+    deserialize_integer!(deserialize_i16);
+    deserialize_integer!(deserialize_i32);
+    deserialize_integer!(deserialize_i64);
+    deserialize_integer!(deserialize_u8);
+    deserialize_integer!(deserialize_u16);
+    deserialize_integer!(deserialize_u32);
+    deserialize_integer!(deserialize_u64);
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let span = self.span;
+        visitor.visit_f64(self.float()?).map_err(|error: Error<Index>| error.or_span(span))
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value.resolve() {
+            ValueRef::Compact(CompactValue::String(value)) | ValueRef::Expanded(ExpandedValue::String { string: value, .. }) =>
+                visitor.visit_borrowed_str(value).map_err(|error: Error<Index>| error.or_span(self.span)),
+            value => Err(Error::expected("string", value, self.span)),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let span = self.span;
+
+        match self.value.resolve() {
+            ValueRef::Compact(CompactValue::Null) | ValueRef::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }.map_err(|error: Error<Index>| error.or_span(span))
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value.resolve() {
+            ValueRef::Compact(CompactValue::Null) | ValueRef::Null => visitor.visit_unit().map_err(|error: Error<Index>| error.or_span(self.span)),
+            value => Err(Error::expected("null", value, self.span)),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        let span = self.span;
+        visitor.visit_newtype_struct(self).map_err(|error: Error<Index>| error.or_span(span))
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let access = match self.value.resolve() {
+            ValueRef::Compact(CompactValue::List { items, .. }) => SeqAccess::Compact(items.iter().enumerate()),
+            ValueRef::Expanded(ExpandedValue::List { items, .. }) => SeqAccess::Expanded(items.iter().enumerate()),
+            value => return Err(Error::expected("list", value, self.span)),
+        };
+
+        visitor.visit_seq(access).map_err(|error: Error<Index>| error.or_span(self.span))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _name: &'static str, _len: usize, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let access = match self.value.resolve() {
+            ValueRef::Compact(CompactValue::Map { entries, .. }) => MapAccess::Compact(entries.iter(), None),
+            ValueRef::Expanded(ExpandedValue::Map { entries, .. }) => MapAccess::Expanded(entries.iter(), None),
+            value => return Err(Error::expected("map", value, self.span)),
+        };
+
+        visitor.visit_map(access).map_err(|error: Error<Index>| error.or_span(self.span))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, name: &'static str, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> {
+        if name == SPANNED_NAME {
+            return visitor.visit_map(SpannedAccess {
+                deserializer: Some(self),
+                field_index: 0,
+            });
+        }
+
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> {
+        let span = self.span;
+
+        match self.value.resolve() {
+            // x: variant
+            ValueRef::Compact(CompactValue::String(variant)) => {
+                visitor.visit_enum(variant.as_str().into_deserializer())
+            },
+
+            // x:
+            //   variant: value
+            ValueRef::Compact(CompactValue::Map { entries, .. }) if entries.len() == 1 => {
+                let entry = &entries[0];
+
+                visitor.visit_enum(EnumAccess {
+                    key: &entry.key,
+                    value: Deserializer::compact(&entry.value),
+                })
+            },
+            ValueRef::Expanded(ExpandedValue::Map { entries, .. }) if entries.len() == 1 => {
+                let entry = &entries[0];
+
+                visitor.visit_enum(EnumAccess {
+                    key: &entry.key,
+                    value: Deserializer::new(&entry.value),
+                })
+            },
+
+            value => Err(Error::expected("string or map with a single key", value, span)),
+        }.map_err(|error: Error<Index>| error.or_span(span))
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+}
+
+
+enum SeqAccess<'de, Index: CharIndex> {
+    Compact(std::iter::Enumerate<std::slice::Iter<'de, WithSpan<CompactValue<Index>, Index>>>),
+    Expanded(std::iter::Enumerate<std::slice::Iter<'de, ExpandedListItem<Index>>>),
+}
+
+impl<'de, Index: CharIndex> de::SeqAccess<'de> for SeqAccess<'de, Index> {
+    type Error = Error<Index>;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error> {
+        let (index, deserializer) = match self {
+            SeqAccess::Compact(items) => match items.next() {
+                Some((index, item)) => (index, Deserializer::compact(item)),
+                None => return Ok(None),
+            },
+            SeqAccess::Expanded(items) => match items.next() {
+                Some((index, item)) => (index, Deserializer::new(&item.value)),
+                None => return Ok(None),
+            },
+        };
+
+        let span = deserializer.span;
+
+        seed.deserialize(deserializer)
+            .map(Some)
+            .map_err(|error: Error<Index>| error.or_span(span).prepend(PathItem::ListIndex(index)))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        match self {
+            SeqAccess::Compact(items) => Some(items.len()),
+            SeqAccess::Expanded(items) => Some(items.len()),
+        }
+    }
+}
+
+
+enum MapAccess<'de, Index: CharIndex> {
+    Compact(std::slice::Iter<'de, CompactMapEntry<Index>>, Option<&'de CompactMapEntry<Index>>),
+    Expanded(std::slice::Iter<'de, ExpandedMapEntry<Index>>, Option<&'de ExpandedMapEntry<Index>>),
+}
+
+impl<'de, Index: CharIndex> de::MapAccess<'de> for MapAccess<'de, Index> {
+    type Error = Error<Index>;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error> {
+        let key = match self {
+            MapAccess::Compact(entries, current) => {
+                *current = entries.next();
+                current.map(|entry| &entry.key)
+            },
+            MapAccess::Expanded(entries, current) => {
+                *current = entries.next();
+                current.map(|entry| &entry.key)
+            },
+        };
+
+        match key {
+            Some(key) => {
+                seed.deserialize(KeyDeserializer { key })
+                    .map(Some)
+                    .map_err(|error: Error<Index>| error.or_span(key.span).prepend(PathItem::MapKey(key.value.clone())))
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Self::Error> {
+        let (key, deserializer) = match self {
+            MapAccess::Compact(_, Some(entry)) => (&entry.key.value, Deserializer::compact(&entry.value)),
+            MapAccess::Expanded(_, Some(entry)) => (&entry.key.value, Deserializer::new(&entry.value)),
+            _ => panic!("next_value_seed() called before next_key_seed()"),
+        };
+
+        let span = deserializer.span;
+
+        seed.deserialize(deserializer)
+            .map_err(|error: Error<Index>| error.or_span(span).prepend(PathItem::MapKey(key.clone())))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        match self {
+            MapAccess::Compact(entries, _) => Some(entries.len()),
+            MapAccess::Expanded(entries, _) => Some(entries.len()),
+        }
+    }
+}
+
+
+struct SpannedAccess<'de, Index: CharIndex> {
+    deserializer: Option<Deserializer<'de, Index>>,
+    field_index: usize,
+}
+
+impl<'de, Index: CharIndex> de::MapAccess<'de> for SpannedAccess<'de, Index> {
+    type Error = Error<Index>;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error> {
+        match SPANNED_FIELDS.get(self.field_index) {
+            Some(field) => seed.deserialize(field.into_deserializer()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Self::Error> {
+        self.field_index += 1;
+
+        match self.field_index {
+            1 => seed.deserialize(self.deserializer.as_ref().unwrap().span.0.byte_offset.into_deserializer()),
+            2 => seed.deserialize(self.deserializer.as_ref().unwrap().span.1.byte_offset.into_deserializer()),
+            _ => seed.deserialize(self.deserializer.take().unwrap()),
+        }
+    }
+}
+
+
+struct KeyDeserializer<'de, Index: CharIndex> {
+    key: &'de WithSpan<String, Index>,
+}
+
+impl<'de, Index: CharIndex> de::Deserializer<'de> for KeyDeserializer<'de, Index> {
+    type Error = Error<Index>;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_borrowed_str(&self.key.value)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_enum(self.key.value.as_str().into_deserializer())
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+
+struct EnumAccess<'de, Index: CharIndex> {
+    key: &'de WithSpan<String, Index>,
+    value: Deserializer<'de, Index>,
+}
+
+impl<'de, Index: CharIndex> de::EnumAccess<'de> for EnumAccess<'de, Index> {
+    type Error = Error<Index>;
+    type Variant = Deserializer<'de, Index>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error> {
+        let key = self.key;
+
+        let variant = seed.deserialize(KeyDeserializer { key })
+            .map_err(|error: Error<Index>| error.or_span(key.span))?;
+
+        Ok((variant, self.value))
+    }
+}
+
+impl<'de, Index: CharIndex> de::VariantAccess<'de> for Deserializer<'de, Index> {
+    type Error = Error<Index>;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        de::Deserialize::deserialize(self)
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Self::Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
+
+
+impl<'de, T: de::Deserialize<'de>> de::Deserialize<'de> for Spanned<T> {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SpannedVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: de::Deserialize<'de>> Visitor<'de> for SpannedVisitor<T> {
+            type Value = Spanned<T>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("a spanned value")
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+                fn next_field<'de, A: de::MapAccess<'de>>(access: &mut A, field: &'static str) -> Result<(), A::Error> {
+                    match access.next_key::<String>()? {
+                        Some(key) if key == field => Ok(()),
+                        _ => Err(de::Error::missing_field(field)),
+                    }
+                }
+
+                next_field(&mut access, SPANNED_FIELDS[0])?;
+                let start: usize = access.next_value()?;
+
+                next_field(&mut access, SPANNED_FIELDS[1])?;
+                let end: usize = access.next_value()?;
+
+                next_field(&mut access, SPANNED_FIELDS[2])?;
+                let value: T = access.next_value()?;
+
+                Ok(WithSpan::new(value, Span(
+                    Marker { byte_offset: start, index: start },
+                    Marker { byte_offset: end, index: end },
+                )))
+            }
+        }
+
+        deserializer.deserialize_struct(SPANNED_NAME, &SPANNED_FIELDS, SpannedVisitor(PhantomData))
+    }
+}
+
+
+pub fn from_value<'de, T: de::Deserialize<'de>, Index: CharIndex>(object: &'de WithSpan<ExpandedValue<Index>, Index>) -> Result<T, Error<Index>> {
+    T::deserialize(Deserializer::new(object))
+}
+
+pub fn from_str<T: DeserializeOwned>(input: &str) -> Result<T, Error<usize>> {
+    let result = parse::<indexers::Character>(input);
+
+    if let Some(error) = result.errors.first() {
+        return Err(Error::new(error.value.to_string(), error.span));
+    }
+
+    match &result.object {
+        Some(object) => from_value(object),
+
+        // Empty document
+        None => {
+            let marker = Marker { byte_offset: 0, index: 0 };
+
+            T::deserialize(Deserializer {
+                span: Span::point(&marker),
+                value: ValueRef::Null,
+            })
+        },
+    }
+}
@@ -0,0 +1,305 @@
+use std::fmt::{self, Write};
+
+use crate::iterator::CharIndex;
+use crate::line_index::{ColumnEncoding, LineIndex};
+use crate::span::Span;
+use crate::width::{display_width, expand_tabs};
+
+
+// ANSI escape codes
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const CYAN: &str = "\x1b[1;36m";
+const GREEN: &str = "\x1b[1;32m";
+const RED: &str = "\x1b[1;31m";
+const RESET: &str = "\x1b[0m";
+const YELLOW: &str = "\x1b[1;33m";
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Severity {
+    Error,
+    Help,
+    Note,
+    Warning,
+}
+
+impl Severity {
+    fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Help => "help",
+            Severity::Note => "note",
+            Severity::Warning => "warning",
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Severity::Error => RED,
+            Severity::Help => CYAN,
+            Severity::Note => GREEN,
+            Severity::Warning => YELLOW,
+        }
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Label<Index: CharIndex> {
+    pub message: Option<String>,
+    // The primary label is underlined with ^ and locates the diagnostic, secondary labels with -
+    pub primary: bool,
+    pub span: Span<Index>,
+}
+
+
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    // Whether to use ANSI escape codes
+    pub color: bool,
+    // Number of lines shown around each labelled line
+    pub context_lines: usize,
+    // Shown before the location of the primary label, e.g. "config.pcrl"
+    pub path: Option<String>,
+    // Number of columns between tab stops when expanding tabs in source lines
+    pub tab_width: usize,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            color: false,
+            context_lines: 1,
+            path: None,
+            tab_width: 4,
+        }
+    }
+}
+
+
+// A message about a document along with labelled spans, rendered as in:
+//
+// error[E001]: duplicate key 'a'
+//  --> 3:1
+//   |
+// 2 | a: 1
+//   | - first defined here
+// 3 | a: 2
+//   | ^ duplicate key
+//   |
+//   = help: remove one of the entries
+#[derive(Clone, Debug)]
+pub struct Diagnostic<Index: CharIndex> {
+    pub code: Option<String>,
+    // Help and note messages, shown after the source
+    pub footers: Vec<(Severity, String)>,
+    pub labels: Vec<Label<Index>>,
+    pub message: String,
+    pub severity: Severity,
+}
+
+impl<Index: CharIndex> Diagnostic<Index> {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            code: None,
+            footers: Vec::new(),
+            labels: Vec::new(),
+            message: message.into(),
+            severity,
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, message)
+    }
+
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    fn with_label(mut self, span: Span<Index>, message: Option<String>, primary: bool) -> Self {
+        self.labels.push(Label { message, primary, span });
+        self
+    }
+
+    pub fn with_primary(self, span: Span<Index>, message: impl Into<String>) -> Self {
+        self.with_label(span, Some(message.into()), true)
+    }
+
+    pub fn with_secondary(self, span: Span<Index>, message: impl Into<String>) -> Self {
+        self.with_label(span, Some(message.into()), false)
+    }
+
+    // Underlines the span without a message
+    pub fn with_span(self, span: Span<Index>) -> Self {
+        self.with_label(span, None, true)
+    }
+
+    pub fn with_help(mut self, message: impl Into<String>) -> Self {
+        self.footers.push((Severity::Help, message.into()));
+        self
+    }
+
+    pub fn with_note(mut self, message: impl Into<String>) -> Self {
+        self.footers.push((Severity::Note, message.into()));
+        self
+    }
+
+    pub fn render(&self, contents: &str, options: &RenderOptions) -> String {
+        let mut output = String::new();
+        self.write(contents, options, &mut output).unwrap();
+        output
+    }
+
+    pub fn write(&self, contents: &str, options: &RenderOptions, output: &mut dyn Write) -> fmt::Result {
+        let paint = |color: &'static str| if options.color { color } else { "" };
+        let reset = paint(RESET);
+
+        // Without a message, only the source is shown, e.g. for Span::format().
+        if !self.message.is_empty() {
+            write!(output, "{}{}", paint(self.severity.color()), self.severity.name())?;
+
+            if let Some(code) = &self.code {
+                write!(output, "[{}]", code)?;
+            }
+
+            writeln!(output, "{}{}: {}{}", reset, paint(BOLD), self.message, reset)?;
+        }
+
+        let lines = SourceLines::new(contents, options.tab_width);
+
+        // Labels split into one segment per line: (line, start column, end column, label, whether it is the last line),
+        // with columns in display width
+        let mut segments = Vec::new();
+
+        for label in &self.labels {
+            let (start_line, start_column) = lines.display_position(label.span.0.byte_offset);
+            let (mut end_line, mut end_column) = lines.display_position(label.span.1.byte_offset);
+
+            // A span ending with a line break ends on the line before.
+            if end_line > start_line && end_column == 0 {
+                end_line -= 1;
+                end_column = lines.width(end_line);
+            }
+
+            for line in start_line..=end_line {
+                let start = if line == start_line { start_column } else { 0 };
+                let end = if line == end_line { end_column } else { lines.width(line) };
+
+                segments.push((line, start, end.max(start + 1), label, line == end_line));
+            }
+        }
+
+        segments.sort_by_key(|(line, start, _, _, _)| (*line, *start));
+
+        let mut shown_lines = Vec::new();
+
+        for (line, _, _, _, _) in &segments {
+            let first = line.saturating_sub(options.context_lines);
+            let last = (line + options.context_lines).min(lines.count().saturating_sub(1)).max(*line);
+
+            shown_lines.extend(first..=last);
+        }
+
+        shown_lines.sort_unstable();
+        shown_lines.dedup();
+
+        let gutter_width = shown_lines.last().map(|line| (line + 1).to_string().len()).unwrap_or(1);
+        let gutter = " ".repeat(gutter_width);
+        let blue = paint(BLUE);
+
+        let located_label = self.labels.iter().find(|label| label.primary).or(self.labels.first());
+
+        if let Some(label) = located_label {
+            let (line, column) = lines.position(label.span.0.byte_offset);
+            let path = options.path.as_ref().map(|path| format!("{}:", path)).unwrap_or_default();
+
+            writeln!(output, "{}{}-->{} {}{}:{}", gutter, blue, reset, path, line + 1, column + 1)?;
+        }
+
+        if !shown_lines.is_empty() {
+            writeln!(output, "{} {}|{}", gutter, blue, reset)?;
+        }
+
+        for (index, line) in shown_lines.iter().enumerate() {
+            if index > 0 && shown_lines[index - 1] + 1 < *line {
+                writeln!(output, "{}...{}", blue, reset)?;
+            }
+
+            writeln!(output, "{}{: >width$} |{} {}", blue, line + 1, reset, expand_tabs(lines.text(*line), options.tab_width), width = gutter_width)?;
+
+            for (_, start, end, label, is_last_line) in segments.iter().filter(|(segment_line, _, _, _, _)| segment_line == line) {
+                let (marker, color) = if label.primary { ("^", self.severity.color()) } else { ("-", BLUE) };
+
+                write!(output, "{} {}|{} {}{}{}", gutter, blue, reset, " ".repeat(*start), paint(color), marker.repeat(end - start))?;
+
+                if let (Some(message), true) = (&label.message, *is_last_line) {
+                    write!(output, " {}", message)?;
+                }
+
+                writeln!(output, "{}", reset)?;
+            }
+        }
+
+        if !self.footers.is_empty() && !shown_lines.is_empty() {
+            writeln!(output, "{} {}|{}", gutter, blue, reset)?;
+        }
+
+        for (severity, message) in &self.footers {
+            writeln!(output, "{} {}={} {}{}{}: {}", gutter, blue, reset, paint(BOLD), severity.name(), reset, message)?;
+        }
+
+        Ok(())
+    }
+}
+
+
+// The lines of a document, for converting byte offsets to columns.
+struct SourceLines<'a> {
+    contents: &'a str,
+    index: LineIndex,
+    tab_width: usize,
+}
+
+impl<'a> SourceLines<'a> {
+    fn new(contents: &'a str, tab_width: usize) -> Self {
+        Self { contents, index: LineIndex::new(contents), tab_width }
+    }
+
+    // Lines of the document, excluding the empty line after a final line break
+    fn count(&self) -> usize {
+        match self.index.line_range(self.index.line_count() - 1) {
+            Some(range) if range.is_empty() && self.index.line_count() > 1 => self.index.line_count() - 1,
+            _ => self.index.line_count(),
+        }
+    }
+
+    // Without the line break
+    fn text(&self, line: usize) -> &'a str {
+        &self.contents[self.index.line_range(line).unwrap()]
+    }
+
+    fn width(&self, line: usize) -> usize {
+        display_width(self.text(line), self.tab_width)
+    }
+
+    // Line and column in characters of a byte offset, both starting at zero
+    fn position(&self, byte_offset: usize) -> (usize, usize) {
+        let position = self.index.position(byte_offset, ColumnEncoding::Utf32);
+        (position.line, position.column)
+    }
+
+    // As position(), with the column in display width
+    fn display_position(&self, byte_offset: usize) -> (usize, usize) {
+        let line = self.index.position(byte_offset, ColumnEncoding::Utf8).line;
+        let range = self.index.line_range(line).unwrap();
+
+        (line, display_width(&self.contents[range.start..byte_offset.clamp(range.start, range.end)], self.tab_width))
+    }
+}
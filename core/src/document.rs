@@ -0,0 +1,562 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+use serde::Serialize;
+
+use crate::find::FindPathItem;
+use crate::formatter::{FormatOptions, Layout, write_key};
+use crate::indexers;
+use crate::iterator::CharIndex;
+use crate::parser::{ParseResult, parse};
+use crate::result::*;
+use crate::schema::is_schema_directive;
+use crate::ser;
+use crate::span::{Span, WithSpan};
+
+
+const DEFAULT_INDENT_WIDTH: usize = 2;
+
+
+#[derive(Debug)]
+pub enum Error {
+    DuplicateKey(String),
+    NotFound,
+    NotList,
+    NotMap,
+    // The document does not parse, either before or after the edit.
+    Parse,
+    Serialize(ser::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::DuplicateKey(key) => write!(f, "duplicate key '{}'", key),
+            Error::NotFound => write!(f, "path not found"),
+            Error::NotList => write!(f, "value is not a list"),
+            Error::NotMap => write!(f, "value is not a map"),
+            Error::Parse => write!(f, "document is invalid"),
+            Error::Serialize(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ser::Error> for Error {
+    fn from(error: ser::Error) -> Self {
+        Error::Serialize(error)
+    }
+}
+
+
+// Replacement of a byte range of the document's text.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+
+#[derive(Clone, Copy)]
+enum Node<'a> {
+    Compact(&'a WithSpan<CompactValue<usize>, usize>),
+    Expanded(&'a WithSpan<ExpandedValue<usize>, usize>),
+}
+
+impl<'a> Node<'a> {
+    fn span(&self) -> &'a Span<usize> {
+        match self {
+            Node::Compact(value) => &value.span,
+            Node::Expanded(value) => &value.span,
+        }
+    }
+
+    fn compact(&self) -> Option<&'a CompactValue<usize>> {
+        match self {
+            Node::Compact(value) => Some(&value.value),
+            Node::Expanded(WithSpan { value: ExpandedValue::Compact(value), .. }) => Some(value),
+            Node::Expanded(_) => None,
+        }
+    }
+}
+
+
+// Position of a value relative to its parent.
+enum Location<'a> {
+    CompactEntry(&'a [CompactMapEntry<usize>], usize),
+    CompactItem(&'a [WithSpan<CompactValue<usize>, usize>], usize),
+    Entry(&'a [ExpandedMapEntry<usize>], usize),
+    Item(&'a [ExpandedListItem<usize>], usize),
+    Root(&'a WithSpan<ExpandedValue<usize>, usize>),
+}
+
+impl<'a> Location<'a> {
+    fn node(&self) -> Node<'a> {
+        match *self {
+            Location::CompactEntry(entries, index) => Node::Compact(&entries[index].value),
+            Location::CompactItem(items, index) => Node::Compact(&items[index]),
+            Location::Entry(entries, index) => Node::Expanded(&entries[index].value),
+            Location::Item(items, index) => Node::Expanded(&items[index].value),
+            Location::Root(object) => Node::Expanded(object),
+        }
+    }
+}
+
+
+// A PCRL document which can be edited while preserving its formatting and comments.
+pub struct Document {
+    indent_width: usize,
+    result: ParseResult<usize>,
+    text: String,
+}
+
+impl Document {
+    pub fn new(text: &str) -> Self {
+        let result = parse::<indexers::Character>(text);
+
+        Self {
+            indent_width: infer_indent_width(&result),
+            result,
+            text: text.to_string(),
+        }
+    }
+
+    pub fn result(&self) -> &ParseResult<usize> {
+        &self.result
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    // Replaces the value at the given path.
+    pub fn set<T: ?Sized + Serialize>(&mut self, path: &[FindPathItem<'_>], value: &T) -> Result<TextEdit, Error> {
+        let edit = self.set_edit(path, value)?;
+        self.apply(edit)
+    }
+
+    // Appends an entry to the map at the given path.
+    pub fn insert<T: ?Sized + Serialize>(&mut self, path: &[FindPathItem<'_>], key: &str, value: &T) -> Result<TextEdit, Error> {
+        let edit = self.insert_edit(path, None, key, value)?;
+        self.apply(edit)
+    }
+
+    // Inserts an entry in the map at the given path, right after the entry with key `after`.
+    pub fn insert_after<T: ?Sized + Serialize>(&mut self, path: &[FindPathItem<'_>], after: &str, key: &str, value: &T) -> Result<TextEdit, Error> {
+        let edit = self.insert_edit(path, Some(after), key, value)?;
+        self.apply(edit)
+    }
+
+    // Appends an item to the list at the given path.
+    pub fn push<T: ?Sized + Serialize>(&mut self, path: &[FindPathItem<'_>], value: &T) -> Result<TextEdit, Error> {
+        let edit = self.push_edit(path, value)?;
+        self.apply(edit)
+    }
+
+    // Removes the map entry or list item at the given path, along with its comments.
+    pub fn remove(&mut self, path: &[FindPathItem<'_>]) -> Result<TextEdit, Error> {
+        let edit = self.remove_edit(path)?;
+        self.apply(edit)
+    }
+
+
+    fn apply(&mut self, edit: TextEdit) -> Result<TextEdit, Error> {
+        let mut text = self.text.clone();
+        text.replace_range(edit.range.clone(), &edit.text);
+
+        let result = parse::<indexers::Character>(&text);
+
+        if !result.errors.is_empty() {
+            return Err(Error::Parse);
+        }
+
+        self.result = result;
+        self.text = text;
+
+        Ok(edit)
+    }
+
+    fn locate(&self, path: &[FindPathItem<'_>]) -> Result<Option<Location<'_>>, Error> {
+        if !self.result.errors.is_empty() {
+            return Err(Error::Parse);
+        }
+
+        let mut location = match &self.result.object {
+            Some(object) => Location::Root(object),
+            None if path.is_empty() => return Ok(None),
+            None => return Err(Error::NotFound),
+        };
+
+        for path_item in path {
+            let node = location.node();
+
+            location = match (node, node.compact(), path_item) {
+                (Node::Expanded(WithSpan { value: ExpandedValue::Map { entries, .. }, .. }), _, FindPathItem::MapKey(key)) =>
+                    Location::Entry(entries, entries.iter().position(|entry| entry.key.value == *key).ok_or(Error::NotFound)?),
+                (Node::Expanded(WithSpan { value: ExpandedValue::List { items, .. }, .. }), _, FindPathItem::ListIndex(index)) if *index < items.len() =>
+                    Location::Item(items, *index),
+                (_, Some(CompactValue::Map { entries, .. }), FindPathItem::MapKey(key)) =>
+                    Location::CompactEntry(entries, entries.iter().position(|entry| entry.key.value == *key).ok_or(Error::NotFound)?),
+                (_, Some(CompactValue::List { items, .. }), FindPathItem::ListIndex(index)) if *index < items.len() =>
+                    Location::CompactItem(items, *index),
+                _ => return Err(Error::NotFound),
+            };
+        }
+
+        Ok(Some(location))
+    }
+
+    fn set_edit<T: ?Sized + Serialize>(&self, path: &[FindPathItem<'_>], value: &T) -> Result<TextEdit, Error> {
+        let location = self.locate(path)?.ok_or(Error::NotFound)?;
+        let span = location.node().span();
+        let (start, end) = (span.0.byte_offset, span.1.byte_offset);
+
+        let (handle_end, indent, list_item) = match location {
+            Location::CompactEntry(..) | Location::CompactItem(..) => {
+                return Ok(TextEdit {
+                    range: start..end,
+                    text: ser::to_compact_string(value, &self.format_options())?,
+                });
+            },
+            Location::Root(_) => {
+                let mut text = ser::to_string_with_options(value, &self.format_options())?;
+                text.pop();
+
+                return Ok(TextEdit {
+                    range: start..end,
+                    text,
+                });
+            },
+            Location::Entry(entries, index) => {
+                let entry = &entries[index];
+                let key_end = entry.key.span.1.byte_offset;
+                (key_end + self.text[key_end..].find(':').unwrap() + 1, entry.context.indent, false)
+            },
+            Location::Item(items, index) => {
+                let item = &items[index];
+                (self.item_handle_end(item), item.context.indent, true)
+            },
+        };
+
+        let rendered = ser::to_nested_string(value, indent, &self.format_options(), list_item)?;
+
+        // Anything after a value spanning multiple lines is the trailing comment of its last child.
+        let end = if self.text[start..end].contains('\n') { self.line_end(end) } else { end };
+
+        if !self.text[handle_end..start].contains('\n') {
+            // key: value  # comment
+            let trailing = &self.text[end..self.line_end(end)];
+
+            if let Some(block) = rendered.strip_prefix('\n') {
+                // A comment cannot follow "key:", hence it is moved above the line.
+                let line_start = self.line_start(handle_end);
+                let comment = match trailing.trim_start() {
+                    "" => String::new(),
+                    comment => format!("{}{}\n", " ".repeat(line_start_indent(&self.text[line_start..])), comment),
+                };
+
+                return Ok(TextEdit {
+                    range: line_start..self.line_end(end),
+                    text: format!("{}{}\n{}", comment, &self.text[line_start..handle_end], block),
+                });
+            }
+
+            match rendered[1..].split_once('\n') {
+                Some((first_line, rest)) => Ok(TextEdit {
+                    range: start..self.line_end(end),
+                    text: format!("{}{}\n{}", first_line, trailing, rest),
+                }),
+                None => Ok(TextEdit {
+                    range: start..end,
+                    text: rendered[1..].to_string(),
+                }),
+            }
+        } else {
+            // key:
+            //   value
+            Ok(TextEdit {
+                range: handle_end..end,
+                text: rendered,
+            })
+        }
+    }
+
+    fn insert_edit<T: ?Sized + Serialize>(&self, path: &[FindPathItem<'_>], after: Option<&str>, key: &str, value: &T) -> Result<TextEdit, Error> {
+        let mut key_text = String::new();
+        write_key(key, &mut key_text);
+
+        let node = match self.locate(path)? {
+            Some(location) => location.node(),
+            None => {
+                return Ok(self.append_edit(format!("{}:{}", key_text, ser::to_nested_string(value, 0, &self.format_options(), false)?)));
+            },
+        };
+
+        let anchor_index = |keys: &mut dyn Iterator<Item = &String>| -> Result<Option<usize>, Error> {
+            let keys = keys.collect::<Vec<_>>();
+
+            if keys.iter().any(|existing_key| *existing_key == key) {
+                return Err(Error::DuplicateKey(key.to_string()));
+            }
+
+            match after {
+                Some(after) => keys.iter().position(|existing_key| *existing_key == after).map(Some).ok_or(Error::NotFound),
+                None => Ok(keys.len().checked_sub(1)),
+            }
+        };
+
+        match (node, node.compact()) {
+            (Node::Expanded(WithSpan { value: ExpandedValue::Map { entries, .. }, .. }), _) => {
+                let anchor = &entries[anchor_index(&mut entries.iter().map(|entry| &entry.key.value))?.unwrap()];
+                let indent = anchor.context.indent;
+                let position = self.line_end(anchor.value.span.1.byte_offset);
+
+                Ok(TextEdit {
+                    range: position..position,
+                    text: format!("\n{}{}:{}", " ".repeat(indent), key_text, ser::to_nested_string(value, indent, &self.format_options(), false)?),
+                })
+            },
+            (_, Some(CompactValue::Map { entries, .. })) => {
+                let value_text = ser::to_compact_string(value, &self.format_options())?;
+
+                match anchor_index(&mut entries.iter().map(|entry| &entry.key.value))? {
+                    Some(index) => {
+                        let position = entries[index].value.span.1.byte_offset;
+
+                        Ok(TextEdit {
+                            range: position..position,
+                            text: format!(", {}: {}", key_text, value_text),
+                        })
+                    },
+                    None => {
+                        let span = node.span();
+
+                        Ok(TextEdit {
+                            range: span.0.byte_offset..span.1.byte_offset,
+                            text: format!("{{ {}: {} }}", key_text, value_text),
+                        })
+                    },
+                }
+            },
+            _ => Err(Error::NotMap),
+        }
+    }
+
+    fn push_edit<T: ?Sized + Serialize>(&self, path: &[FindPathItem<'_>], value: &T) -> Result<TextEdit, Error> {
+        let node = match self.locate(path)? {
+            Some(location) => location.node(),
+            None => {
+                return Ok(self.append_edit(format!("-{}", ser::to_nested_string(value, 0, &self.format_options(), true)?)));
+            },
+        };
+
+        match (node, node.compact()) {
+            (Node::Expanded(WithSpan { value: ExpandedValue::List { items, .. }, .. }), _) => {
+                let last_item = items.last().unwrap();
+                let indent = last_item.context.indent;
+                let position = self.line_end(last_item.value.span.1.byte_offset);
+
+                Ok(TextEdit {
+                    range: position..position,
+                    text: format!("\n{}-{}", " ".repeat(indent), ser::to_nested_string(value, indent, &self.format_options(), true)?),
+                })
+            },
+            (_, Some(CompactValue::List { items, .. })) => {
+                let value_text = ser::to_compact_string(value, &self.format_options())?;
+
+                match items.last() {
+                    Some(last_item) => {
+                        let position = last_item.span.1.byte_offset;
+
+                        Ok(TextEdit {
+                            range: position..position,
+                            text: format!(", {}", value_text),
+                        })
+                    },
+                    None => {
+                        let span = node.span();
+
+                        Ok(TextEdit {
+                            range: span.0.byte_offset..span.1.byte_offset,
+                            text: format!("[{}]", value_text),
+                        })
+                    },
+                }
+            },
+            _ => Err(Error::NotList),
+        }
+    }
+
+    fn remove_edit(&self, path: &[FindPathItem<'_>]) -> Result<TextEdit, Error> {
+        let location = self.locate(path)?.ok_or(Error::NotFound)?;
+        let parent_path = &path[..path.len().saturating_sub(1)];
+
+        match location {
+            Location::Root(_) => Err(Error::NotFound),
+
+            // An expanded collection cannot be empty, hence its last child is removed by replacing it with a compact one.
+            Location::Entry(entries, _) if entries.len() == 1 && !parent_path.is_empty() =>
+                self.set_edit(parent_path, &BTreeMap::<String, ()>::new()),
+            Location::Item(items, _) if items.len() == 1 && !parent_path.is_empty() =>
+                self.set_edit(parent_path, &Vec::<()>::new()),
+
+            Location::Entry(entries, index) => {
+                let entry = &entries[index];
+                let key_start = entry.key.span.0.byte_offset;
+                let line_start = self.line_start(key_start);
+
+                // - x: y
+                //   z: w
+                if index == 0 && !self.text[line_start..key_start].trim().is_empty() {
+                    let next_entry = &entries[1];
+                    let mut text = String::new();
+
+                    // Comments of the next entry are moved above the list item's handle.
+                    for comment in &next_entry.context.comments {
+                        text.push_str(&" ".repeat(line_start_indent(&self.text[line_start..])));
+                        text.push_str(self.text[self.line_start(comment.contents.span.0.byte_offset)..self.line_end(comment.contents.span.1.byte_offset)].trim_start());
+                        text.push('\n');
+                    }
+
+                    text.push_str(&self.text[line_start..key_start]);
+
+                    return Ok(TextEdit {
+                        range: line_start..next_entry.key.span.0.byte_offset,
+                        text,
+                    });
+                }
+
+                // The schema directive is stored as a comment of the first entry but applies to the whole document.
+                let comments = match entry.context.comments.split_first() {
+                    Some((first, rest)) if parent_path.is_empty() && index == 0 && first.gap == 0 && is_schema_directive(&first.contents.value) => rest,
+                    _ => &entry.context.comments,
+                };
+
+                Ok(self.remove_lines_edit(comments, key_start, entry.value.span.1.byte_offset))
+            },
+            Location::Item(items, index) => {
+                let item = &items[index];
+                let handle_end = self.item_handle_end(item);
+
+                Ok(self.remove_lines_edit(&item.context.comments, handle_end, item.value.span.1.byte_offset))
+            },
+
+            Location::CompactEntry(entries, index) => {
+                let spans = entries
+                    .iter()
+                    .map(|entry| Span(entry.key.span.0, entry.value.span.1))
+                    .collect::<Vec<_>>();
+
+                Ok(remove_compact_edit(&spans, index))
+            },
+            Location::CompactItem(items, index) => {
+                let spans = items
+                    .iter()
+                    .map(|item| item.span)
+                    .collect::<Vec<_>>();
+
+                Ok(remove_compact_edit(&spans, index))
+            },
+        }
+    }
+
+    // Removes whole lines, from the first comment of the context to the end of the value.
+    fn remove_lines_edit(&self, comments: &[StandaloneComment<usize>], start: usize, end: usize) -> TextEdit {
+        let start = comments.first().map(|comment| comment.contents.span.0.byte_offset).unwrap_or(start);
+        let end = self.line_end(end);
+
+        TextEdit {
+            range: self.line_start(start)..(end + 1).min(self.text.len()),
+            text: String::new(),
+        }
+    }
+
+    fn append_edit(&self, line: String) -> TextEdit {
+        let position = self.text.len();
+
+        TextEdit {
+            range: position..position,
+            text: if self.text.is_empty() || self.text.ends_with('\n') {
+                format!("{}\n", line)
+            } else {
+                format!("\n{}\n", line)
+            },
+        }
+    }
+
+    // Returns the offset right after the '-' of a list item, which is the last line with the item's indentation starting with a '-'.
+    fn item_handle_end(&self, item: &ExpandedListItem<usize>) -> usize {
+        let mut line_start = self.line_start(item.value.span.0.byte_offset);
+
+        loop {
+            let line = &self.text[line_start..];
+
+            if line_start_indent(line) == item.context.indent && line[item.context.indent..].starts_with('-') {
+                return line_start + item.context.indent + 1;
+            }
+
+            line_start = self.line_start(line_start - 1);
+        }
+    }
+
+    // Inserted values reuse the document's indentation and are written in compact form where they fit.
+    fn format_options(&self) -> FormatOptions {
+        FormatOptions {
+            indent_width: self.indent_width,
+            layout: Layout::Auto,
+            ..Default::default()
+        }
+    }
+
+    fn line_start(&self, offset: usize) -> usize {
+        self.text[..offset].rfind('\n').map(|index| index + 1).unwrap_or(0)
+    }
+
+    fn line_end(&self, offset: usize) -> usize {
+        self.text[offset..].find('\n').map(|index| offset + index).unwrap_or(self.text.len())
+    }
+}
+
+
+fn line_start_indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn remove_compact_edit(spans: &[Span<usize>], index: usize) -> TextEdit {
+    let range = if index + 1 < spans.len() {
+        // [a, b, c] -> [a, c]
+        spans[index].0.byte_offset..spans[index + 1].0.byte_offset
+    } else if index > 0 {
+        // [a, b] -> [a]
+        spans[index - 1].1.byte_offset..spans[index].1.byte_offset
+    } else {
+        spans[index].0.byte_offset..spans[index].1.byte_offset
+    };
+
+    TextEdit {
+        range,
+        text: String::new(),
+    }
+}
+
+// Uses the indentation of the first nested collection of the root, if any.
+pub fn infer_indent_width<Index: CharIndex>(result: &ParseResult<Index>) -> usize {
+    let entries = match &result.object {
+        Some(WithSpan { value: ExpandedValue::Map { entries, .. }, .. }) => entries,
+        _ => return DEFAULT_INDENT_WIDTH,
+    };
+
+    entries
+        .iter()
+        .find_map(|entry| {
+            let child_indent = match &entry.value.value {
+                ExpandedValue::List { items, .. } => items.first()?.context.indent,
+                ExpandedValue::Map { entries, .. } => entries.first()?.context.indent,
+                _ => return None,
+            };
+
+            child_indent.checked_sub(entry.context.indent).filter(|width| *width > 0)
+        })
+        .unwrap_or(DEFAULT_INDENT_WIDTH)
+}
@@ -1,23 +1,60 @@
+use crate::formatter::is_identifier;
 use crate::parser::*;
 use crate::iterator::CharIndex;
 use crate::result::*;
-use crate::span::WithSpan;
+use crate::span::{Span, WithSpan};
 
 
+// The ancestors are the spans enclosing the result, from the innermost to the root, i.e. those of the
+// entries and the collections containing it.
 #[derive(Debug)]
 pub enum FindResult<'a, Index: CharIndex> {
+    // A key of a compact map, e.g. `a` in `{ a: 1 }`
+    CompactMapKey {
+        ancestors: Vec<Span<Index>>,
+        entry: &'a CompactMapEntry<Index>,
+        path: FindPath<'a>,
+    },
+    // A scalar inside a compact collection
+    CompactValue {
+        ancestors: Vec<Span<Index>>,
+        object: &'a WithSpan<CompactValue<Index>, Index>,
+        path: FindPath<'a>,
+    },
     MapKey {
+        ancestors: Vec<Span<Index>>,
         entry: &'a ExpandedMapEntry<Index>,
         path: FindPath<'a>,
     },
+    // A scalar or a multiline string, with the expanded map entry it is the value of, if any
     Value {
+        ancestors: Vec<Span<Index>>,
+        entry: Option<&'a ExpandedMapEntry<Index>>,
         object: &'a WithSpan<ExpandedValue<Index>, Index>,
         path: FindPath<'a>,
     },
 }
 
+impl<Index: CharIndex> FindResult<'_, Index> {
+    pub fn ancestors(&self) -> &[Span<Index>] {
+        match self {
+            FindResult::CompactMapKey { ancestors, .. } | FindResult::CompactValue { ancestors, .. } | FindResult::MapKey { ancestors, .. } | FindResult::Value { ancestors, .. } => ancestors,
+        }
+    }
 
-#[derive(Debug)]
+    // Span of the key or the value
+    pub fn span(&self) -> Span<Index> {
+        match self {
+            FindResult::CompactMapKey { entry, .. } => entry.key.span,
+            FindResult::CompactValue { object, .. } => object.span,
+            FindResult::MapKey { entry, .. } => entry.key.span,
+            FindResult::Value { object, .. } => object.span,
+        }
+    }
+}
+
+
+#[derive(Clone, Debug)]
 pub enum FindPathItem<'a> {
     ListIndex(usize),
     MapKey(&'a str),
@@ -25,10 +62,58 @@ pub enum FindPathItem<'a> {
 
 type FindPath<'a> = Vec<FindPathItem<'a>>;
 
+// Writes a path as in `servers[2].port`, with keys which are not identifiers quoted, as in `a["b c"]`.
+pub fn path_to_string(path: &[FindPathItem<'_>]) -> String {
+    let mut output = String::new();
+
+    for path_item in path {
+        match path_item {
+            FindPathItem::ListIndex(index) => output += &format!("[{}]", index),
+            FindPathItem::MapKey(key) if is_identifier(key) => {
+                if !output.is_empty() {
+                    output.push('.');
+                }
+
+                output += key;
+            },
+            FindPathItem::MapKey(key) => output += &format!("[{}]", serde_json::Value::from(*key)),
+        }
+    }
+
+    output
+}
+
+
+// A place where something is missing and could be completed, along with the collection it belongs to.
+#[derive(Debug)]
+pub enum Completion<'a, Index: CharIndex> {
+    // An item of a list, after a handle
+    Item {
+        list: &'a ExpandedValue<Index>,
+        path: FindPath<'a>,
+    },
+    // A key of a map, on a blank line or while being typed
+    Key {
+        map: &'a ExpandedValue<Index>,
+        path: FindPath<'a>,
+    },
+    // The value of a key, either inline or nested below it
+    Value {
+        key: &'a str,
+        map: &'a ExpandedValue<Index>,
+        nested: bool,
+        path: FindPath<'a>,
+    },
+}
+
 
+// Finds the key or the scalar value containing the index, or returns None if there is none, e.g. when the
+// index is between items or there is no root object.
 pub fn find<'a, Index: CharIndex>(result: &'a ParseResult<Index>, index: Index, include_end: bool) -> Option<FindResult<'a, Index>> {
-    let mut current_object = result.object.as_ref().unwrap();
+    let mut current_object = result.object.as_ref()?;
+    let mut current_entry = None;
     let mut path = FindPath::new();
+    let mut ancestors = Vec::new();
 
     if !current_object.span.contains_index(index, include_end) {
         return None;
@@ -36,19 +121,41 @@ pub fn find<'a, Index: CharIndex>(result: &'a ParseResult<Index>, index: Index,
 
     'b: loop {
         match &current_object.value {
+            ExpandedValue::Compact(value @ (CompactValue::List { .. } | CompactValue::Map { .. })) => {
+                ancestors.insert(0, current_object.span);
+                return find_compact(value, index, include_end, path, ancestors);
+            },
+            ExpandedValue::Compact(_) | ExpandedValue::String { .. } => {
+                return Some(FindResult::Value {
+                    ancestors,
+                    entry: current_entry,
+                    object: current_object,
+                    path,
+                });
+            },
             ExpandedValue::List { items, .. } => {
+                ancestors.insert(0, current_object.span);
+
                 for (item_index, item) in items.iter().enumerate() {
                     if item.value.span.contains_index(index, include_end) {
                         current_object = &item.value;
+                        current_entry = None;
                         path.push(FindPathItem::ListIndex(item_index));
                         continue 'b;
                     }
                 }
             },
             ExpandedValue::Map { entries, .. } => {
+                ancestors.insert(0, current_object.span);
+
                 for entry in entries {
+                    if entry.key.span.contains_index(index, include_end) || entry.value.span.contains_index(index, include_end) {
+                        ancestors.insert(0, Span(entry.key.span.0, entry.value.span.1));
+                    }
+
                     if entry.key.span.contains_index(index, include_end) {
                         return Some(FindResult::MapKey {
+                            ancestors,
                             entry,
                             path,
                         });
@@ -56,21 +163,106 @@ pub fn find<'a, Index: CharIndex>(result: &'a ParseResult<Index>, index: Index,
 
                     if entry.value.span.contains_index(index, include_end) {
                         current_object = &entry.value;
+                        current_entry = Some(entry);
                         path.push(FindPathItem::MapKey(&entry.key.value));
 
                         continue 'b;
                     }
                 }
             },
-            ExpandedValue::Compact(CompactValue::Integer(_) | CompactValue::Float(_) | CompactValue::String(_) | CompactValue::Bool(_) | CompactValue::Null) => {
-                return Some(FindResult::Value {
-                    object: current_object,
-                    path,
-                });
-            },
-            _ => todo!(),
         }
 
         return None;
     }
 }
+
+fn find_compact<'a, Index: CharIndex>(value: &'a CompactValue<Index>, index: Index, include_end: bool, mut path: FindPath<'a>, mut ancestors: Vec<Span<Index>>) -> Option<FindResult<'a, Index>> {
+    match value {
+        CompactValue::List { items, .. } => {
+            let (item_index, item) = items.iter().enumerate().find(|(_, item)| item.span.contains_index(index, include_end))?;
+            path.push(FindPathItem::ListIndex(item_index));
+
+            find_compact_item(item, index, include_end, path, ancestors)
+        },
+        CompactValue::Map { entries, .. } => {
+            for entry in entries {
+                if entry.key.span.contains_index(index, include_end) || entry.value.span.contains_index(index, include_end) {
+                    ancestors.insert(0, Span(entry.key.span.0, entry.value.span.1));
+                }
+
+                if entry.key.span.contains_index(index, include_end) {
+                    return Some(FindResult::CompactMapKey {
+                        ancestors,
+                        entry,
+                        path,
+                    });
+                }
+
+                if entry.value.span.contains_index(index, include_end) {
+                    path.push(FindPathItem::MapKey(&entry.key.value));
+                    return find_compact_item(&entry.value, index, include_end, path, ancestors);
+                }
+            }
+
+            None
+        },
+        _ => None,
+    }
+}
+
+fn find_compact_item<'a, Index: CharIndex>(item: &'a WithSpan<CompactValue<Index>, Index>, index: Index, include_end: bool, path: FindPath<'a>, mut ancestors: Vec<Span<Index>>) -> Option<FindResult<'a, Index>> {
+    match &item.value {
+        CompactValue::List { .. } | CompactValue::Map { .. } => {
+            ancestors.insert(0, item.span);
+            find_compact(&item.value, index, include_end, path, ancestors)
+        },
+        _ => Some(FindResult::CompactValue {
+            ancestors,
+            object: item,
+            path,
+        }),
+    }
+}
+
+
+// Finds the completion span containing the index, including its end.
+pub fn find_completion<Index: CharIndex>(result: &ParseResult<Index>, index: Index) -> Option<Completion<'_, Index>> {
+    find_completion_in(&result.object.as_ref()?.value, index, FindPath::new())
+}
+
+fn find_completion_in<'a, Index: CharIndex>(value: &'a ExpandedValue<Index>, index: Index, path: FindPath<'a>) -> Option<Completion<'a, Index>> {
+    match value {
+        ExpandedValue::List { items, item_completion_spans } => {
+            if item_completion_spans.iter().any(|span| span.contains_index(index, true)) {
+                return Some(Completion::Item { list: value, path });
+            }
+
+            items.iter().enumerate().find_map(|(item_index, item)| {
+                let mut path = path.clone();
+                path.push(FindPathItem::ListIndex(item_index));
+                find_completion_in(&item.value.value, index, path)
+            })
+        },
+        ExpandedValue::Map { entries, key_completion_spans, value_completion_spans } => {
+            if key_completion_spans.iter().any(|span| span.contains_index(index, true)) {
+                return Some(Completion::Key { map: value, path });
+            }
+
+            if let Some(value_completion) = value_completion_spans.iter().find(|value_completion| value_completion.span.contains_index(index, true)) {
+                return Some(Completion::Value {
+                    key: &value_completion.key.value,
+                    map: value,
+                    nested: value_completion.nested,
+                    path,
+                });
+            }
+
+            entries.iter().find_map(|entry| {
+                let mut path = path.clone();
+                path.push(FindPathItem::MapKey(&entry.key.value));
+                find_completion_in(&entry.value.value, index, path)
+            })
+        },
+        _ => None,
+    }
+}
@@ -0,0 +1,484 @@
+use crate::iterator::CharIndex;
+use crate::parser::ParseResult;
+use crate::result::*;
+use crate::span::WithSpan;
+use crate::width::display_width;
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Layout {
+    // Collections are written in the form they were parsed in.
+    Preserve,
+
+    // Collections are written in compact form if they fit in the maximum line width and carry no comments, and in
+    // expanded form otherwise.
+    Auto,
+}
+
+#[derive(Clone, Debug)]
+pub struct FormatOptions {
+    pub indent_width: usize,
+    pub layout: Layout,
+    pub max_width: usize,
+    pub trailing_commas: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            indent_width: 2,
+            layout: Layout::Preserve,
+            max_width: 80,
+            trailing_commas: false,
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum StringContext {
+    // [...] and {...}
+    Compact,
+
+    // x: ...
+    MapValue,
+
+    // - ...
+    ListItem,
+}
+
+pub(crate) fn is_identifier(string: &str) -> bool {
+    let mut chars = string.chars();
+
+    matches!(chars.next(), Some('A'..='Z' | 'a'..='z' | '_'))
+        && chars.all(|ch| ch.is_alphanumeric() || ch == '_')
+}
+
+// Mirrors the rules of Parser::accept_expr() and Parser::accept_key().
+fn string_needs_quotes(string: &str, context: StringContext) -> bool {
+    let first_char = match string.chars().next() {
+        Some(ch) => ch,
+        None => return true,
+    };
+
+    if matches!(first_char, '+' | '-' | '0'..='9' | '.' | '[' | '{' | '"' | ' ' | '\t') || string.ends_with([' ', '\t']) {
+        return true;
+    }
+
+    if ["null", "true", "false", "inf", "nan"].iter().any(|constant| string.starts_with(constant)) {
+        return true;
+    }
+
+    if string.chars().any(|ch| ch == '#' || ch.is_control()) {
+        return true;
+    }
+
+    match context {
+        StringContext::Compact => string.contains([',', ']', '}']),
+        StringContext::ListItem => {
+            let key_end = string.find(|ch: char| !(ch.is_alphanumeric() || ch == '_')).unwrap_or(string.len());
+            is_identifier(&string[..key_end]) && string[key_end..].trim_start_matches([' ', '\t']).starts_with(':')
+        },
+        StringContext::MapValue => false,
+    }
+}
+
+fn write_quoted(string: &str, output: &mut String) {
+    output.push('"');
+
+    for ch in string.chars() {
+        match ch {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            _ if ch.is_control() => output.push_str(&format!("\\u{:04x}", ch as u32)),
+            _ => output.push(ch),
+        }
+    }
+
+    output.push('"');
+}
+
+pub(crate) fn write_string(string: &str, context: StringContext, output: &mut String) {
+    if string_needs_quotes(string, context) {
+        write_quoted(string, output);
+    } else {
+        output.push_str(string);
+    }
+}
+
+// Writes a map key, quoted unless it is an identifier.
+pub fn write_key(key: &str, output: &mut String) {
+    if is_identifier(key) {
+        output.push_str(key);
+    } else {
+        write_quoted(key, output);
+    }
+}
+
+pub(crate) fn write_float(value: f64, output: &mut String) {
+    if value.is_nan() {
+        output.push_str("nan");
+    } else if value.is_infinite() {
+        output.push_str(if value > 0.0 { "inf" } else { "-inf" });
+    } else {
+        // The Debug implementation always includes a decimal point or an exponent.
+        output.push_str(&format!("{:?}", value));
+    }
+}
+
+
+#[derive(Clone, Copy, Debug)]
+enum NodeRef<'a, Index: CharIndex> {
+    Compact(&'a CompactValue<Index>),
+    Expanded(&'a ExpandedValue<Index>),
+}
+
+struct EntryRef<'a, Index: CharIndex> {
+    comment: Option<&'a WithSpan<String, Index>>,
+    context: Option<&'a Context<Index>>,
+    key: &'a str,
+    value: NodeRef<'a, Index>,
+}
+
+struct ItemRef<'a, Index: CharIndex> {
+    comment: Option<&'a WithSpan<String, Index>>,
+    context: Option<&'a Context<Index>>,
+    value: NodeRef<'a, Index>,
+}
+
+enum Collection<'a, Index: CharIndex> {
+    List(Vec<ItemRef<'a, Index>>),
+    Map(Vec<EntryRef<'a, Index>>),
+}
+
+impl<'a, Index: CharIndex> Collection<'a, Index> {
+    fn is_empty(&self) -> bool {
+        match self {
+            Collection::List(items) => items.is_empty(),
+            Collection::Map(entries) => entries.is_empty(),
+        }
+    }
+}
+
+impl<'a, Index: CharIndex> NodeRef<'a, Index> {
+    fn resolve(self) -> Self {
+        match self {
+            NodeRef::Expanded(ExpandedValue::Compact(value)) => NodeRef::Compact(value),
+            _ => self,
+        }
+    }
+
+    // Returns None for scalars.
+    fn collection(self) -> Option<Collection<'a, Index>> {
+        match self.resolve() {
+            NodeRef::Compact(CompactValue::List { items, .. }) =>
+                Some(Collection::List(items.iter().map(|item| ItemRef {
+                    comment: None,
+                    context: None,
+                    value: NodeRef::Compact(&item.value),
+                }).collect())),
+            NodeRef::Compact(CompactValue::Map { entries, .. }) =>
+                Some(Collection::Map(entries.iter().map(|entry| EntryRef {
+                    comment: None,
+                    context: None,
+                    key: &entry.key.value,
+                    value: NodeRef::Compact(&entry.value.value),
+                }).collect())),
+            NodeRef::Expanded(ExpandedValue::List { items, .. }) =>
+                Some(Collection::List(items.iter().map(|item| ItemRef {
+                    comment: item.comment.as_ref(),
+                    context: Some(&item.context),
+                    value: NodeRef::Expanded(&item.value.value),
+                }).collect())),
+            NodeRef::Expanded(ExpandedValue::Map { entries, .. }) =>
+                Some(Collection::Map(entries.iter().map(|entry| EntryRef {
+                    comment: entry.comment.as_ref(),
+                    context: Some(&entry.context),
+                    key: &entry.key.value,
+                    value: NodeRef::Expanded(&entry.value.value),
+                }).collect())),
+            _ => None,
+        }
+    }
+
+    fn has_comments(self) -> bool {
+        match self {
+            NodeRef::Compact(_) => false,
+            NodeRef::Expanded(value) => value.has_comments(),
+        }
+    }
+}
+
+
+// Tab width used to measure comments and strings containing tabs
+const TAB_WIDTH: usize = 4;
+
+fn last_line(output: &str) -> &str {
+    &output[output.rfind('\n').map(|index| index + 1).unwrap_or(0)..]
+}
+
+struct Formatter<'o> {
+    options: &'o FormatOptions,
+    output: String,
+}
+
+impl<'o> Formatter<'o> {
+    fn write_indent(&mut self, indent: usize) {
+        self.output.push_str(&" ".repeat(indent));
+    }
+
+    fn write_gap(&mut self, gap: usize) {
+        self.output.push_str(&"\n".repeat(gap));
+    }
+
+    fn write_comment(&mut self, comment: &str) {
+        self.output.push('#');
+
+        if !comment.is_empty() {
+            self.output.push(' ');
+            self.output.push_str(comment);
+        }
+    }
+
+    fn write_context(&mut self, context: &Context<impl CharIndex>, indent: usize) {
+        self.write_comments(&context.comments, indent);
+        self.write_gap(context.gap);
+    }
+
+    fn write_comments(&mut self, comments: &[StandaloneComment<impl CharIndex>], indent: usize) {
+        for comment in comments {
+            self.write_gap(comment.gap);
+            self.write_indent(indent);
+            self.write_comment(&comment.contents.value);
+            self.output.push('\n');
+        }
+    }
+
+    fn write_line_end(&mut self, comment: Option<&WithSpan<String, impl CharIndex>>) {
+        if let Some(comment) = comment {
+            self.output.push(' ');
+            self.write_comment(&comment.value);
+        }
+
+        self.output.push('\n');
+    }
+
+    // Writes any value in compact form.
+    fn write_compact<Index: CharIndex>(&mut self, node: NodeRef<'_, Index>, context: StringContext) {
+        let trailing_comma = if self.options.trailing_commas { "," } else { "" };
+
+        match node.collection() {
+            Some(collection) if collection.is_empty() => {
+                self.output.push_str(match collection {
+                    Collection::List(_) => "[]",
+                    Collection::Map(_) => "{}",
+                });
+            },
+            Some(Collection::List(items)) => {
+                self.output.push('[');
+
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        self.output.push_str(", ");
+                    }
+
+                    self.write_compact(item.value, StringContext::Compact);
+                }
+
+                self.output.push_str(trailing_comma);
+                self.output.push(']');
+            },
+            Some(Collection::Map(entries)) => {
+                self.output.push_str("{ ");
+
+                for (index, entry) in entries.iter().enumerate() {
+                    if index > 0 {
+                        self.output.push_str(", ");
+                    }
+
+                    write_key(entry.key, &mut self.output);
+                    self.output.push_str(": ");
+                    self.write_compact(entry.value, StringContext::Compact);
+                }
+
+                self.output.push_str(trailing_comma);
+                self.output.push_str(" }");
+            },
+            None => match node.resolve() {
+                NodeRef::Compact(CompactValue::Bool(value)) => self.output.push_str(if *value { "true" } else { "false" }),
+                NodeRef::Compact(CompactValue::Float(value)) => write_float(*value, &mut self.output),
+                NodeRef::Compact(CompactValue::Integer(value)) => self.output.push_str(&value.to_string()),
+                NodeRef::Compact(CompactValue::Null) => self.output.push_str("null"),
+                NodeRef::Compact(CompactValue::String(value)) | NodeRef::Expanded(ExpandedValue::String { string: value, .. }) =>
+                    write_string(value, context, &mut self.output),
+                _ => unreachable!(),
+            },
+        }
+    }
+
+    fn is_compact<Index: CharIndex>(&self, node: NodeRef<'_, Index>, comment: Option<&WithSpan<String, Index>>) -> bool {
+        match node.collection() {
+            None => true,
+            Some(collection) if collection.is_empty() => true,
+            Some(_) => match self.options.layout {
+                Layout::Preserve => matches!(node.resolve(), NodeRef::Compact(_)),
+                Layout::Auto if node.has_comments() => false,
+                Layout::Auto => {
+                    // The line as it would be written in compact form
+                    let mut formatter = Formatter {
+                        options: self.options,
+                        output: format!("{} ", last_line(&self.output)),
+                    };
+
+                    formatter.write_compact(node, StringContext::MapValue);
+
+                    if let Some(comment) = comment {
+                        formatter.output.push(' ');
+                        formatter.write_comment(&comment.value);
+                    }
+
+                    display_width(&formatter.output, TAB_WIDTH) <= self.options.max_width
+                },
+            },
+        }
+    }
+
+    // Writes what follows "key:" or "-", including the line break.
+    fn write_nested<Index: CharIndex>(&mut self, node: NodeRef<'_, Index>, comment: Option<&WithSpan<String, Index>>, indent: usize, context: StringContext) {
+        if self.is_compact(node, comment) {
+            self.output.push(' ');
+            self.write_compact(node, context);
+            self.write_line_end(comment);
+            return;
+        }
+
+        let nested_indent = indent + self.options.indent_width;
+
+        match node.collection().unwrap() {
+            // - x: y
+            //   z: w
+            Collection::Map(entries) if context == StringContext::ListItem && comment.is_none() => {
+                let first_entry = &entries[0];
+
+                // Entries are aligned with the first key, whatever the indent width.
+                let key_indent = indent + "- ".len();
+
+                self.output.push(' ');
+                write_key(first_entry.key, &mut self.output);
+                self.output.push(':');
+                self.write_nested(first_entry.value, first_entry.comment, key_indent, StringContext::MapValue);
+                self.write_entries(&entries[1..], key_indent);
+            },
+            Collection::Map(entries) => {
+                self.write_line_end(comment);
+                self.write_entries(&entries, nested_indent);
+            },
+            Collection::List(items) => {
+                self.write_line_end(comment);
+                self.write_items(&items, nested_indent);
+            },
+        }
+    }
+
+    fn write_entries<Index: CharIndex>(&mut self, entries: &[EntryRef<'_, Index>], indent: usize) {
+        for entry in entries {
+            if let Some(context) = entry.context {
+                self.write_context(context, indent);
+            }
+
+            self.write_indent(indent);
+            write_key(entry.key, &mut self.output);
+            self.output.push(':');
+            self.write_nested(entry.value, entry.comment, indent, StringContext::MapValue);
+        }
+    }
+
+    fn write_items<Index: CharIndex>(&mut self, items: &[ItemRef<'_, Index>], indent: usize) {
+        for item in items {
+            if let Some(context) = item.context {
+                self.write_context(context, indent);
+            }
+
+            // The comments of the first entry of a map can only be written before the list handle.
+            if item.comment.is_none() && !self.is_compact(item.value, None) {
+                if let Some(Collection::Map(entries)) = item.value.collection() {
+                    if let Some(context) = entries[0].context {
+                        self.write_context(context, indent);
+                    }
+                }
+            }
+
+            self.write_indent(indent);
+            self.output.push('-');
+            self.write_nested(item.value, item.comment, indent, StringContext::ListItem);
+        }
+    }
+}
+
+
+// Formats a document, re-indenting it while preserving comments and blank lines.
+pub fn format_with_options<Index: CharIndex>(object: &WithSpan<ExpandedValue<Index>, Index>, options: &FormatOptions) -> String {
+    let mut formatter = Formatter {
+        options,
+        output: String::new(),
+    };
+
+    let node = NodeRef::Expanded(&object.value);
+
+    // The root collection is always expanded.
+    match node.collection() {
+        Some(Collection::List(items)) => formatter.write_items(&items, 0),
+        Some(Collection::Map(entries)) => formatter.write_entries(&entries, 0),
+        None => {
+            formatter.write_compact(node, StringContext::MapValue);
+            formatter.output.push('\n');
+        },
+    }
+
+    formatter.output
+}
+
+pub fn format<Index: CharIndex>(object: &WithSpan<ExpandedValue<Index>, Index>) -> String {
+    format_with_options(object, &FormatOptions::default())
+}
+
+// As format_with_options(), also writing the comments after the last value. Returns None if the document has no
+// value because of errors.
+pub fn format_document<Index: CharIndex>(result: &ParseResult<Index>, options: &FormatOptions) -> Option<String> {
+    let output = match &result.object {
+        Some(object) => format_with_options(object, options),
+        None if result.errors.is_empty() => String::new(),
+        None => return None,
+    };
+
+    let mut formatter = Formatter { options, output };
+
+    formatter.write_comments(&result.trailing_comments, 0);
+    Some(formatter.output)
+}
+
+// Writes what follows "key:" or "-" for a value nested in a line indented by `indent`, without the final line break.
+pub(crate) fn format_nested<Index: CharIndex>(value: &ExpandedValue<Index>, indent: usize, options: &FormatOptions, context: StringContext) -> String {
+    let mut formatter = Formatter {
+        options,
+        output: " ".repeat(indent),
+    };
+
+    formatter.write_nested(NodeRef::Expanded(value), None, indent, context);
+    formatter.output.pop();
+    formatter.output.split_off(indent)
+}
+
+// Writes a value in compact form, as inside a compact collection.
+pub(crate) fn format_compact<Index: CharIndex>(value: &ExpandedValue<Index>, options: &FormatOptions) -> String {
+    let mut formatter = Formatter {
+        options,
+        output: String::new(),
+    };
+
+    formatter.write_compact(NodeRef::Expanded(value), StringContext::Compact);
+    formatter.output
+}
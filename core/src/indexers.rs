@@ -27,7 +27,7 @@ impl CharIndexer for Empty {
     }
 
     fn export(&mut self, _: &str) -> Self::Index {
-        ()
+
     }
 }
 
@@ -99,7 +99,7 @@ impl Ord for LineColumnIndex {
 
 impl PartialOrd for LineColumnIndex {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        (self.line, self.column).partial_cmp(&(other.line, other.column))
+        Some(self.cmp(other))
     }
 }
 
@@ -0,0 +1,206 @@
+use crate::iterator::CharIndex;
+use crate::result::*;
+use crate::schema::{ConstType, Schema, SchemaType};
+
+
+// Strings with few distinct values become an enum, provided each value occurs several times on average,
+// so that unique values such as names do not.
+const MAX_ENUM_VALUES: usize = 5;
+const MIN_ENUM_OCCURRENCES: usize = 2;
+
+
+// The values found at one location across all samples.
+#[derive(Default)]
+struct Shape {
+    booleans: bool,
+    description: Option<String>,
+    // Shape of the items of all lists
+    list: Option<Box<Shape>>,
+    map: Option<MapShape>,
+    nulls: bool,
+    // Range of the numbers, and whether they are all integers
+    numbers: Option<(f64, f64, bool)>,
+    // Distinct strings along with their number of occurrences
+    strings: Vec<(String, usize)>,
+}
+
+#[derive(Default)]
+struct MapShape {
+    count: usize,
+    // Shapes of the values along with the number of maps containing the key, in order of appearance
+    entries: Vec<(String, Shape, usize)>,
+}
+
+impl MapShape {
+    fn entry(&mut self, key: &str) -> &mut Shape {
+        let index = match self.entries.iter().position(|(entry_key, _, _)| entry_key == key) {
+            Some(index) => index,
+            None => {
+                self.entries.push((key.to_string(), Shape::default(), 0));
+                self.entries.len() - 1
+            },
+        };
+
+        let (_, shape, count) = &mut self.entries[index];
+        *count += 1;
+
+        shape
+    }
+}
+
+impl Shape {
+    fn is_empty(&self) -> bool {
+        !self.booleans && self.list.is_none() && self.map.is_none() && !self.nulls && self.numbers.is_none() && self.strings.is_empty()
+    }
+
+    fn add_number(&mut self, value: f64, integer: bool) {
+        self.numbers = Some(match self.numbers {
+            Some((minimum, maximum, all_integers)) => (minimum.min(value), maximum.max(value), all_integers && integer),
+            None => (value, value, integer),
+        });
+    }
+
+    fn add_string(&mut self, value: &str) {
+        match self.strings.iter_mut().find(|(string, _)| string == value) {
+            Some((_, count)) => *count += 1,
+            None => self.strings.push((value.to_string(), 1)),
+        }
+    }
+
+    fn add_compact<Index: CharIndex>(&mut self, value: &CompactValue<Index>) {
+        match value {
+            CompactValue::Bool(_) => self.booleans = true,
+            CompactValue::Float(value) => self.add_number(*value, false),
+            CompactValue::Integer(value) => self.add_number(*value as f64, true),
+            CompactValue::List { items, .. } => {
+                let list = self.list.get_or_insert_with(Default::default);
+
+                for item in items {
+                    list.add_compact(&item.value);
+                }
+            },
+            CompactValue::Map { entries, .. } => {
+                let map = self.map.get_or_insert_with(Default::default);
+                map.count += 1;
+
+                for entry in entries {
+                    map.entry(&entry.key.value).add_compact(&entry.value.value);
+                }
+            },
+            CompactValue::Null => self.nulls = true,
+            CompactValue::String(value) => self.add_string(value),
+        }
+    }
+
+    fn add_expanded<Index: CharIndex>(&mut self, value: &ExpandedValue<Index>) {
+        match value {
+            ExpandedValue::Compact(value) => self.add_compact(value),
+            ExpandedValue::List { items, .. } => {
+                let list = self.list.get_or_insert_with(Default::default);
+
+                for item in items {
+                    list.add_expanded(&item.value.value);
+                }
+            },
+            ExpandedValue::Map { entries, .. } => {
+                let map = self.map.get_or_insert_with(Default::default);
+                map.count += 1;
+
+                for entry in entries {
+                    let shape = map.entry(&entry.key.value);
+
+                    if shape.description.is_none() {
+                        shape.description = entry.context.doc_comment();
+                    }
+
+                    shape.add_expanded(&entry.value.value);
+                }
+            },
+            ExpandedValue::String { string, .. } => self.add_string(string),
+        }
+    }
+
+    fn to_schema(&self) -> Schema {
+        let mut kinds = Vec::new();
+
+        if let Some(map) = &self.map {
+            kinds.push(SchemaType::Map {
+                additional_properties: None,
+                properties: map.entries.iter().map(|(key, shape, _)| (key.clone(), shape.to_schema())).collect(),
+                max_properties: None,
+                min_properties: 0,
+                required: map.entries.iter().filter(|(_, _, count)| *count == map.count).map(|(key, _, _)| key.clone()).collect(),
+                dependent_required: Default::default(),
+            });
+        }
+
+        if let Some(list) = &self.list {
+            kinds.push(SchemaType::Array {
+                items: (!list.is_empty()).then(|| Box::new(list.to_schema())),
+                max_items: None,
+                min_items: 0,
+                prefix_items: Vec::new(),
+                unique_items: false,
+            });
+        }
+
+        if !self.strings.is_empty() {
+            let occurrences = self.strings.iter().map(|(_, count)| count).sum::<usize>();
+
+            kinds.push(if self.strings.len() <= MAX_ENUM_VALUES && occurrences >= self.strings.len() * MIN_ENUM_OCCURRENCES {
+                SchemaType::Enum(self.strings.iter().map(|(string, _)| SchemaType::Const(ConstType::String(string.clone()))).collect())
+            } else {
+                SchemaType::String {
+                    format: None,
+                    max_length: None,
+                    min_length: 0,
+                    pattern: None,
+                }
+            });
+        }
+
+        if let Some((minimum, maximum, integer)) = self.numbers {
+            kinds.push(SchemaType::Numeric {
+                integer,
+                multiple_of: None,
+                maximum: Some(maximum),
+                exclusive_maximum: None,
+                minimum: Some(minimum),
+                exclusive_minimum: None,
+            });
+        }
+
+        if self.booleans {
+            kinds.push(SchemaType::Boolean);
+        }
+
+        if self.nulls {
+            kinds.push(SchemaType::Null);
+        }
+
+        let kind = match kinds.len() {
+            0 => SchemaType::Any,
+            1 => kinds.pop().unwrap(),
+            _ => SchemaType::Union(kinds),
+        };
+
+        Schema {
+            description: self.description.clone(),
+            ..kind.into()
+        }
+    }
+}
+
+
+// Infers a schema from example documents. Keys present in all samples are required, strings with few
+// distinct values become enums, numbers are limited to the range of the samples, and the comments
+// above the keys become descriptions. Use Schema::to_json() to obtain a JSON Schema document.
+pub fn infer_schema<'a, Index: CharIndex + 'a>(samples: impl IntoIterator<Item = &'a ExpandedValue<Index>>) -> Schema {
+    let mut shape = Shape::default();
+
+    for sample in samples {
+        shape.add_expanded(sample);
+    }
+
+    shape.to_schema()
+}
@@ -132,19 +132,12 @@ impl<'a, Indexer: CharIndexer> CharIterator<'a, Indexer> {
 
         let start_byte_offset = self.byte_offset;
 
-        loop {
-            match self.next() {
-                Some((ch, size)) => {
-                    if predicate(ch) {
-                        self.byte_offset += size;
-                    } else {
-                        break;
-                    }
-                },
-                None => {
-                    break;
-                },
+        while let Some((ch, size)) = self.next() {
+            if !predicate(ch) {
+                break;
             }
+
+            self.byte_offset += size;
         }
 
         unsafe { std::str::from_utf8_unchecked(&self.bytes[start_byte_offset..self.byte_offset]) }
@@ -156,22 +149,15 @@ impl<'a, Indexer: CharIndexer> CharIterator<'a, Indexer> {
         let start_byte_offset = self.byte_offset;
         let mut end_byte_offset = self.byte_offset;
 
-        loop {
-            match self.next() {
-                Some((ch, size)) => {
-                    if !predicate_while(ch) {
-                        break;
-                    }
+        while let Some((ch, size)) = self.next() {
+            if !predicate_while(ch) {
+                break;
+            }
 
-                    self.byte_offset += size;
+            self.byte_offset += size;
 
-                    if !predicate_until(ch) {
-                        end_byte_offset = self.byte_offset;
-                    }
-                },
-                None => {
-                    break;
-                },
+            if !predicate_until(ch) {
+                end_byte_offset = self.byte_offset;
             }
         }
 
@@ -201,6 +187,12 @@ impl<'a, Indexer: CharIndexer> CharIterator<'a, Indexer> {
         true
     }
 
+    // Only valid for offsets at or after the last marker.
+    pub fn seek(&mut self, byte_offset: usize) {
+        self.byte_offset = byte_offset;
+        self.last_char.set(None);
+    }
+
     pub fn pop_char(&mut self, ch: char) -> bool {
         if self.peek() == Some(ch) {
             self.advance();
@@ -0,0 +1,101 @@
+use std::ops::Range;
+
+use crate::iterator::{CharIndex, CharIndexer, CharIterator};
+use crate::parser::parse_with_tokens;
+use crate::span::Span;
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TokenKind {
+    Colon,
+    Comma,
+    // # text
+    Comment,
+    Dash,
+    // Text which could not be tokenized, up to the end of the line or the next comment
+    Error,
+    // Identifier or quoted string followed by a colon
+    Key,
+    LeftBrace,
+    LeftBracket,
+    Newline,
+    RightBrace,
+    RightBracket,
+    // Unquoted number, constant or string
+    Scalar,
+    // Quoted string
+    String,
+    Whitespace,
+}
+
+impl TokenKind {
+    // Whether the token carries no meaning, i.e. whitespace and comments.
+    pub fn is_trivia(self) -> bool {
+        matches!(self, TokenKind::Comment | TokenKind::Newline | TokenKind::Whitespace)
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Token<Index: CharIndex> {
+    pub kind: TokenKind,
+    pub span: Span<Index>,
+}
+
+
+// Splits the input into tokens covering every byte, as read by the parser.
+pub fn tokenize<Indexer: CharIndexer>(input: &str) -> Vec<Token<Indexer::Index>> {
+    parse_with_tokens::<Indexer>(input).1
+}
+
+
+// Turns the byte ranges of the tokens recognized by the parser into tokens covering every byte of the input. The
+// text between them is split into whitespace, line breaks and errors, the latter up to the end of the line.
+pub(crate) fn complete_tokens<Indexer: CharIndexer>(input: &str, mut ranges: Vec<(TokenKind, Range<usize>)>) -> Vec<Token<Indexer::Index>> {
+    let mut chars = CharIterator::<'_, Indexer>::new(input);
+    let mut tokens = Vec::new();
+    let mut start_marker = chars.marker();
+
+    let mut push = |chars: &mut CharIterator<'_, Indexer>, kind: TokenKind, end_offset: usize| {
+        chars.seek(end_offset);
+        let end_marker = chars.marker();
+
+        tokens.push(Token { kind, span: Span(start_marker, end_marker) });
+        start_marker = end_marker;
+    };
+
+    ranges.sort_by_key(|(_, range)| range.start);
+
+    // The final empty range only fills the text after the last token.
+    ranges.push((TokenKind::Error, input.len()..input.len()));
+
+    for (kind, range) in ranges {
+        // Overlapping ranges are not expected but would break the order of the markers.
+        if range.start < chars.byte_offset {
+            continue;
+        }
+
+        while chars.byte_offset < range.start {
+            let gap = &input[chars.byte_offset..range.start];
+            let whitespace_length = gap.len() - gap.trim_start_matches([' ', '\t']).len();
+
+            let (kind, length) = if whitespace_length > 0 {
+                (TokenKind::Whitespace, whitespace_length)
+            } else if gap.starts_with('\n') {
+                (TokenKind::Newline, 1)
+            } else {
+                let line = &gap[..gap.find('\n').unwrap_or(gap.len())];
+                (TokenKind::Error, line.trim_end_matches([' ', '\t']).len())
+            };
+
+            let end_offset = chars.byte_offset + length;
+            push(&mut chars, kind, end_offset);
+        }
+
+        if !range.is_empty() {
+            push(&mut chars, kind, range.end);
+        }
+    }
+
+    tokens
+}
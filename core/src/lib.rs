@@ -1,13 +1,41 @@
+mod cst;
+pub mod de;
+mod diagnostic;
+pub mod document;
 pub mod indexers;
 mod find;
+mod formatter;
+mod infer;
 mod iterator;
+mod lexer;
+mod line_index;
 mod parser;
+mod query;
 mod result;
+pub mod schema;
+pub mod ser;
+mod sort;
 mod span;
 mod tests;
+pub mod visit;
+mod width;
 
 
-pub use find::{FindResult, find};
+pub use cst::{NodeKind, SyntaxElement, SyntaxNode, SyntaxTree, parse_tree};
+pub use de::{Spanned, from_str, from_value};
+pub use diagnostic::{Diagnostic, Label, RenderOptions, Severity};
+pub use document::{Document, TextEdit, infer_indent_width};
+pub use find::{Completion, FindPathItem, FindResult, find, find_completion, path_to_string};
+pub use formatter::{FormatOptions, Layout, format, format_document, format_with_options, write_key};
+pub use infer::infer_schema;
 pub use iterator::{CharIndexer, CharIterator, Marker};
+pub use lexer::{Token, TokenKind, tokenize};
+pub use line_index::{ColumnEncoding, LineIndex};
 pub use parser::{Error, ParseResult, parse};
+pub use query::{NodeRef, Query, QueryError, QueryErrorKind, QueryMatch, get, query};
 pub use result::*;
+pub use schema::{apply_defaults, validate, validate_with_formats};
+pub use ser::{to_string, to_string_with_options, to_writer};
+pub use sort::{KeyOrder, sort_keys, sort_keys_at};
+pub use span::{Span, WithSpan};
+pub use visit::{Visitor, VisitorMut};
@@ -0,0 +1,194 @@
+use crate::indexers::LineColumnIndex;
+use crate::iterator::{CharIndex, Marker};
+use crate::span::Span;
+
+
+// Unit in which columns are counted
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColumnEncoding {
+    // Bytes
+    Utf8,
+    // Code units, as in the Language Server Protocol
+    Utf16,
+    // Characters
+    Utf32,
+}
+
+impl ColumnEncoding {
+    fn char_len(self, ch: &MultibyteChar) -> usize {
+        match self {
+            ColumnEncoding::Utf8 => ch.len_utf8,
+            ColumnEncoding::Utf16 => ch.len_utf16,
+            ColumnEncoding::Utf32 => 1,
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, Debug)]
+struct MultibyteChar {
+    byte_offset: usize,
+    len_utf8: usize,
+    len_utf16: usize,
+}
+
+
+// Converts between byte offsets and line-column positions of a document in O(log n), e.g. to translate the spans
+// of a document parsed with the Empty indexer. Lines end with "\n", "\r\n" or "\r", as in the Language Server
+// Protocol.
+#[derive(Clone, Debug)]
+pub struct LineIndex {
+    len: usize,
+    // Byte offsets of the start and end of each line, excluding the line break
+    lines: Vec<(usize, usize)>,
+    multibyte_chars: Vec<MultibyteChar>,
+    // Prefix sums of the bytes of multibyte characters beyond their length in UTF-16 and in UTF-32, with one more
+    // item than multibyte_chars
+    utf16_excess: Vec<usize>,
+    utf32_excess: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(contents: &str) -> Self {
+        let mut lines = Vec::new();
+        let mut multibyte_chars = Vec::new();
+        let mut line_start = 0;
+        let mut chars = contents.char_indices().peekable();
+
+        while let Some((byte_offset, ch)) = chars.next() {
+            match ch {
+                '\n' | '\r' => {
+                    let mut next_line_start = byte_offset + 1;
+
+                    if ch == '\r' && chars.next_if(|(_, next)| *next == '\n').is_some() {
+                        next_line_start += 1;
+                    }
+
+                    lines.push((line_start, byte_offset));
+                    line_start = next_line_start;
+                },
+                _ if ch.len_utf8() > 1 => {
+                    multibyte_chars.push(MultibyteChar {
+                        byte_offset,
+                        len_utf8: ch.len_utf8(),
+                        len_utf16: ch.len_utf16(),
+                    });
+                },
+                _ => (),
+            }
+        }
+
+        lines.push((line_start, contents.len()));
+
+        let prefix_sums = |excess: fn(&MultibyteChar) -> usize| {
+            [0].into_iter().chain(multibyte_chars.iter().scan(0, |sum, ch| {
+                *sum += excess(ch);
+                Some(*sum)
+            })).collect()
+        };
+
+        let utf16_excess = prefix_sums(|ch| ch.len_utf8 - ch.len_utf16);
+        let utf32_excess = prefix_sums(|ch| ch.len_utf8 - 1);
+
+        Self {
+            len: contents.len(),
+            lines,
+            multibyte_chars,
+            utf16_excess,
+            utf32_excess,
+        }
+    }
+
+    // Includes the empty line after a final line break
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    // Byte offsets of the start and end of the line, excluding the line break
+    pub fn line_range(&self, line: usize) -> Option<std::ops::Range<usize>> {
+        self.lines.get(line).map(|(start, end)| *start..*end)
+    }
+
+    // Offsets past the end of the document are clamped and offsets inside a character or a line break are moved
+    // back to its start.
+    pub fn position(&self, byte_offset: usize, encoding: ColumnEncoding) -> LineColumnIndex {
+        let byte_offset = byte_offset.min(self.len);
+        let line = self.lines.partition_point(|(start, _)| *start <= byte_offset) - 1;
+        let (line_start, line_end) = self.lines[line];
+
+        let mut byte_offset = byte_offset.min(line_end);
+        let char_index = self.multibyte_chars.partition_point(|ch| ch.byte_offset < byte_offset);
+
+        if let Some(ch) = char_index.checked_sub(1).map(|index| &self.multibyte_chars[index]) {
+            if byte_offset < ch.byte_offset + ch.len_utf8 {
+                byte_offset = ch.byte_offset;
+            }
+        }
+
+        LineColumnIndex {
+            column: self.column(line_start, byte_offset, encoding),
+            line,
+        }
+    }
+
+    // Columns past the end of the line are clamped to it and columns inside a character are moved back to its
+    // start. Returns None if the line does not exist.
+    pub fn offset(&self, position: LineColumnIndex, encoding: ColumnEncoding) -> Option<usize> {
+        let (line_start, line_end) = *self.lines.get(position.line)?;
+
+        let first_char = self.multibyte_chars.partition_point(|ch| ch.byte_offset < line_start);
+        let last_char = self.multibyte_chars.partition_point(|ch| ch.byte_offset < line_end);
+
+        // Column of the multibyte character with the given index
+        let char_column = |index: usize| self.multibyte_chars[index].byte_offset - line_start - self.excess(first_char, index, encoding);
+
+        // Binary search for the end of the multibyte characters of the line which start before the column
+        let (mut low, mut high) = (first_char, last_char);
+
+        while low < high {
+            let middle = (low + high) / 2;
+
+            if char_column(middle) < position.column {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+
+        let byte_offset = match low.checked_sub(1).filter(|index| *index >= first_char) {
+            Some(index) if char_column(index) + encoding.char_len(&self.multibyte_chars[index]) > position.column => self.multibyte_chars[index].byte_offset,
+            _ => line_start + position.column + self.excess(first_char, low, encoding),
+        };
+
+        Some(byte_offset.min(line_end))
+    }
+
+    pub fn marker(&self, byte_offset: usize, encoding: ColumnEncoding) -> Marker<LineColumnIndex> {
+        Marker {
+            byte_offset,
+            index: self.position(byte_offset, encoding),
+        }
+    }
+
+    // Translates a span obtained with any indexer, using its byte offsets
+    pub fn span<Index: CharIndex>(&self, span: Span<Index>, encoding: ColumnEncoding) -> Span<LineColumnIndex> {
+        Span(self.marker(span.0.byte_offset, encoding), self.marker(span.1.byte_offset, encoding))
+    }
+
+    // Column of a byte offset on the line starting at line_start, which must not be inside a character
+    fn column(&self, line_start: usize, byte_offset: usize, encoding: ColumnEncoding) -> usize {
+        let first_char = self.multibyte_chars.partition_point(|ch| ch.byte_offset < line_start);
+        let end_char = self.multibyte_chars.partition_point(|ch| ch.byte_offset < byte_offset);
+
+        byte_offset - line_start - self.excess(first_char, end_char, encoding)
+    }
+
+    // Bytes of the multibyte characters in the range beyond their length in the encoding
+    fn excess(&self, start_char: usize, end_char: usize, encoding: ColumnEncoding) -> usize {
+        match encoding {
+            ColumnEncoding::Utf8 => 0,
+            ColumnEncoding::Utf16 => self.utf16_excess[end_char] - self.utf16_excess[start_char],
+            ColumnEncoding::Utf32 => self.utf32_excess[end_char] - self.utf32_excess[start_char],
+        }
+    }
+}
@@ -1,4 +1,3 @@
-#[cfg(feature = "format")]
 fn main() -> Result<(), ()> {
     let input = "
 a:
@@ -19,36 +18,34 @@ a:
 
     let x = result.object.as_ref().unwrap();
     x.span.format(input, &mut std::io::stdout()).unwrap();
-    println!("");
+    println!();
 
-    match &x.value {
-/*       pcrl::ExpandedValue::List { items, .. } => {
+/*  if let pcrl::ExpandedValue::List { items, .. } = &x.value {
         for item in items.iter() {
           item.value.span.format(input, &mut std::io::stdout()).unwrap();
           println!("");
         }
-      }, */
-      pcrl::ExpandedValue::Map { entries, .. } => {
+    } */
+
+    if let pcrl::ExpandedValue::Map { entries, .. } = &x.value {
         for entry in entries.iter() {
           for comment in &entry.context.comments {
             println!("Comment: {:?}", comment.contents.value);
             comment.contents.span.format(input, &mut std::io::stdout()).unwrap();
-            println!("");
+            println!();
           }
 
           if let Some(comment) = &entry.comment {
             println!("Local comment: {:?}", comment.value);
             comment.span.format(input, &mut std::io::stdout()).unwrap();
-            println!("");
+            println!();
           }
 
           entry.key.span.format(input, &mut std::io::stdout()).unwrap();
-          println!("");
+          println!();
           entry.value.span.format(input, &mut std::io::stdout()).unwrap();
-          println!("");
+          println!();
         }
-      },
-      _ => (),
     }
 
     eprintln!("Result: {:#?}", result.object);
@@ -62,7 +59,7 @@ a:
     // eprintln!("Errors: {:#?}", result.errors);
 
     for error in result.errors {
-        eprintln!("Error: {:#?}", error.value);
+        eprintln!("Error: {}", error.value);
         error.span.format(input, &mut std::io::stdout()).unwrap();
     }
 
@@ -75,8 +72,3 @@ a:
     Ok(())
 }
 
-
-#[cfg(not(feature = "format"))]
-fn main() {
-
-}
@@ -1,3 +1,8 @@
+use std::fmt;
+use std::ops::Range;
+
+use crate::indexers;
+use crate::lexer::{Token, TokenKind, complete_tokens};
 use crate::result::*;
 use crate::iterator::{CharIndex, CharIndexer, CharIterator, Marker};
 use crate::span::{Span, WithSpan};
@@ -7,14 +12,19 @@ use crate::span::{Span, WithSpan};
 enum StackItemKind<Index: CharIndex> {
     List {
         floating_handle_end_marker: Option<Marker<Index>>,
+        item_completion_spans: Vec<Span<Index>>,
         items: Vec<ExpandedListItem<Index>>,
+        next_item_comment: Option<WithSpan<String, Index>>,
         next_item_context: Option<Context<Index>>,
         start_marker: Marker<Index>,
     },
     Map {
         entries: Vec<ExpandedMapEntry<Index>>,
         floating_key: Option<WithSpan<String, Index>>,
+        key_completion_spans: Vec<Span<Index>>,
+        next_entry_comment: Option<WithSpan<String, Index>>,
         next_entry_context: Option<Context<Index>>,
+        value_completion_spans: Vec<ValueCompletionSpan<Index>>,
     },
     // String(String),
 }
@@ -30,6 +40,11 @@ pub struct Parser<'a, Indexer: CharIndexer> {
     chars: CharIterator<'a, Indexer>,
     pub errors: Vec<Error<Indexer::Index>>,
     stack: Vec<StackItem<Indexer::Index>>,
+    // Comments after the last value, which belong to no entry or item
+    pub trailing_comments: Vec<StandaloneComment<Indexer::Index>>,
+    // Byte ranges of the tokens recognized so far, if requested. Whitespace, line breaks and skipped text are
+    // left out and filled in by complete_tokens().
+    tokens: Option<Vec<(TokenKind, Range<usize>)>>,
 }
 
 impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
@@ -38,6 +53,15 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
             chars: CharIterator::new(contents),
             errors: Vec::new(),
             stack: Vec::new(),
+            tokens: None,
+            trailing_comments: Vec::new(),
+        }
+    }
+
+    pub fn with_tokens(contents: &'a str) -> Self {
+        Self {
+            tokens: Some(Vec::new()),
+            ..Self::new(contents)
         }
     }
 
@@ -56,6 +80,7 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
 
 
 // pub type Object<Index> = WithSpan<Value<Index>, Index>;
+type CompactObject<Index> = WithSpan<CompactValue<Index>, Index>;
 pub type Error<Index> = WithSpan<ErrorKind, Index>;
 
 #[derive(Debug)]
@@ -92,6 +117,31 @@ pub enum ErrorKind {
 
     // x: 3.4.5
     InvalidScalarLiteral,
+
+    // x: "a\qb"
+    InvalidEscapeSequence,
+
+    // x: "abc
+    MissingStringClose,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::EmptyExpandedList => write!(f, "empty list item"),
+            ErrorKind::ExtraneousChars => write!(f, "unexpected characters after value"),
+            ErrorKind::InvalidIndent => write!(f, "invalid indentation"),
+            ErrorKind::InvalidIndentSize => write!(f, "indentation does not match any enclosing block"),
+            ErrorKind::MissingListClose => write!(f, "missing ']'"),
+            ErrorKind::MissingMapClose => write!(f, "missing '}}'"),
+            ErrorKind::MissingMapSemicolon => write!(f, "missing ':' after key"),
+            ErrorKind::MissingCompactMapValue => write!(f, "missing value after ':'"),
+            ErrorKind::MissingExpandedMapValue => write!(f, "missing value after ':'"),
+            ErrorKind::InvalidScalarLiteral => write!(f, "invalid literal"),
+            ErrorKind::InvalidEscapeSequence => write!(f, "invalid escape sequence"),
+            ErrorKind::MissingStringClose => write!(f, "missing closing quote"),
+        }
+    }
 }
 
 // #[derive(Clone, Debug)]
@@ -112,7 +162,6 @@ enum Node<Index: CharIndex> {
         handle: ListHandle<Index>,
     },
     ListItem {
-        handle: ListHandle<Index>,
         object: WithSpan<ExpandedValue<Index>, Index>,
     },
     MapKey {
@@ -134,7 +183,7 @@ enum Node<Index: CharIndex> {
 
 impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
     // Only returns None if the first character is \n, #, or EOF.
-    fn accept_expr(&mut self, break_chars: &[char]) -> Result<Option<WithSpan<CompactValue<Indexer::Index>, Indexer::Index>>, ()> {
+    fn accept_expr(&mut self, break_chars: &[char]) -> Result<Option<CompactObject<Indexer::Index>>, ()> {
         self.pop_whitespace();
 
         let start_marker = self.chars.marker();
@@ -145,9 +194,10 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
 
         let value = match ch {
             _ if break_chars.contains(&ch) => return Ok(None),
-            '\n' => return Ok(None),
+            '\n' | '#' => return Ok(None),
             '[' => {
                 self.chars.advance();
+                self.add_token(TokenKind::LeftBracket, start_marker.byte_offset);
                 self.pop_whitespace();
 
                 let mut items = Vec::new();
@@ -158,7 +208,7 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
                     loop {
                         self.pop_whitespace();
 
-                        if !self.chars.pop_char(',') {
+                        if !self.pop_token(',', TokenKind::Comma) {
                             break;
                         }
 
@@ -170,60 +220,82 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
                     }
                 }
 
-                if !self.chars.pop_char(']') {
+                if !self.pop_token(']', TokenKind::RightBracket) {
                     self.errors.push(Error::new(ErrorKind::MissingListClose, Span::point(&self.chars.marker())));
                     return Err(());
                 }
 
                 CompactValue::List {
-                    items: Vec::new(),
+                    items,
                     item_completion_spans: Vec::new(),
                 }
             },
             '{' => {
                 self.chars.advance();
-                self.pop_whitespace();
+                self.add_token(TokenKind::LeftBrace, start_marker.byte_offset);
 
-                let mut items = Vec::new();
+                let mut entries = Vec::new();
 
                 loop {
+                    self.pop_whitespace();
+
                     let key_start_marker = self.chars.marker();
-                    let key = self.chars.pop_while(|ch| ch != ':' && ch != '}');
 
-                    if key.is_empty() {
-                        break;
-                    }
+                    let key = if self.chars.peek() == Some('"') {
+                        match self.pop_quoted_string() {
+                            Ok(key) => key,
+                            Err(kind) => {
+                                self.errors.push(Error::new(kind, Span(key_start_marker, self.chars.marker())));
+                                return Err(());
+                            },
+                        }
+                    } else {
+                        let key = self.chars.pop_until(|ch| ch != ':' && ch != ',' && ch != '}' && ch != '\n', |ch| ch == ' ');
+
+                        if key.is_empty() {
+                            break;
+                        }
+
+                        key.to_string()
+                    };
 
                     let key_span = Span(key_start_marker, self.chars.marker());
+                    self.add_token(TokenKind::Key, key_start_marker.byte_offset);
 
                     self.pop_whitespace();
 
-                    if !self.chars.pop_char(':') {
+                    if !self.pop_token(':', TokenKind::Colon) {
                         self.errors.push(Error::new(ErrorKind::MissingMapSemicolon, Span::point(&self.chars.marker())));
                         return Err(());
                     }
 
-                    self.pop_whitespace();
-
                     if let Some(value) = self.accept_expr(&[',', '}'])? {
-                        items.push((WithSpan { span: key_span, value: key.to_string() }, value));
+                        entries.push(CompactMapEntry {
+                            key: WithSpan::new(key, key_span),
+                            value,
+                        });
                     } else {
                         self.errors.push(Error::new(ErrorKind::MissingCompactMapValue, Span::point(&self.chars.marker())));
                         return Err(());
                     }
 
-                    if !self.chars.pop_char(',') {
+                    self.pop_whitespace();
+
+                    if !self.pop_token(',', TokenKind::Comma) {
                         break;
                     }
                 }
 
-                if !self.chars.pop_char('}') {
+                if !self.pop_token('}', TokenKind::RightBrace) {
                     self.errors.push(Error::new(ErrorKind::MissingMapClose, Span::point(&self.chars.marker())));
                     return Err(());
                 }
 
-                // Value::Map(items)
-                return Err(());
+                CompactValue::Map {
+                    entries,
+                    key_completion_spans: Vec::new(),
+                    value_completion_spans: Vec::new(),
+                }
             },
             '+' if self.chars.pop_constant("+inf") => {
                 CompactValue::Float(f64::INFINITY)
@@ -240,6 +312,7 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
                     if let Ok(value) = string.parse::<f64>() {
                         CompactValue::Float(value)
                     } else {
+                        self.add_token(TokenKind::Scalar, start_marker.byte_offset);
                         self.errors.push(Error::new(ErrorKind::InvalidScalarLiteral, Span(start_marker, self.chars.marker())));
                         return Err(());
                     }
@@ -260,42 +333,122 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
             'n' if self.chars.pop_constant("nan") => {
                 CompactValue::Float(f64::NAN)
             },
+            '"' => {
+                match self.pop_quoted_string() {
+                    Ok(value) => CompactValue::String(value),
+                    Err(kind) => {
+                        self.errors.push(Error::new(kind, Span(start_marker, self.chars.marker())));
+                        return Err(());
+                    },
+                }
+            },
             _ => {
                 let string = self.chars.pop_until(|ch| !break_chars.contains(&ch) && ch != '\n' && ch != '#', |ch| ch == ' ');
                 CompactValue::String(string.to_string())
             },
         };
 
+        match ch {
+            '[' | '{' => (),
+            '"' => self.add_token(TokenKind::String, start_marker.byte_offset),
+            _ => self.add_token(TokenKind::Scalar, start_marker.byte_offset),
+        }
+
         Ok(Some(WithSpan {
             span: Span(start_marker, self.chars.marker()),
             value,
         }))
     }
 
+    // Records where a key, a value or an item could be completed, i.e. a blank line or a key being typed.
+    // It belongs to the innermost collection with the same indent, or to the floating key or handle of the
+    // innermost collection when indented further.
+    fn add_completion_span(&mut self, indent: usize, span: Span<Indexer::Index>) {
+        let Some(position) = self.stack.iter().rposition(|item| item.indent <= indent) else {
+            return;
+        };
+
+        let is_innermost = position + 1 == self.stack.len();
+        let item = &mut self.stack[position];
+
+        match &mut item.kind {
+            StackItemKind::Map { key_completion_spans, .. } if item.indent == indent => {
+                key_completion_spans.push(span);
+            },
+            StackItemKind::Map { floating_key: Some(key), value_completion_spans, .. } if is_innermost => {
+                value_completion_spans.push(ValueCompletionSpan {
+                    key: WithSpan::new(key.value.clone(), key.span),
+                    nested: true,
+                    span,
+                });
+            },
+            StackItemKind::List { floating_handle_end_marker: Some(_), item_completion_spans, .. } if is_innermost && item.indent < indent => {
+                item_completion_spans.push(span);
+            },
+            _ => (),
+        }
+    }
+
+    // Accepts an identifier alone on its line, i.e. a key being typed, as a place for completion. The
+    // identifier is still reported as extraneous.
+    fn accept_partial_key(&mut self, indent: usize) {
+        let rest = &self.chars.bytes[self.chars.byte_offset..];
+        let line = &rest[..rest.iter().position(|byte| *byte == b'\n' || *byte == b'#').unwrap_or(rest.len())];
+
+        let Ok(line) = std::str::from_utf8(line) else {
+            return;
+        };
+
+        let word = line.trim_end_matches(' ');
+        let is_identifier = word.starts_with(|ch: char| ch.is_alphabetic() || ch == '_')
+            && word.chars().all(|ch| ch.is_alphanumeric() || ch == '_');
+
+        if !is_identifier {
+            return;
+        }
+
+        let start_marker = self.chars.marker();
+        self.chars.pop_while(|ch| ch.is_alphanumeric() || ch == '_');
+        let end_marker = self.chars.marker();
+
+        self.add_token(TokenKind::Error, start_marker.byte_offset);
+        self.errors.push(Error::new(ErrorKind::ExtraneousChars, Span(start_marker, end_marker)));
+        self.add_completion_span(indent, Span(start_marker, end_marker));
+    }
+
     fn reduce_stack(&mut self, level: usize) -> Option<WithSpan<ExpandedValue<Indexer::Index>, Indexer::Index>> {
         while self.stack.len() > level {
             let item = self.stack.pop().unwrap();
 
             let object = match item.kind {
-                StackItemKind::List { floating_handle_end_marker, items, start_marker, .. } => {
+                StackItemKind::List { floating_handle_end_marker, item_completion_spans, items, start_marker, .. } => {
                     match items.last() {
                         Some(item) => {
                             WithSpan {
                                 span: Span(start_marker, item.value.span.1),
                                 value: ExpandedValue::List {
-                                    item_completion_spans: Vec::new(),
+                                    item_completion_spans,
                                     items,
                                 },
                             }
                         },
                         None => {
-                            // The list can only be empty if there is a floating handle.
-                            self.errors.push(Error::new(ErrorKind::EmptyExpandedList, Span(start_marker, floating_handle_end_marker.unwrap())));
-                            continue;
+                            // The list can only be empty if there is a floating handle. It is kept for the
+                            // completion of its first item.
+                            let span = Span(start_marker, floating_handle_end_marker.unwrap());
+                            self.errors.push(Error::new(ErrorKind::EmptyExpandedList, span));
+
+                            WithSpan {
+                                span,
+                                value: ExpandedValue::List {
+                                    item_completion_spans,
+                                    items,
+                                },
+                            }
                         },
                     }
                 },
-                StackItemKind::Map { entries, floating_key: None, .. } => {
+                StackItemKind::Map { entries, floating_key: None, key_completion_spans, value_completion_spans, .. } => {
                     WithSpan {
                         span: Span(
                             entries.first().unwrap().key.span.0,
@@ -303,40 +456,42 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
                         ),
                         value: ExpandedValue::Map {
                             entries,
-                            key_completion_spans: Vec::new(),
-                            value_completion_spans: Vec::new(),
+                            key_completion_spans,
+                            value_completion_spans,
                         },
                     }
                 },
-                StackItemKind::Map { entries, floating_key: Some(floating_key), .. } => {
+                StackItemKind::Map { entries, floating_key: Some(floating_key), key_completion_spans, value_completion_spans, .. } => {
                     self.errors.push(Error::new(ErrorKind::MissingExpandedMapValue, floating_key.span));
 
                     WithSpan {
                         span: Span(
-                            entries.first().and_then(|entry| Some(entry.key.span.0)).unwrap_or(floating_key.span.0),
+                            entries.first().map(|entry| entry.key.span.0).unwrap_or(floating_key.span.0),
                             floating_key.span.1,
                         ),
                         value: ExpandedValue::Map {
                             entries,
-                            key_completion_spans: Vec::new(),
-                            value_completion_spans: Vec::new(),
+                            key_completion_spans,
+                            value_completion_spans,
                         },
                     }
                 },
             };
 
-            match self.stack.last_mut().and_then(|item| Some(&mut item.kind)) {
-                Some(StackItemKind::List { items, next_item_context, .. }) => {
+            // The context of the pending item or entry is missing if the parent was reopened by a later line, as in
+            // "-\n  -\na\n    -".
+            match self.stack.last_mut() {
+                Some(StackItem { indent, kind: StackItemKind::List { items, next_item_comment, next_item_context, .. } }) => {
                     items.push(ExpandedListItem {
-                        comment: None,
-                        context: next_item_context.take().unwrap(),
+                        comment: next_item_comment.take(),
+                        context: next_item_context.take().unwrap_or_else(|| Context::new(*indent)),
                         value: object,
                     });
                 },
-                Some(StackItemKind::Map { entries, floating_key: key @ Some(_), next_entry_context }) => {
+                Some(StackItem { indent, kind: StackItemKind::Map { entries, floating_key: key @ Some(_), next_entry_comment, next_entry_context, .. } }) => {
                     entries.push(ExpandedMapEntry {
-                        comment: None,
-                        context: next_entry_context.take().unwrap(),
+                        comment: next_entry_comment.take(),
+                        context: next_entry_context.take().unwrap_or_else(|| Context::new(*indent)),
                         key: key.take().unwrap(),
                         value: object,
                     });
@@ -359,6 +514,11 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
             let line_start_marker = self.chars.marker();
 
             if self.chars.peek().is_none() {
+                // The empty line at the end of the input
+                if line_start_marker.byte_offset > 0 && self.chars.bytes[line_start_marker.byte_offset - 1] == b'\n' {
+                    self.add_completion_span(0, Span::point(&line_start_marker));
+                }
+
                 break;
             }
 
@@ -367,6 +527,11 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
             match self.chars.peek() {
                 // Whitespace-only line
                 Some('\n' | '#') | None => {
+                    if self.chars.peek() != Some('#') {
+                        let line_end_marker = self.chars.marker();
+                        self.add_completion_span(indent, Span(line_start_marker, line_end_marker));
+                    }
+
                     if let Some(comment) = self.accept_line_end() {
                         comments.push(StandaloneComment {
                             contents: comment,
@@ -421,6 +586,7 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
             let handle = match self.chars.peek() {
                 Some('-') => {
                     self.chars.advance();
+                    self.add_token(TokenKind::Dash, content_start_marker.byte_offset);
                     let handle_end_marker = self.chars.marker();
 
                     self.pop_whitespace();
@@ -433,7 +599,15 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
                 _ => None,
             };
 
-            let node = if let Some(key) = self.accept_key() {
+            let key = self.accept_key();
+
+            let node = if key.is_err() {
+                // The rest of the line belongs to the unterminated string.
+                let start_offset = self.chars.byte_offset;
+                self.chars.pop_while(|ch| ch != '\n');
+                self.add_token(TokenKind::Error, start_offset);
+                None
+            } else if let Ok(Some(key)) = key {
                 match self.accept_expr(&[]) {
                     // [-] x: y
                     Ok(Some(value)) => {
@@ -461,7 +635,6 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
                     // - x
                     Ok(Some(item)) => {
                         Some(Node::ListItem {
-                            handle,
                             object: WithSpan::new(ExpandedValue::Compact(item.value), item.span),
                         })
                     },
@@ -478,6 +651,7 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
                     },
                 }
             } else {
+                self.accept_partial_key(indent);
                 None
             };
 
@@ -485,13 +659,29 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
 
             self.pop_whitespace();
 
+            // [-] x: |
+            // - |
+            let line_end_marker = self.chars.marker();
+            let completion_span = Span(content_end_marker, line_end_marker);
+
+            let value_completion = match &node {
+                Some(Node::MapKey { key, .. }) => Some(ValueCompletionSpan {
+                    key: WithSpan::new(key.value.clone(), key.span),
+                    nested: false,
+                    span: completion_span,
+                }),
+                _ => None,
+            };
+
+            let is_list_open = matches!(node, Some(Node::ListOpen { .. }));
+
             let local_comment = self.accept_line_end();
             // let content_comments = std::mem::replace(&mut comments, Vec::new());
             // let content_gap = gap;
             // gap = 0;
 
             let context = Context {
-                comments: std::mem::replace(&mut comments, Vec::new()),
+                comments: std::mem::take(&mut comments),
                 gap,
                 indent,
             };
@@ -505,12 +695,20 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
                 },
             };
 
-            match (node, self.stack.last_mut().and_then(|item| Some(&mut item.kind)), nested) {
+            match (node, self.stack.last_mut().map(|item| &mut item.kind), nested) {
                 // [root]
                 // -
-                (Node::ListOpen { handle }, None, true) => {
+                //
+                // a:
+                //   -
+                //
+                // -
+                //   -
+                (Node::ListOpen { handle }, Some(StackItemKind::Map { floating_key: Some(_), .. } | StackItemKind::List { floating_handle_end_marker: Some(_), .. }) | None, true) => {
                     self.stack.push(StackItem {
                         kind: StackItemKind::List {
+                            item_completion_spans: Vec::new(),
+                            next_item_comment: local_comment,
                             next_item_context: Some(context),
                             floating_handle_end_marker: Some(handle.end_marker),
                             items: Vec::new(),
@@ -522,8 +720,9 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
 
                 // - a
                 // -
-                (Node::ListOpen { handle }, Some(StackItemKind::List { floating_handle_end_marker, next_item_context, .. }), false) => {
+                (Node::ListOpen { handle }, Some(StackItemKind::List { floating_handle_end_marker, next_item_comment, next_item_context, .. }), false) => {
                     *floating_handle_end_marker = Some(handle.end_marker);
+                    *next_item_comment = local_comment;
                     *next_item_context = Some(context);
 
                     // ??
@@ -548,12 +747,14 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
                 (Node::ListItem { object, .. }, Some(StackItemKind::Map { floating_key: Some(_), .. }) | None, true) => {
                     self.stack.push(StackItem {
                         kind: StackItemKind::List {
+                            item_completion_spans: Vec::new(),
                             floating_handle_end_marker: None,
                             items: vec![ExpandedListItem {
                                 comment: local_comment,
                                 context,
                                 value: object,
                             }],
+                            next_item_comment: None,
                             next_item_context: None,
                             start_marker: content_start_marker,
                         },
@@ -566,12 +767,14 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
                 (Node::ListItem { object, .. }, Some(StackItemKind::List { floating_handle_end_marker: Some(_), .. }), true) => {
                     self.stack.push(StackItem {
                         kind: StackItemKind::List {
+                            item_completion_spans: Vec::new(),
                             floating_handle_end_marker: None,
                             items: vec![ExpandedListItem {
                                 comment: local_comment,
                                 context,
                                 value: object,
                             }],
+                            next_item_comment: None,
                             next_item_context: None,
                             start_marker: content_start_marker,
                         },
@@ -606,6 +809,8 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
                     if handle.is_some() {
                         self.stack.push(StackItem {
                             kind: StackItemKind::List {
+                                item_completion_spans: Vec::new(),
+                                next_item_comment: None,
                                 next_item_context: optional_context.take(),
                                 floating_handle_end_marker: None,
                                 items: Vec::new(),
@@ -616,18 +821,18 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
                     }
 
                     let map_indent = handle
-                        .as_ref()
-                        .and_then(|handle| Some(handle.item_indent))
+                        .as_ref().map(|handle| handle.item_indent)
                         .unwrap_or(indent);
 
                     self.stack.push(StackItem {
                         kind: StackItemKind::Map {
+                            key_completion_spans: Vec::new(),
+                            value_completion_spans: Vec::new(),
                             entries: vec![
                                 ExpandedMapEntry {
                                     comment: local_comment,
                                     context: optional_context.unwrap_or(Context::new(
-                                        handle
-                                            .and_then(|handle| Some(handle.item_indent))
+                                        handle.map(|handle| handle.item_indent)
                                             .unwrap_or(indent)
                                     )),
                                     key,
@@ -635,6 +840,7 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
                                 }
                             ],
                             floating_key: None,
+                            next_entry_comment: None,
                             next_entry_context: None,
                         },
                         indent: map_indent,
@@ -648,6 +854,8 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
 
                     self.stack.push(StackItem {
                         kind: StackItemKind::Map {
+                            key_completion_spans: Vec::new(),
+                            value_completion_spans: Vec::new(),
                             entries: vec![ExpandedMapEntry {
                                 comment: local_comment,
                                 context: Context::new(handle.item_indent),
@@ -658,16 +866,21 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
                                 },
                             }],
                             floating_key: None,
+                            next_entry_comment: None,
                             next_entry_context: None,
                         },
-                        indent,
+                        indent: handle.item_indent,
                     });
                 },
 
                 // a: b
                 // x: y
                 (Node::MapEntry { handle: None, key, value }, Some(StackItemKind::Map { entries, floating_key, .. }), false) => {
-                    debug_assert!(floating_key.is_none());
+                    // a:
+                    // x: y
+                    if let Some(floating_key) = floating_key.take() {
+                        self.errors.push(Error::new(ErrorKind::MissingExpandedMapValue, floating_key.span));
+                    }
 
                     entries.push(ExpandedMapEntry {
                         comment: local_comment,
@@ -685,9 +898,13 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
 
                 // a: b
                 // x:
-                (Node::MapKey { handle: None, key }, Some(StackItemKind::Map { floating_key, next_entry_context, .. }), false) => {
-                    debug_assert!(floating_key.is_none());
+                (Node::MapKey { handle: None, key }, Some(StackItemKind::Map { floating_key, next_entry_comment, next_entry_context, .. }), false) => {
+                    if let Some(floating_key) = floating_key.take() {
+                        self.errors.push(Error::new(ErrorKind::MissingExpandedMapValue, floating_key.span));
+                    }
+
                     *floating_key = Some(key);
+                    *next_entry_comment = local_comment;
                     *next_entry_context = Some(context);
                 },
 
@@ -708,8 +925,10 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
                     if handle.is_some() {
                         self.stack.push(StackItem {
                             kind: StackItemKind::List {
+                                item_completion_spans: Vec::new(),
                                 floating_handle_end_marker: None,
                                 items: Vec::new(),
+                                next_item_comment: None,
                                 next_item_context: optional_context.take(),
                                 start_marker: content_start_marker,
                             },
@@ -718,17 +937,18 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
                     }
 
                     let map_indent = optional_context
-                        .as_ref()
-                        .and_then(|context| Some(context.indent))
+                        .as_ref().map(|context| context.indent)
                         .unwrap_or(indent);
 
                     self.stack.push(StackItem {
                         kind: StackItemKind::Map {
+                            key_completion_spans: Vec::new(),
+                            value_completion_spans: Vec::new(),
                             entries: Vec::new(),
                             floating_key: Some(key),
+                            next_entry_comment: local_comment,
                             next_entry_context: optional_context.or(Some(Context::new(
-                                handle
-                                    .and_then(|handle| Some(handle.item_indent))
+                                handle.map(|handle| handle.item_indent)
                                     .unwrap_or(indent)
                             ))),
                         },
@@ -742,10 +962,23 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
                 },
             }
 
+            match self.stack.last_mut().map(|item| &mut item.kind) {
+                Some(StackItemKind::Map { floating_key: Some(key), value_completion_spans, .. }) => {
+                    if let Some(value_completion) = value_completion.filter(|value_completion| value_completion.key.span == key.span) {
+                        value_completion_spans.push(value_completion);
+                    }
+                },
+                Some(StackItemKind::List { floating_handle_end_marker: Some(_), item_completion_spans, .. }) if is_list_open => {
+                    item_completion_spans.push(completion_span);
+                },
+                _ => (),
+            }
+
             // eprintln!("Comment: {:#?}", comment);
         }
 
         // eprintln!("Stack: {:#?}", self.stack);
+        self.trailing_comments = comments;
         self.reduce_stack(0).ok_or(())
     }
 
@@ -758,6 +991,8 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
                 None
             },
             Some('#') => {
+                let hash_offset = self.chars.byte_offset;
+
                 self.chars.advance();
                 self.pop_whitespace();
 
@@ -765,6 +1000,7 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
                 let value = self.chars.pop_while(|ch| ch != '\n').to_string();
 
                 let comment_end_marker = self.chars.marker();
+                self.add_token(TokenKind::Comment, hash_offset);
 
                 self.chars.pop();
 
@@ -778,6 +1014,7 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
                 let extraneous_chars = self.chars.pop_until(|ch| ch != '\n' && ch != '#', |ch| ch == ' ');
 
                 if !extraneous_chars.is_empty() {
+                    self.add_token(TokenKind::Error, extraneous_chars_start_marker.byte_offset);
                     self.errors.push(Error::new(ErrorKind::ExtraneousChars, Span(extraneous_chars_start_marker, self.chars.marker())));
                 }
 
@@ -788,42 +1025,154 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
         }
     }
 
-    fn accept_key(&mut self) -> Option<WithSpan<String, Indexer::Index>>{
-        match self.chars.peek() {
+    // Returns Err if a quoted string is not terminated, after reporting it and restoring the position.
+    fn accept_key(&mut self) -> Result<Option<WithSpan<String, Indexer::Index>>, ()> {
+        let key_start_offset = self.chars.byte_offset;
+
+        let key = match self.chars.peek() {
             Some('A'..='Z' | 'a'..='z' | '_') => {
-                let key_start_offset = self.chars.byte_offset;
+                self.chars.pop_while(|ch| ch.is_alphanumeric() || ch == '_').to_string()
+            },
+            Some('"') => {
                 let key_start_marker = self.chars.marker();
 
-                let key = self.chars.pop_while(|ch| ch.is_alphanumeric() || ch == '_');
-                // let key = self.contents[key_start_offset..self.offset].to_string();
-
-                self.pop_whitespace();
-
-                match self.chars.peek() {
-                    Some(':') => {
-                        let key_end_marker = self.chars.marker();
-                        self.chars.advance();
-
-                        Some(WithSpan {
-                            span: Span(key_start_marker, key_end_marker),
-                            value: key.to_string(),
-                        })
-                    },
-                    _ => {
-                        self.chars.byte_offset = key_start_offset;
-                        None
+                match self.pop_quoted_string() {
+                    Ok(key) => key,
+                    Err(kind) => {
+                        self.errors.push(Error::new(kind, Span(key_start_marker, self.chars.marker())));
+                        self.chars.seek(key_start_offset);
+                        return Err(());
                     },
                 }
             },
             // For completion
             // Some(':') => {},
-            _ => None,
+            _ => return Ok(None),
+        };
+
+        let key_end_offset = self.chars.byte_offset;
+
+        self.pop_whitespace();
+
+        match self.chars.peek() {
+            Some(':') => {
+                self.chars.seek(key_start_offset);
+                let key_start_marker = self.chars.marker();
+
+                self.chars.seek(key_end_offset);
+                let key_end_marker = self.chars.marker();
+
+                if let Some(tokens) = &mut self.tokens {
+                    tokens.push((TokenKind::Key, key_start_offset..key_end_offset));
+                }
+
+                self.pop_whitespace();
+                self.pop_token(':', TokenKind::Colon);
+
+                Ok(Some(WithSpan {
+                    span: Span(key_start_marker, key_end_marker),
+                    value: key,
+                }))
+            },
+            _ => {
+                self.chars.seek(key_start_offset);
+                Ok(None)
+            },
+        }
+    }
+
+    // Assumes that the next character is a double quote.
+    fn pop_quoted_string(&mut self) -> Result<String, ErrorKind> {
+        self.chars.pop();
+
+        let mut value = String::new();
+        let mut error = None;
+
+        loop {
+            match self.chars.peek() {
+                Some('\n') | None => return Err(ErrorKind::MissingStringClose),
+                Some('"') => {
+                    self.chars.advance();
+                    break;
+                },
+                Some('\\') => {
+                    self.chars.advance();
+
+                    let escaped = match self.chars.peek() {
+                        Some('\n') | None => return Err(ErrorKind::MissingStringClose),
+                        Some(ch) => {
+                            self.chars.advance();
+                            ch
+                        },
+                    };
+
+                    match escaped {
+                        '"' => value.push('"'),
+                        '\\' => value.push('\\'),
+                        '/' => value.push('/'),
+                        'b' => value.push('\u{8}'),
+                        'f' => value.push('\u{c}'),
+                        'n' => value.push('\n'),
+                        'r' => value.push('\r'),
+                        't' => value.push('\t'),
+                        'u' => {
+                            let mut code = Some(0);
+
+                            for _ in 0..4 {
+                                match self.chars.peek().and_then(|ch| ch.to_digit(16)) {
+                                    Some(digit) => {
+                                        self.chars.advance();
+                                        code = code.map(|code| code * 16 + digit);
+                                    },
+                                    None => {
+                                        code = None;
+                                        break;
+                                    },
+                                }
+                            }
+
+                            match code.and_then(char::from_u32) {
+                                Some(ch) => value.push(ch),
+                                None => { error.get_or_insert(ErrorKind::InvalidEscapeSequence); },
+                            }
+                        },
+                        _ => { error.get_or_insert(ErrorKind::InvalidEscapeSequence); },
+                    }
+                },
+                Some(ch) => {
+                    self.chars.advance();
+                    value.push(ch);
+                },
+            }
+        }
+
+        match error {
+            Some(kind) => Err(kind),
+            None => Ok(value),
         }
     }
 
     fn pop_whitespace(&mut self) {
         self.chars.pop_while(|ch| ch == ' ' || ch == '\t');
     }
+
+    // Records a token from the given offset to the current one, unless it is empty.
+    fn add_token(&mut self, kind: TokenKind, start_offset: usize) {
+        if let Some(tokens) = self.tokens.as_mut().filter(|_| start_offset < self.chars.byte_offset) {
+            tokens.push((kind, start_offset..self.chars.byte_offset));
+        }
+    }
+
+    fn pop_token(&mut self, ch: char, kind: TokenKind) -> bool {
+        let start_offset = self.chars.byte_offset;
+        let popped = self.chars.pop_char(ch);
+
+        if popped {
+            self.add_token(kind, start_offset);
+        }
+
+        popped
+    }
 }
 
 
@@ -831,6 +1180,8 @@ impl<'a, Indexer: CharIndexer> Parser<'a, Indexer> {
 pub struct ParseResult<Index: CharIndex> {
     pub errors: Vec<Error<Index>>,
     pub object: Option<WithSpan<ExpandedValue<Index>, Index>>,
+    // Comments after the last value, e.g. at the end of the document
+    pub trailing_comments: Vec<StandaloneComment<Index>>,
 }
 
 // #[cfg(test)]
@@ -848,5 +1199,44 @@ pub fn parse<Indexer: CharIndexer>(input: &str) -> ParseResult<Indexer::Index> {
     ParseResult {
         errors: parser.errors,
         object: object.ok(),
+        trailing_comments: parser.trailing_comments,
     }
 }
+
+// As parse(), also returning the tokens covering every byte of the input.
+pub(crate) fn parse_with_tokens<Indexer: CharIndexer>(input: &str) -> (ParseResult<Indexer::Index>, Vec<Token<Indexer::Index>>) {
+    let mut parser = Parser::<'_, Indexer>::with_tokens(input);
+    let object = parser.parse();
+    let tokens = complete_tokens::<Indexer>(input, parser.tokens.take().unwrap());
+
+    (ParseResult {
+        errors: parser.errors,
+        object: object.ok(),
+        trailing_comments: parser.trailing_comments,
+    }, tokens)
+}
+
+
+// Interprets the text of a single scalar, returning None if it is not a valid literal.
+pub(crate) fn parse_scalar<Index: CharIndex>(text: &str) -> Option<CompactValue<Index>> {
+    let mut parser = Parser::<'_, indexers::Empty>::new(text);
+    let value = parser.accept_expr(&[]).ok()??;
+
+    if parser.chars.peek().is_some() {
+        return None;
+    }
+
+    match value.value {
+        CompactValue::Bool(value) => Some(CompactValue::Bool(value)),
+        CompactValue::Float(value) => Some(CompactValue::Float(value)),
+        CompactValue::Integer(value) => Some(CompactValue::Integer(value)),
+        CompactValue::Null => Some(CompactValue::Null),
+        CompactValue::String(value) => Some(CompactValue::String(value)),
+        CompactValue::List { .. } | CompactValue::Map { .. } => None,
+    }
+}
+
+// Decodes a quoted string, including its quotes.
+pub(crate) fn parse_quoted_string(text: &str) -> Result<String, ErrorKind> {
+    Parser::<'_, indexers::Empty>::new(text).pop_quoted_string()
+}
@@ -0,0 +1,504 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::find::FindPathItem;
+use crate::formatter::is_identifier;
+use crate::iterator::CharIndex;
+use crate::parser::{ParseResult, parse_quoted_string, parse_scalar};
+use crate::result::*;
+use crate::span::{Span, WithSpan};
+
+
+// A value of the document, either expanded or compact.
+#[derive(Clone, Copy, Debug)]
+pub enum NodeRef<'a, Index: CharIndex> {
+    Compact(&'a CompactValue<Index>),
+    Expanded(&'a ExpandedValue<Index>),
+}
+
+impl<'a, Index: CharIndex> NodeRef<'a, Index> {
+    fn expanded(value: &'a ExpandedValue<Index>) -> Self {
+        match value {
+            ExpandedValue::Compact(value) => NodeRef::Compact(value),
+            value => NodeRef::Expanded(value),
+        }
+    }
+
+    // Scalars as a compact value, for comparisons
+    fn scalar(&self) -> Option<ScalarRef<'a>> {
+        match *self {
+            NodeRef::Compact(CompactValue::Bool(value)) => Some(ScalarRef::Bool(*value)),
+            NodeRef::Compact(CompactValue::Float(value)) => Some(ScalarRef::Number(*value)),
+            NodeRef::Compact(CompactValue::Integer(value)) => Some(ScalarRef::Number(*value as f64)),
+            NodeRef::Compact(CompactValue::Null) => Some(ScalarRef::Null),
+            NodeRef::Compact(CompactValue::String(value)) | NodeRef::Expanded(ExpandedValue::String { string: value, .. }) => Some(ScalarRef::String(value)),
+            _ => None,
+        }
+    }
+}
+
+
+// A value found by get() or query(), along with its location.
+#[derive(Debug)]
+pub struct QueryMatch<'a, Index: CharIndex> {
+    // Span of the key when the value belongs to a map entry
+    pub key_span: Option<Span<Index>>,
+    pub path: Vec<FindPathItem<'a>>,
+    pub span: Span<Index>,
+    pub value: NodeRef<'a, Index>,
+}
+
+impl<'a, Index: CharIndex> QueryMatch<'a, Index> {
+    fn root(object: &'a WithSpan<ExpandedValue<Index>, Index>) -> Self {
+        Self {
+            key_span: None,
+            path: Vec::new(),
+            span: object.span,
+            value: NodeRef::expanded(&object.value),
+        }
+    }
+
+    fn child(&self, path_item: FindPathItem<'a>, key_span: Option<Span<Index>>, span: Span<Index>, value: NodeRef<'a, Index>) -> Self {
+        let mut path = self.path.clone();
+        path.push(path_item);
+
+        Self {
+            key_span,
+            path,
+            span,
+            value,
+        }
+    }
+
+    fn children(&self) -> Vec<Self> {
+        match self.value {
+            NodeRef::Compact(CompactValue::List { items, .. }) => items
+                .iter()
+                .enumerate()
+                .map(|(index, item)| self.child(FindPathItem::ListIndex(index), None, item.span, NodeRef::Compact(&item.value)))
+                .collect(),
+            NodeRef::Compact(CompactValue::Map { entries, .. }) => entries
+                .iter()
+                .map(|entry| self.child(FindPathItem::MapKey(&entry.key.value), Some(entry.key.span), entry.value.span, NodeRef::Compact(&entry.value.value)))
+                .collect(),
+            NodeRef::Expanded(ExpandedValue::List { items, .. }) => items
+                .iter()
+                .enumerate()
+                .map(|(index, item)| self.child(FindPathItem::ListIndex(index), None, item.value.span, NodeRef::expanded(&item.value.value)))
+                .collect(),
+            NodeRef::Expanded(ExpandedValue::Map { entries, .. }) => entries
+                .iter()
+                .map(|entry| self.child(FindPathItem::MapKey(&entry.key.value), Some(entry.key.span), entry.value.span, NodeRef::expanded(&entry.value.value)))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn get(&self, path_item: &FindPathItem<'_>) -> Option<Self> {
+        self.children().into_iter().find(|child| match (child.path.last(), path_item) {
+            (Some(FindPathItem::ListIndex(a)), FindPathItem::ListIndex(b)) => a == b,
+            (Some(FindPathItem::MapKey(a)), FindPathItem::MapKey(b)) => a == b,
+            _ => false,
+        })
+    }
+
+    // The value and all the values it contains, depth first
+    fn descendants(self) -> Vec<Self> {
+        let mut descendants = Vec::new();
+        let mut stack = vec![self];
+
+        while let Some(node) = stack.pop() {
+            stack.extend(node.children().into_iter().rev());
+            descendants.push(node);
+        }
+
+        descendants
+    }
+}
+
+
+// Finds the value at the path, e.g. [MapKey("servers"), ListIndex(0)], including inside compact collections.
+pub fn get<'a, Index: CharIndex>(result: &'a ParseResult<Index>, path: &[FindPathItem<'_>]) -> Option<QueryMatch<'a, Index>> {
+    let mut node = QueryMatch::root(result.object.as_ref()?);
+
+    for path_item in path {
+        node = node.get(path_item)?;
+    }
+
+    Some(node)
+}
+
+
+#[derive(Debug)]
+pub enum QueryErrorKind {
+    ExpectedKey,
+    InvalidIndex,
+    InvalidLiteral,
+    MissingBracketClose,
+    MissingStringClose,
+    UnexpectedChar(char),
+}
+
+impl fmt::Display for QueryErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryErrorKind::ExpectedKey => write!(f, "expected a key"),
+            QueryErrorKind::InvalidIndex => write!(f, "invalid index"),
+            QueryErrorKind::InvalidLiteral => write!(f, "invalid literal"),
+            QueryErrorKind::MissingBracketClose => write!(f, "missing ']'"),
+            QueryErrorKind::MissingStringClose => write!(f, "missing closing quote"),
+            QueryErrorKind::UnexpectedChar(ch) => write!(f, "unexpected '{}'", ch),
+        }
+    }
+}
+
+
+#[derive(Debug)]
+pub struct QueryError {
+    pub kind: QueryErrorKind,
+    // Byte offset in the query
+    pub offset: usize,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {}", self.kind, self.offset)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Operator {
+    Equal,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    NotEqual,
+}
+
+#[derive(Debug)]
+enum Scalar {
+    Bool(bool),
+    Null,
+    Number(f64),
+    String(String),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum ScalarRef<'a> {
+    Bool(bool),
+    Null,
+    Number(f64),
+    String(&'a str),
+}
+
+impl ScalarRef<'_> {
+    fn compare(self, other: &Scalar) -> Option<Ordering> {
+        match (self, other) {
+            (ScalarRef::Bool(a), Scalar::Bool(b)) => Some(a.cmp(b)),
+            (ScalarRef::Null, Scalar::Null) => Some(Ordering::Equal),
+            (ScalarRef::Number(a), Scalar::Number(b)) => a.partial_cmp(b),
+            (ScalarRef::String(a), Scalar::String(b)) => Some(a.cmp(b.as_str())),
+            _ => None,
+        }
+    }
+}
+
+// [?role == "backend"], or [?role] for the values which have the key
+#[derive(Debug)]
+struct Filter {
+    comparison: Option<(Operator, Scalar)>,
+    path: Vec<Selector>,
+}
+
+#[derive(Debug)]
+enum Selector {
+    Filter(Filter),
+    Index(usize),
+    Key(String),
+    Wildcard,
+}
+
+#[derive(Debug)]
+struct Segment {
+    // Whether the selector applies to all descendants, as in ..name
+    descendant: bool,
+    selector: Selector,
+}
+
+
+// A parsed query, e.g. `servers[?role == "backend"].ip`, `database.ports[0]` or `..name`.
+//
+// - `key` or `.key` selects the value of a key, with `["key"]` for keys which are not identifiers
+// - `[0]` selects an item of a list
+// - `*` or `[*]` selects all the items or values
+// - `..` applies the following selector to the value and all its descendants
+// - `[?path]` selects the items or values which contain the relative path, and `[?path == literal]` those
+//   whose value at the path compares with the literal using ==, !=, <, <=, > or >=
+#[derive(Debug)]
+pub struct Query {
+    segments: Vec<Segment>,
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Self, QueryError> {
+        let mut parser = QueryParser { offset: 0, query };
+        let mut segments = Vec::new();
+
+        while parser.offset < query.len() {
+            let descendant = parser.accept("..");
+
+            let selector = if parser.accept_char('[') {
+                parser.bracket()?
+            } else {
+                // The first key may omit its dot
+                if !descendant && !parser.accept_char('.') && !segments.is_empty() {
+                    return Err(parser.error_at_char());
+                }
+
+                if parser.accept_char('*') {
+                    Selector::Wildcard
+                } else {
+                    Selector::Key(parser.identifier()?)
+                }
+            };
+
+            segments.push(Segment { descendant, selector });
+        }
+
+        Ok(Self { segments })
+    }
+
+    // Finds all the values matching the query, in document order for each segment.
+    pub fn matches<'a, Index: CharIndex>(&self, result: &'a ParseResult<Index>) -> Vec<QueryMatch<'a, Index>> {
+        let Some(object) = &result.object else {
+            return Vec::new();
+        };
+
+        let mut nodes = vec![QueryMatch::root(object)];
+
+        for segment in &self.segments {
+            nodes = nodes
+                .into_iter()
+                .flat_map(|node| if segment.descendant { node.descendants() } else { vec![node] })
+                .flat_map(|node| select(node, &segment.selector))
+                .collect();
+        }
+
+        nodes
+    }
+}
+
+fn select<'a, Index: CharIndex>(node: QueryMatch<'a, Index>, selector: &Selector) -> Vec<QueryMatch<'a, Index>> {
+    match selector {
+        Selector::Filter(filter) => node.children().into_iter().filter(|child| filter.accepts(child)).collect(),
+        Selector::Index(index) => node.get(&FindPathItem::ListIndex(*index)).into_iter().collect(),
+        Selector::Key(key) => node.get(&FindPathItem::MapKey(key)).into_iter().collect(),
+        Selector::Wildcard => node.children(),
+    }
+}
+
+impl Filter {
+    fn accepts<Index: CharIndex>(&self, node: &QueryMatch<'_, Index>) -> bool {
+        let mut nodes = vec![QueryMatch {
+            key_span: node.key_span,
+            path: Vec::new(),
+            span: node.span,
+            value: node.value,
+        }];
+
+        for selector in &self.path {
+            nodes = nodes.into_iter().flat_map(|node| select(node, selector)).collect();
+        }
+
+        match &self.comparison {
+            None => !nodes.is_empty(),
+            Some((operator, literal)) => nodes.iter().any(|node| {
+                let Some(ordering) = node.value.scalar().and_then(|value| value.compare(literal)) else {
+                    return *operator == Operator::NotEqual;
+                };
+
+                match operator {
+                    Operator::Equal => ordering.is_eq(),
+                    Operator::Greater => ordering.is_gt(),
+                    Operator::GreaterOrEqual => ordering.is_ge(),
+                    Operator::Less => ordering.is_lt(),
+                    Operator::LessOrEqual => ordering.is_le(),
+                    Operator::NotEqual => ordering.is_ne(),
+                }
+            }),
+        }
+    }
+}
+
+
+struct QueryParser<'a> {
+    offset: usize,
+    query: &'a str,
+}
+
+impl QueryParser<'_> {
+    fn rest(&self) -> &str {
+        &self.query[self.offset..]
+    }
+
+    fn error(&self, kind: QueryErrorKind) -> QueryError {
+        QueryError { kind, offset: self.offset }
+    }
+
+    fn error_at_char(&self) -> QueryError {
+        match self.rest().chars().next() {
+            Some(ch) => self.error(QueryErrorKind::UnexpectedChar(ch)),
+            None => self.error(QueryErrorKind::ExpectedKey),
+        }
+    }
+
+    fn accept(&mut self, text: &str) -> bool {
+        let accepted = self.rest().starts_with(text);
+
+        if accepted {
+            self.offset += text.len();
+        }
+
+        accepted
+    }
+
+    fn accept_char(&mut self, ch: char) -> bool {
+        self.accept(ch.encode_utf8(&mut [0; 4]))
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.offset = self.query.len() - self.rest().trim_start_matches(' ').len();
+    }
+
+    fn expect_bracket_close(&mut self) -> Result<(), QueryError> {
+        self.skip_whitespace();
+
+        if self.accept_char(']') {
+            Ok(())
+        } else {
+            Err(self.error(QueryErrorKind::MissingBracketClose))
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, QueryError> {
+        let length = self.rest().find(|ch: char| !ch.is_alphanumeric() && ch != '_').unwrap_or(self.rest().len());
+        let identifier = self.rest()[..length].to_string();
+
+        if !is_identifier(&identifier) {
+            return Err(self.error_at_char());
+        }
+
+        self.offset += length;
+        Ok(identifier)
+    }
+
+    fn quoted_string(&mut self) -> Result<String, QueryError> {
+        let mut escaped = false;
+
+        let length = self.rest().char_indices().skip(1).find_map(|(index, ch)| {
+            match ch {
+                '"' if !escaped => return Some(index + 1),
+                '\\' => escaped = !escaped,
+                _ => escaped = false,
+            }
+
+            None
+        }).ok_or_else(|| self.error(QueryErrorKind::MissingStringClose))?;
+
+        let string = parse_quoted_string(&self.rest()[..length]).map_err(|_| self.error(QueryErrorKind::InvalidLiteral))?;
+        self.offset += length;
+
+        Ok(string)
+    }
+
+    // After '[': an index, '*', a quoted key or a filter
+    fn bracket(&mut self) -> Result<Selector, QueryError> {
+        self.skip_whitespace();
+
+        let selector = if self.accept_char('*') {
+            Selector::Wildcard
+        } else if self.rest().starts_with('"') {
+            Selector::Key(self.quoted_string()?)
+        } else if self.accept_char('?') {
+            self.skip_whitespace();
+            Selector::Filter(self.filter()?)
+        } else {
+            let length = self.rest().find(|ch: char| !ch.is_ascii_digit()).unwrap_or(self.rest().len());
+            let index = self.rest()[..length].parse().map_err(|_| self.error(QueryErrorKind::InvalidIndex))?;
+            self.offset += length;
+
+            Selector::Index(index)
+        };
+
+        self.expect_bracket_close()?;
+        Ok(selector)
+    }
+
+    fn filter(&mut self) -> Result<Filter, QueryError> {
+        let mut path = vec![Selector::Key(self.identifier()?)];
+
+        loop {
+            if self.accept_char('.') {
+                path.push(Selector::Key(self.identifier()?));
+            } else if self.accept_char('[') {
+                match self.bracket()? {
+                    selector @ (Selector::Index(_) | Selector::Key(_)) => path.push(selector),
+                    _ => return Err(self.error(QueryErrorKind::ExpectedKey)),
+                }
+            } else {
+                break;
+            }
+        }
+
+        self.skip_whitespace();
+
+        let operators = [
+            ("==", Operator::Equal),
+            ("!=", Operator::NotEqual),
+            ("<=", Operator::LessOrEqual),
+            (">=", Operator::GreaterOrEqual),
+            ("<", Operator::Less),
+            (">", Operator::Greater),
+        ];
+
+        let Some(operator) = operators.into_iter().find_map(|(text, operator)| self.accept(text).then_some(operator)) else {
+            return Ok(Filter { comparison: None, path });
+        };
+
+        self.skip_whitespace();
+
+        let literal = if self.rest().starts_with('"') {
+            Scalar::String(self.quoted_string()?)
+        } else {
+            let length = self.rest().find(']').unwrap_or(self.rest().len());
+            let text = self.rest()[..length].trim_end_matches(' ');
+
+            let literal = match parse_scalar::<()>(text) {
+                Some(CompactValue::Bool(value)) => Scalar::Bool(value),
+                Some(CompactValue::Float(value)) => Scalar::Number(value),
+                Some(CompactValue::Integer(value)) => Scalar::Number(value as f64),
+                Some(CompactValue::Null) => Scalar::Null,
+                Some(CompactValue::String(value)) if !value.is_empty() => Scalar::String(value),
+                _ => return Err(self.error(QueryErrorKind::InvalidLiteral)),
+            };
+
+            self.offset += text.len();
+            literal
+        };
+
+        Ok(Filter {
+            comparison: Some((operator, literal)),
+            path,
+        })
+    }
+}
+
+
+// Parses the query and finds all the values matching it.
+pub fn query<'a, Index: CharIndex>(result: &'a ParseResult<Index>, query: &str) -> Result<Vec<QueryMatch<'a, Index>>, QueryError> {
+    Ok(Query::parse(query)?.matches(result))
+}
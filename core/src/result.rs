@@ -34,6 +34,24 @@ impl<Index: CharIndex> Context<Index> {
             indent,
         }
     }
+
+    // The comments directly above an entry or item, without a blank line in between, excluding a schema
+    // directive.
+    pub fn doc_comment(&self) -> Option<String> {
+        if self.gap > 0 {
+            return None;
+        }
+
+        let start = self.comments.iter().rposition(|comment| comment.gap > 0).unwrap_or(0);
+
+        let lines = self.comments[start..]
+            .iter()
+            .map(|comment| comment.contents.value.trim_end())
+            .filter(|line| !line.starts_with("$schema:"))
+            .collect::<Vec<_>>();
+
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
 }
 
 #[derive(Debug)]
@@ -51,6 +69,14 @@ pub struct ExpandedMapEntry<Index: CharIndex> {
     pub value: WithSpan<ExpandedValue<Index>, Index>,
 }
 
+// Where the missing value of a key could be completed, either after the key or on a line below it
+#[derive(Debug)]
+pub struct ValueCompletionSpan<Index: CharIndex> {
+    pub key: WithSpan<String, Index>,
+    pub nested: bool,
+    pub span: Span<Index>,
+}
+
 #[derive(Debug)]
 pub struct CompactMapEntry<Index: CharIndex> {
     pub key: WithSpan<String, Index>,
@@ -75,7 +101,7 @@ pub enum ExpandedValue<Index: CharIndex> {
     Map {
         entries: Vec<ExpandedMapEntry<Index>>,
         key_completion_spans: Vec<Span<Index>>,
-        value_completion_spans: Vec<Span<Index>>,
+        value_completion_spans: Vec<ValueCompletionSpan<Index>>,
     },
     String {
         lines: Vec<MultilineStringLine<Index>>,
@@ -83,6 +109,118 @@ pub enum ExpandedValue<Index: CharIndex> {
     },
 }
 
+impl<Index: CharIndex> ExpandedValue<Index> {
+    pub fn has_comments(&self) -> bool {
+        match self {
+            ExpandedValue::Compact(_) => false,
+            ExpandedValue::List { items, .. } =>
+                items.iter().any(|item| {
+                    item.comment.is_some() || !item.context.comments.is_empty() || item.value.value.has_comments()
+                }),
+            ExpandedValue::Map { entries, .. } =>
+                entries.iter().any(|entry| {
+                    entry.comment.is_some() || !entry.context.comments.is_empty() || entry.value.value.has_comments()
+                }),
+            ExpandedValue::String { lines, .. } =>
+                lines.iter().any(|line| line.comment.is_some() || !line.context.comments.is_empty()),
+        }
+    }
+
+    // Fails if the value carries comments, as they cannot be represented in compact form.
+    pub fn into_compact(self) -> Result<CompactValue<Index>, Self> {
+        if self.has_comments() {
+            Err(self)
+        } else {
+            Ok(self.into_compact_unchecked())
+        }
+    }
+
+    fn into_compact_unchecked(self) -> CompactValue<Index> {
+        match self {
+            ExpandedValue::Compact(value) => value,
+            ExpandedValue::List { items, item_completion_spans } =>
+                CompactValue::List {
+                    items: items
+                        .into_iter()
+                        .map(|item| WithSpan::new(item.value.value.into_compact_unchecked(), item.value.span))
+                        .collect(),
+                    item_completion_spans,
+                },
+            ExpandedValue::Map { entries, key_completion_spans, value_completion_spans } =>
+                CompactValue::Map {
+                    entries: entries
+                        .into_iter()
+                        .map(|entry| CompactMapEntry {
+                            key: entry.key,
+                            value: WithSpan::new(entry.value.value.into_compact_unchecked(), entry.value.span),
+                        })
+                        .collect(),
+                    key_completion_spans,
+                    value_completion_spans,
+                },
+            ExpandedValue::String { string, .. } =>
+                CompactValue::String(string),
+        }
+    }
+
+    // Recursively converts compact collections to expanded ones. Empty collections are left as is because they have
+    // no expanded form.
+    pub fn into_expanded(self) -> Self {
+        match self {
+            ExpandedValue::Compact(CompactValue::List { items, item_completion_spans }) if !items.is_empty() =>
+                ExpandedValue::List {
+                    items: items
+                        .into_iter()
+                        .map(|item| ExpandedListItem {
+                            comment: None,
+                            context: Context::new(0),
+                            value: WithSpan::new(ExpandedValue::Compact(item.value).into_expanded(), item.span),
+                        })
+                        .collect(),
+                    item_completion_spans,
+                },
+            ExpandedValue::Compact(CompactValue::Map { entries, key_completion_spans, value_completion_spans }) if !entries.is_empty() =>
+                ExpandedValue::Map {
+                    entries: entries
+                        .into_iter()
+                        .map(|entry| ExpandedMapEntry {
+                            comment: None,
+                            context: Context::new(0),
+                            key: entry.key,
+                            value: WithSpan::new(ExpandedValue::Compact(entry.value.value).into_expanded(), entry.value.span),
+                        })
+                        .collect(),
+                    key_completion_spans,
+                    value_completion_spans,
+                },
+            ExpandedValue::List { items, item_completion_spans } =>
+                ExpandedValue::List {
+                    items: items
+                        .into_iter()
+                        .map(|item| ExpandedListItem {
+                            value: WithSpan::new(item.value.value.into_expanded(), item.value.span),
+                            ..item
+                        })
+                        .collect(),
+                    item_completion_spans,
+                },
+            ExpandedValue::Map { entries, key_completion_spans, value_completion_spans } =>
+                ExpandedValue::Map {
+                    entries: entries
+                        .into_iter()
+                        .map(|entry| ExpandedMapEntry {
+                            value: WithSpan::new(entry.value.value.into_expanded(), entry.value.span),
+                            ..entry
+                        })
+                        .collect(),
+                    key_completion_spans,
+                    value_completion_spans,
+                },
+            value => value,
+        }
+    }
+}
+
 impl<Index: CharIndex> std::convert::From<ExpandedValue<Index>> for RegularValue {
     fn from(value: ExpandedValue<Index>) -> Self {
         use ExpandedValue::*;
@@ -123,7 +261,7 @@ pub enum CompactValue<Index: CharIndex> {
     Map {
         entries: Vec<CompactMapEntry<Index>>,
         key_completion_spans: Vec<Span<Index>>,
-        value_completion_spans: Vec<Span<Index>>,
+        value_completion_spans: Vec<ValueCompletionSpan<Index>>,
     },
     Null,
     String(String),
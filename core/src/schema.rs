@@ -1,20 +1,220 @@
-#[derive(Debug)]
-enum SchemaType {
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use regex::Regex;
+use serde_json::Value;
+
+use crate::find::FindPathItem;
+use crate::iterator::CharIndex;
+use crate::result::*;
+use crate::span::{Span, WithSpan};
+
+
+// A schema node along with its annotations, which do not affect validation.
+#[derive(Clone, Debug)]
+pub struct Schema {
+    // Applied in addition to the kind
+    pub applicators: Vec<Applicator>,
+    pub default: Option<ConstType>,
+    pub description: Option<String>,
+    pub examples: Vec<ConstType>,
+    pub kind: SchemaType,
+    pub title: Option<String>,
+}
+
+impl Schema {
+    // Writes the schema as a JSON Schema document, the inverse of load(). References are written as is,
+    // without their targets.
+    pub fn to_json(&self) -> Value {
+        let is_bare = self.applicators.is_empty() && self.default.is_none() && self.description.is_none() && self.examples.is_empty() && self.title.is_none();
+
+        match self.kind {
+            SchemaType::Any if is_bare => return Value::Bool(true),
+            SchemaType::Never if is_bare => return Value::Bool(false),
+            _ => (),
+        }
+
+        let mut object = serde_json::Map::new();
+
+        if let Some(title) = &self.title {
+            object.insert("title".to_string(), Value::from(title.as_str()));
+        }
+
+        if let Some(description) = &self.description {
+            object.insert("description".to_string(), Value::from(description.as_str()));
+        }
+
+        if let Some(default) = &self.default {
+            object.insert("default".to_string(), default.to_json());
+        }
+
+        if !self.examples.is_empty() {
+            object.insert("examples".to_string(), self.examples.iter().map(ConstType::to_json).collect());
+        }
+
+        object.extend(self.kind.to_json());
+
+        let list = |schemas: &[Schema]| schemas.iter().map(Schema::to_json).collect::<Value>();
+
+        for applicator in &self.applicators {
+            let keywords = match applicator {
+                Applicator::AllOf(schemas) => vec![("allOf", list(schemas))],
+                Applicator::AnyOf(schemas) => vec![("anyOf", list(schemas))],
+                Applicator::Conditional { condition, then, otherwise } => [("if", Some(condition)), ("then", then.as_ref()), ("else", otherwise.as_ref())]
+                    .into_iter()
+                    .filter_map(|(keyword, schema)| Some((keyword, schema?.to_json())))
+                    .collect(),
+                Applicator::Not(schema) => vec![("not", schema.to_json())],
+                Applicator::OneOf(schemas) => vec![("oneOf", list(schemas))],
+                Applicator::Ref(reference) => vec![("$ref", Value::from(reference.uri.as_str()))],
+            };
+
+            // A keyword which is already taken, e.g. anyOf written for an Enum, moves into allOf.
+            if keywords.iter().any(|(keyword, _)| object.contains_key(*keyword)) {
+                let conjunction = Value::Object(keywords.into_iter().map(|(keyword, value)| (keyword.to_string(), value)).collect());
+
+                match object.entry("allOf").or_insert_with(|| Value::Array(Vec::new())) {
+                    Value::Array(schemas) => schemas.push(conjunction),
+                    _ => unreachable!(),
+                }
+            } else {
+                object.extend(keywords.into_iter().map(|(keyword, value)| (keyword.to_string(), value)));
+            }
+        }
+
+        Value::Object(object)
+    }
+
+    // The schemas which a value may be described by, following references and combinators, the schema
+    // itself first. Negations and conditions are skipped, as they do not describe the value.
+    pub fn variants(&self) -> Vec<&Schema> {
+        let mut variants = Vec::new();
+        self.collect_variants(&mut variants);
+        variants
+    }
+
+    fn collect_variants<'a>(&'a self, variants: &mut Vec<&'a Schema>) {
+        // Guards against references which refer back to themselves.
+        if variants.iter().any(|variant| std::ptr::eq(*variant, self)) {
+            return;
+        }
+
+        variants.push(self);
+
+        for applicator in &self.applicators {
+            match applicator {
+                Applicator::AllOf(schemas) | Applicator::AnyOf(schemas) | Applicator::OneOf(schemas) => {
+                    for schema in schemas {
+                        schema.collect_variants(variants);
+                    }
+                },
+                Applicator::Conditional { then, otherwise, .. } => {
+                    for schema in [then, otherwise].into_iter().flatten() {
+                        schema.collect_variants(variants);
+                    }
+                },
+                Applicator::Not(_) => (),
+                Applicator::Ref(reference) => reference.schema().collect_variants(variants),
+            }
+        }
+    }
+
+    // The kinds of the schema, i.e. those listed by a type array or next to the allowed values, or the kind
+    // itself.
+    pub fn kinds(&self) -> Vec<&SchemaType> {
+        fn collect_kinds<'a>(kind: &'a SchemaType, kinds: &mut Vec<&'a SchemaType>) {
+            match kind {
+                SchemaType::Intersection(members) | SchemaType::Union(members) => {
+                    for member in members {
+                        collect_kinds(member, kinds);
+                    }
+                },
+                _ => kinds.push(kind),
+            }
+        }
+
+        let mut kinds = Vec::new();
+        collect_kinds(&self.kind, &mut kinds);
+        kinds
+    }
+
+    // The variants of the schemas which may describe the value at the path, regardless of the document's
+    // contents, e.g. for completion.
+    pub fn schemas_at(&self, path: &[FindPathItem<'_>]) -> Vec<&Schema> {
+        let mut schemas = self.variants();
+
+        for path_item in path {
+            schemas = schemas
+                .into_iter()
+                .flat_map(|schema| schema.kinds())
+                .filter_map(|kind| match (kind, path_item) {
+                    (SchemaType::Array { items, prefix_items, .. }, FindPathItem::ListIndex(index)) =>
+                        prefix_items.get(*index).or(items.as_deref()),
+                    (SchemaType::Map { additional_properties, properties, .. }, FindPathItem::MapKey(key)) =>
+                        match properties.iter().find(|(property, _)| property == key) {
+                            Some((_, schema)) => Some(schema),
+                            None => additional_properties.as_deref(),
+                        },
+                    _ => None,
+                })
+                .flat_map(Schema::variants)
+                .collect();
+        }
+
+        schemas
+    }
+}
+
+impl From<SchemaType> for Schema {
+    fn from(kind: SchemaType) -> Self {
+        Self {
+            applicators: Vec::new(),
+            default: None,
+            description: None,
+            examples: Vec::new(),
+            kind,
+            title: None,
+        }
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub enum SchemaType {
+    // Accepts any value, e.g. the `true` schema
+    Any,
     Array {
+        // Applies to the items after the prefix items
+        items: Option<Box<Schema>>,
         max_items: Option<usize>,
         min_items: usize,
+        prefix_items: Vec<Schema>,
         unique_items: bool,
     },
+    Boolean,
     Const(ConstType),
+    // Allowed values, as constants
     Enum(Vec<SchemaType>),
+    // Kinds which all apply, e.g. a type next to the allowed values
+    Intersection(Vec<SchemaType>),
     Map {
+        // Applies to the entries not listed in properties
+        additional_properties: Option<Box<Schema>>,
+        // Ordered as in the schema
+        properties: Vec<(String, Schema)>,
         max_properties: Option<usize>,
         min_properties: usize,
         required: Vec<String>,
         dependent_required: HashMap<String, Vec<String>>,
     },
+    // Rejects any value, e.g. the `false` schema
+    Never,
     Null,
     Numeric {
+        integer: bool,
         multiple_of: Option<f64>,
         maximum: Option<f64>,
         exclusive_maximum: Option<f64>,
@@ -22,18 +222,1492 @@ enum SchemaType {
         exclusive_minimum: Option<f64>,
     },
     String {
+        // Name of the format, e.g. "email", checked by StringFormats
+        format: Option<String>,
         max_length: Option<usize>,
         min_length: usize,
         pattern: Option<Regex>,
     },
+    // Kinds of which one applies, e.g. from a type array
+    Union(Vec<SchemaType>),
 }
 
 
-#[derive(Debug)]
-enum ConstType {
+impl SchemaType {
+    // Keywords of the kind, without annotations.
+    fn to_json(&self) -> serde_json::Map<String, Value> {
+        let mut object = serde_json::Map::new();
+        let mut insert = |keyword: &str, value: Value| {
+            object.insert(keyword.to_string(), value);
+        };
+
+        match self {
+            SchemaType::Any => (),
+            SchemaType::Array { items, max_items, min_items, prefix_items, unique_items } => {
+                insert("type", Value::from("array"));
+
+                if !prefix_items.is_empty() {
+                    insert("prefixItems", prefix_items.iter().map(Schema::to_json).collect());
+                }
+
+                if let Some(items) = items {
+                    insert("items", items.to_json());
+                }
+
+                if let Some(max_items) = max_items {
+                    insert("maxItems", Value::from(*max_items));
+                }
+
+                if *min_items > 0 {
+                    insert("minItems", Value::from(*min_items));
+                }
+
+                if *unique_items {
+                    insert("uniqueItems", Value::Bool(true));
+                }
+            },
+            SchemaType::Boolean => insert("type", Value::from("boolean")),
+            SchemaType::Const(value) => insert("const", value.to_json()),
+            SchemaType::Enum(variants) => {
+                let values = variants.iter().map(|variant| match variant {
+                    SchemaType::Const(value) => Some(value.to_json()),
+                    _ => None,
+                }).collect::<Option<Vec<_>>>();
+
+                match values {
+                    Some(values) => insert("enum", Value::Array(values)),
+                    None => insert("anyOf", variants.iter().map(|variant| Value::Object(variant.to_json())).collect()),
+                }
+            },
+            SchemaType::Intersection(members) => {
+                for member in members {
+                    object.extend(member.to_json());
+                }
+            },
+            SchemaType::Map { additional_properties, properties, max_properties, min_properties, required, dependent_required } => {
+                insert("type", Value::from("object"));
+
+                if !properties.is_empty() {
+                    insert("properties", Value::Object(properties.iter().map(|(key, schema)| (key.clone(), schema.to_json())).collect()));
+                }
+
+                if let Some(additional_properties) = additional_properties {
+                    insert("additionalProperties", additional_properties.to_json());
+                }
+
+                if !required.is_empty() {
+                    insert("required", required.iter().map(String::as_str).collect());
+                }
+
+                if let Some(max_properties) = max_properties {
+                    insert("maxProperties", Value::from(*max_properties));
+                }
+
+                if *min_properties > 0 {
+                    insert("minProperties", Value::from(*min_properties));
+                }
+
+                if !dependent_required.is_empty() {
+                    let mut dependencies = dependent_required.iter().collect::<Vec<_>>();
+                    dependencies.sort_by_key(|(property, _)| *property);
+
+                    insert("dependentRequired", Value::Object(dependencies.into_iter().map(|(property, dependencies)| (property.clone(), dependencies.iter().map(String::as_str).collect())).collect()));
+                }
+            },
+            SchemaType::Never => insert("not", Value::Object(serde_json::Map::new())),
+            SchemaType::Null => insert("type", Value::from("null")),
+            SchemaType::Numeric { integer, multiple_of, maximum, exclusive_maximum, minimum, exclusive_minimum } => {
+                insert("type", Value::from(if *integer { "integer" } else { "number" }));
+
+                for (keyword, value) in [("multipleOf", multiple_of), ("maximum", maximum), ("exclusiveMaximum", exclusive_maximum), ("minimum", minimum), ("exclusiveMinimum", exclusive_minimum)] {
+                    if let Some(value) = value {
+                        insert(keyword, number_to_json(*value));
+                    }
+                }
+            },
+            SchemaType::String { format, max_length, min_length, pattern } => {
+                insert("type", Value::from("string"));
+
+                if let Some(format) = format {
+                    insert("format", Value::from(format.as_str()));
+                }
+
+                if let Some(max_length) = max_length {
+                    insert("maxLength", Value::from(*max_length));
+                }
+
+                if *min_length > 0 {
+                    insert("minLength", Value::from(*min_length));
+                }
+
+                if let Some(pattern) = pattern {
+                    insert("pattern", Value::from(pattern.as_str()));
+                }
+            },
+            SchemaType::Union(members) => {
+                let members = members.iter().map(SchemaType::to_json).collect::<Vec<_>>();
+
+                // Members of different types only overlap in their type, hence they can be merged into a type array,
+                // unless some of them have no type, e.g. allowed values.
+                if members.iter().all(|member| member.contains_key("type")) {
+                    let mut types = Vec::new();
+
+                    for (keyword, value) in members.into_iter().flatten() {
+                        match keyword.as_str() {
+                            "type" => types.push(value),
+                            _ => {
+                                object.insert(keyword, value);
+                            },
+                        }
+                    }
+
+                    object.insert("type".to_string(), Value::Array(types));
+                } else {
+                    object.insert("anyOf".to_string(), members.into_iter().map(Value::Object).collect());
+                }
+            },
+        }
+
+        object
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub enum Applicator {
+    AllOf(Vec<Schema>),
+    AnyOf(Vec<Schema>),
+    // if/then/else
+    Conditional {
+        condition: Box<Schema>,
+        then: Option<Box<Schema>>,
+        otherwise: Option<Box<Schema>>,
+    },
+    Not(Box<Schema>),
+    OneOf(Vec<Schema>),
+    Ref(Reference),
+}
+
+
+// A resolved $ref. The target is shared by all references to it, which allows recursive schemas.
+#[derive(Clone)]
+pub struct Reference {
+    target: Arc<OnceLock<Schema>>,
+    // As written in the schema, e.g. "#/$defs/server"
+    pub uri: String,
+}
+
+impl Reference {
+    pub fn schema(&self) -> &Schema {
+        self.target.get().expect("reference used while its target is being loaded")
+    }
+}
+
+impl fmt::Debug for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Reference").field(&self.uri).finish()
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConstType {
     Array(Vec<ConstType>),
     Boolean(bool),
     Map(HashMap<String, ConstType>),
+    Null,
     Numeric(f64),
     String(String),
 }
+
+impl ConstType {
+    pub fn to_json(&self) -> Value {
+        match self {
+            ConstType::Array(items) => items.iter().map(ConstType::to_json).collect(),
+            ConstType::Boolean(value) => Value::Bool(*value),
+            ConstType::Map(entries) => Value::Object(entries.iter().map(|(key, value)| (key.clone(), value.to_json())).collect()),
+            ConstType::Null => Value::Null,
+            ConstType::Numeric(value) => number_to_json(*value),
+            ConstType::String(value) => Value::from(value.as_str()),
+        }
+    }
+
+    // Writes the value in compact form as it would appear in a document, or returns None if it cannot be
+    // serialized.
+    pub fn to_compact_string(&self) -> Option<String> {
+        crate::ser::to_compact_string(&self.to_json(), &Default::default()).ok()
+    }
+}
+
+impl ConstType {
+    // Integral numbers become integers, e.g. 80 rather than 80.0.
+    fn to_regular(&self) -> RegularValue {
+        match self {
+            ConstType::Array(items) => RegularValue::List(items.iter().map(ConstType::to_regular).collect()),
+            ConstType::Boolean(value) => RegularValue::Bool(*value),
+            ConstType::Map(entries) => RegularValue::Map(entries.iter().map(|(key, value)| (key.clone(), value.to_regular())).collect()),
+            ConstType::Null => RegularValue::Null,
+            ConstType::Numeric(value) if value.fract() == 0.0 && value.abs() < 2f64.powi(53) => RegularValue::Integer(*value as i64),
+            ConstType::Numeric(value) => RegularValue::Float(*value),
+            ConstType::String(value) => RegularValue::String(value.clone()),
+        }
+    }
+}
+
+// Integral values are written as integers, e.g. 80 rather than 80.0.
+fn number_to_json(value: f64) -> Value {
+    if value.fract() == 0.0 && value.abs() < 2f64.powi(53) {
+        Value::from(value as i64)
+    } else {
+        Value::from(value)
+    }
+}
+
+
+#[derive(Debug)]
+pub enum ViolationKind {
+    AdditionalProperty(String),
+    Const,
+    DependentRequired {
+        dependency: String,
+        property: String,
+    },
+    Enum,
+    ExclusiveMaximum(f64),
+    ExclusiveMinimum(f64),
+    // Name of the format
+    Format(String),
+    InvalidType {
+        expected: &'static str,
+        found: &'static str,
+    },
+    MaxItems(usize),
+    MaxLength(usize),
+    MaxProperties(usize),
+    Maximum(f64),
+    MinItems(usize),
+    MinLength(usize),
+    MinProperties(usize),
+    Minimum(f64),
+    MissingProperty(String),
+    MultipleOf(f64),
+    // The value matches the schema of `not`
+    Not,
+    NotAllowed,
+    // Indices of the matching schemas of `oneOf`
+    OneOf(Vec<usize>),
+    Pattern(String),
+    UniqueItems,
+}
+
+impl fmt::Display for ViolationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ViolationKind::AdditionalProperty(property) => write!(f, "property '{}' is not allowed", property),
+            ViolationKind::Const => write!(f, "value does not match the constant"),
+            ViolationKind::DependentRequired { dependency, property } => write!(f, "missing property '{}', required by '{}'", dependency, property),
+            ViolationKind::Enum => write!(f, "value is not one of the allowed values"),
+            ViolationKind::ExclusiveMaximum(limit) => write!(f, "value must be less than {}", limit),
+            ViolationKind::ExclusiveMinimum(limit) => write!(f, "value must be greater than {}", limit),
+            ViolationKind::Format(format) => write!(f, "string is not a valid {}", format),
+            ViolationKind::InvalidType { expected, found } => write!(f, "expected {}, found {}", expected, found),
+            ViolationKind::MaxItems(limit) => write!(f, "list must have at most {} items", limit),
+            ViolationKind::MaxLength(limit) => write!(f, "string must have at most {} characters", limit),
+            ViolationKind::MaxProperties(limit) => write!(f, "map must have at most {} entries", limit),
+            ViolationKind::Maximum(limit) => write!(f, "value must be at most {}", limit),
+            ViolationKind::MinItems(limit) => write!(f, "list must have at least {} items", limit),
+            ViolationKind::MinLength(limit) => write!(f, "string must have at least {} characters", limit),
+            ViolationKind::MinProperties(limit) => write!(f, "map must have at least {} entries", limit),
+            ViolationKind::Minimum(limit) => write!(f, "value must be at least {}", limit),
+            ViolationKind::MissingProperty(property) => write!(f, "missing property '{}'", property),
+            ViolationKind::MultipleOf(divisor) => write!(f, "value must be a multiple of {}", divisor),
+            ViolationKind::Not => write!(f, "value must not match the schema"),
+            ViolationKind::NotAllowed => write!(f, "value is not allowed"),
+            ViolationKind::OneOf(indices) => write!(f, "value must match exactly one schema, but matches {}", indices.iter().map(usize::to_string).collect::<Vec<_>>().join(", ")),
+            ViolationKind::Pattern(pattern) => write!(f, "string does not match pattern '{}'", pattern),
+            ViolationKind::UniqueItems => write!(f, "list items must be unique"),
+        }
+    }
+}
+
+
+#[derive(Debug)]
+pub struct Violation<Index: CharIndex> {
+    pub kind: ViolationKind,
+    // JSON pointer to the keyword of the schema, e.g. "/properties/port/maximum"
+    pub schema_path: String,
+    pub span: Span<Index>,
+}
+
+
+#[derive(Clone, Copy)]
+enum ValueRef<'a, Index: CharIndex> {
+    Compact(&'a CompactValue<Index>),
+    Expanded(&'a ExpandedValue<Index>),
+}
+
+#[derive(Clone, Copy)]
+struct Node<'a, Index: CharIndex> {
+    span: Span<Index>,
+    value: ValueRef<'a, Index>,
+}
+
+impl<'a, Index: CharIndex> Node<'a, Index> {
+    fn compact(object: &'a WithSpan<CompactValue<Index>, Index>) -> Self {
+        Self {
+            span: object.span,
+            value: ValueRef::Compact(&object.value),
+        }
+    }
+
+    fn expanded(object: &'a WithSpan<ExpandedValue<Index>, Index>) -> Self {
+        Self {
+            span: object.span,
+            value: match &object.value {
+                ExpandedValue::Compact(value) => ValueRef::Compact(value),
+                value => ValueRef::Expanded(value),
+            },
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self.value {
+            ValueRef::Compact(CompactValue::Bool(_)) => "boolean",
+            ValueRef::Compact(CompactValue::Float(_)) => "number",
+            ValueRef::Compact(CompactValue::Integer(_)) => "integer",
+            ValueRef::Compact(CompactValue::List { .. }) | ValueRef::Expanded(ExpandedValue::List { .. }) => "list",
+            ValueRef::Compact(CompactValue::Map { .. }) | ValueRef::Expanded(ExpandedValue::Map { .. }) => "map",
+            ValueRef::Compact(CompactValue::Null) => "null",
+            ValueRef::Compact(CompactValue::String(_)) | ValueRef::Expanded(ExpandedValue::String { .. }) => "string",
+            ValueRef::Expanded(ExpandedValue::Compact(_)) => unreachable!(),
+        }
+    }
+
+    fn items(&self) -> Option<Vec<Self>> {
+        match self.value {
+            ValueRef::Compact(CompactValue::List { items, .. }) => Some(items.iter().map(Self::compact).collect()),
+            ValueRef::Expanded(ExpandedValue::List { items, .. }) => Some(items.iter().map(|item| Self::expanded(&item.value)).collect()),
+            _ => None,
+        }
+    }
+
+    fn entries(&self) -> Option<Vec<(&'a WithSpan<String, Index>, Self)>> {
+        match self.value {
+            ValueRef::Compact(CompactValue::Map { entries, .. }) => Some(entries.iter().map(|entry| (&entry.key, Self::compact(&entry.value))).collect()),
+            ValueRef::Expanded(ExpandedValue::Map { entries, .. }) => Some(entries.iter().map(|entry| (&entry.key, Self::expanded(&entry.value))).collect()),
+            _ => None,
+        }
+    }
+
+    fn number(&self) -> Option<f64> {
+        match self.value {
+            ValueRef::Compact(CompactValue::Float(value)) => Some(*value),
+            ValueRef::Compact(CompactValue::Integer(value)) => Some(*value as f64),
+            _ => None,
+        }
+    }
+
+    fn string(&self) -> Option<&'a str> {
+        match self.value {
+            ValueRef::Compact(CompactValue::String(value)) | ValueRef::Expanded(ExpandedValue::String { string: value, .. }) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn to_const(self) -> ConstType {
+        match self.value {
+            ValueRef::Compact(CompactValue::Bool(value)) => ConstType::Boolean(*value),
+            ValueRef::Compact(CompactValue::Null) => ConstType::Null,
+            _ => if let Some(value) = self.number() {
+                ConstType::Numeric(value)
+            } else if let Some(value) = self.string() {
+                ConstType::String(value.to_string())
+            } else if let Some(items) = self.items() {
+                ConstType::Array(items.into_iter().map(Self::to_const).collect())
+            } else {
+                ConstType::Map(self.entries().unwrap().into_iter().map(|(key, value)| (key.value.clone(), value.to_const())).collect())
+            },
+        }
+    }
+
+    fn to_regular(self) -> RegularValue {
+        match self.value {
+            ValueRef::Compact(CompactValue::Bool(value)) => RegularValue::Bool(*value),
+            ValueRef::Compact(CompactValue::Float(value)) => RegularValue::Float(*value),
+            ValueRef::Compact(CompactValue::Integer(value)) => RegularValue::Integer(*value),
+            ValueRef::Compact(CompactValue::Null) => RegularValue::Null,
+            _ => if let Some(value) = self.string() {
+                RegularValue::String(value.to_string())
+            } else if let Some(items) = self.items() {
+                RegularValue::List(items.into_iter().map(Self::to_regular).collect())
+            } else {
+                RegularValue::Map(self.entries().unwrap().into_iter().map(|(key, value)| (key.value.clone(), value.to_regular())).collect())
+            },
+        }
+    }
+}
+
+
+// Joins the segments into a JSON pointer, escaping them.
+fn json_pointer<'a>(segments: impl Iterator<Item = &'a str>) -> String {
+    let mut pointer = String::new();
+
+    for segment in segments {
+        pointer.push('/');
+        pointer.push_str(&segment.replace('~', "~0").replace('/', "~1"));
+    }
+
+    pointer
+}
+
+
+type FormatCheck = Box<dyn Fn(&str) -> bool + Send + Sync>;
+
+// Checks for the values of the `format` keyword. Besides the built-in formats, applications can register
+// their own, e.g. "semver", which take precedence. Unknown formats are not checked.
+#[derive(Default)]
+pub struct StringFormats {
+    custom: HashMap<String, FormatCheck>,
+}
+
+impl StringFormats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, name: &str, check: impl Fn(&str) -> bool + Send + Sync + 'static) {
+        self.custom.insert(name.to_string(), Box::new(check));
+    }
+
+    // Returns whether the value has the format, or None if the format is unknown.
+    pub fn check(&self, name: &str, value: &str) -> Option<bool> {
+        if let Some(check) = self.custom.get(name) {
+            return Some(check(value));
+        }
+
+        Some(match name {
+            "date-time" => is_date_time(value),
+            "email" => is_email(value),
+            "hostname" => is_hostname(value),
+            "ipv4" => value.parse::<Ipv4Addr>().is_ok(),
+            "ipv6" => value.parse::<Ipv6Addr>().is_ok(),
+            "regex" => Regex::new(value).is_ok(),
+            "uri" => is_uri(value),
+            "uuid" => is_uuid(value),
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Debug for StringFormats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.custom.keys()).finish()
+    }
+}
+
+// RFC 3339, e.g. "2023-06-30T12:00:00.5+02:00"
+fn is_date_time(value: &str) -> bool {
+    static DATE_TIME: OnceLock<Regex> = OnceLock::new();
+
+    let date_time = DATE_TIME.get_or_init(|| {
+        Regex::new(r"^(\d{4})-(\d{2})-(\d{2})[Tt ](\d{2}):(\d{2}):(\d{2})(\.\d+)?([Zz]|[+-](\d{2}):(\d{2}))$").unwrap()
+    });
+
+    let Some(captures) = date_time.captures(value) else {
+        return false;
+    };
+
+    let field = |index: usize| captures.get(index).map_or(0, |field| field.as_str().parse::<u32>().unwrap());
+    let (year, month, day) = (field(1), field(2), field(3));
+    let is_leap_year = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+
+    let days = match month {
+        2 if is_leap_year => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+
+    // Seconds may be 60 on leap seconds.
+    (1..=12).contains(&month) && (1..=days).contains(&day) && field(4) < 24 && field(5) < 60 && field(6) <= 60 && field(9) < 24 && field(10) < 60
+}
+
+// RFC 1123, without a trailing dot
+fn is_hostname(value: &str) -> bool {
+    value.len() <= 253 && value.split('.').all(|label| {
+        (1..=63).contains(&label.len())
+            && label.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '-')
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+    })
+}
+
+// A local part without whitespace or quotes, followed by a hostname
+fn is_email(value: &str) -> bool {
+    let Some((local, domain)) = value.rsplit_once('@') else {
+        return false;
+    };
+
+    (1..=64).contains(&local.len())
+        && local.chars().all(|ch| ch.is_ascii_graphic() && !"\"(),:;<>@[\\]".contains(ch))
+        && !local.starts_with('.')
+        && !local.ends_with('.')
+        && !local.contains("..")
+        && is_hostname(domain)
+}
+
+// An absolute URI as in RFC 3986, only checking the scheme and the allowed characters
+fn is_uri(value: &str) -> bool {
+    let Some((scheme, rest)) = value.split_once(':') else {
+        return false;
+    };
+
+    let is_scheme_valid = scheme.starts_with(|ch: char| ch.is_ascii_alphabetic())
+        && scheme.chars().all(|ch| ch.is_ascii_alphanumeric() || "+-.".contains(ch));
+
+    let bytes = rest.as_bytes();
+    let is_rest_valid = bytes.iter().enumerate().all(|(index, byte)| match byte {
+        b'%' => bytes.get(index + 1..index + 3).is_some_and(|digits| digits.iter().all(u8::is_ascii_hexdigit)),
+        _ => byte.is_ascii_alphanumeric() || b"-._~:/?#[]@!$&'()*+,;=".contains(byte),
+    });
+
+    is_scheme_valid && is_rest_valid
+}
+
+// e.g. "123e4567-e89b-12d3-a456-426614174000"
+fn is_uuid(value: &str) -> bool {
+    let groups = value.split('-').collect::<Vec<_>>();
+
+    groups.iter().map(|group| group.len()).eq([8, 4, 4, 4, 12])
+        && groups.iter().all(|group| group.chars().all(|ch| ch.is_ascii_hexdigit()))
+}
+
+
+struct Validator<'a, Index: CharIndex> {
+    formats: &'a StringFormats,
+    schema_path: Vec<String>,
+    violations: Vec<Violation<Index>>,
+}
+
+impl<Index: CharIndex> Validator<'_, Index> {
+    fn report(&mut self, kind: ViolationKind, keyword: &[&str], span: Span<Index>) {
+        self.violations.push(Violation {
+            kind,
+            schema_path: json_pointer(self.schema_path.iter().map(String::as_str).chain(keyword.iter().copied())),
+            span,
+        });
+    }
+
+    fn invalid_type(&mut self, expected: &'static str, node: Node<'_, Index>) {
+        self.report(ViolationKind::InvalidType { expected, found: node.kind() }, &["type"], node.span);
+    }
+
+    // Validates a node against a nested schema, e.g. ["properties", "port"].
+    fn validate_nested(&mut self, node: Node<'_, Index>, schema: &Schema, segments: &[&str]) {
+        let length = self.schema_path.len();
+
+        self.schema_path.extend(segments.iter().map(|segment| segment.to_string()));
+        self.validate_schema(node, schema);
+        self.schema_path.truncate(length);
+    }
+
+    // Collects the violations of a nested schema without reporting them.
+    fn nested_violations(&self, node: Node<'_, Index>, schema: &Schema, segments: &[&str]) -> Vec<Violation<Index>> {
+        let mut validator = Validator {
+            formats: self.formats,
+            schema_path: self.schema_path.clone(),
+            violations: Vec::new(),
+        };
+
+        validator.validate_nested(node, schema, segments);
+        validator.violations
+    }
+
+    // Collects the violations of each branch of anyOf or oneOf.
+    fn branch_violations(&self, node: Node<'_, Index>, schemas: &[Schema], keyword: &str) -> Vec<Vec<Violation<Index>>> {
+        schemas.iter()
+            .enumerate()
+            .map(|(index, schema)| self.nested_violations(node, schema, &[keyword, &index.to_string()]))
+            .collect()
+    }
+
+    // Reports the violations of the closest branch when none matched. Branches which expect another type
+    // or constant, which is usually a discriminator, are the furthest, followed by those with more violations.
+    fn report_closest(&mut self, node: Node<'_, Index>, branches: Vec<Vec<Violation<Index>>>) {
+        let closest = branches.into_iter().min_by_key(|violations| {
+            let type_mismatches = violations.iter().filter(|violation| matches!(violation.kind, ViolationKind::InvalidType { .. }) && violation.span == node.span).count();
+            let constant_mismatches = violations.iter().filter(|violation| matches!(violation.kind, ViolationKind::Const | ViolationKind::Enum)).count();
+
+            (type_mismatches, constant_mismatches, violations.len())
+        });
+
+        self.violations.extend(closest.into_iter().flatten());
+    }
+
+    fn validate_schema(&mut self, node: Node<'_, Index>, schema: &Schema) {
+        self.validate(node, &schema.kind);
+
+        for applicator in &schema.applicators {
+            match applicator {
+                Applicator::AllOf(schemas) => {
+                    for (index, schema) in schemas.iter().enumerate() {
+                        self.validate_nested(node, schema, &["allOf", &index.to_string()]);
+                    }
+                },
+                Applicator::AnyOf(schemas) => {
+                    let branches = self.branch_violations(node, schemas, "anyOf");
+
+                    if !branches.iter().any(Vec::is_empty) {
+                        self.report_closest(node, branches);
+                    }
+                },
+                Applicator::Conditional { condition, then, otherwise } => {
+                    let branch = if self.nested_violations(node, condition, &["if"]).is_empty() {
+                        then.as_ref().map(|schema| (schema, "then"))
+                    } else {
+                        otherwise.as_ref().map(|schema| (schema, "else"))
+                    };
+
+                    if let Some((schema, keyword)) = branch {
+                        self.validate_nested(node, schema, &[keyword]);
+                    }
+                },
+                Applicator::Not(schema) => {
+                    if self.nested_violations(node, schema, &["not"]).is_empty() {
+                        self.report(ViolationKind::Not, &["not"], node.span);
+                    }
+                },
+                Applicator::OneOf(schemas) => {
+                    let branches = self.branch_violations(node, schemas, "oneOf");
+                    let matching = branches.iter().enumerate().filter(|(_, violations)| violations.is_empty()).map(|(index, _)| index).collect::<Vec<_>>();
+
+                    match matching.len() {
+                        0 => self.report_closest(node, branches),
+                        1 => (),
+                        _ => self.report(ViolationKind::OneOf(matching), &["oneOf"], node.span),
+                    }
+                },
+                Applicator::Ref(reference) => self.validate_nested(node, reference.schema(), &["$ref"]),
+            }
+        }
+    }
+
+    fn validate(&mut self, node: Node<'_, Index>, schema: &SchemaType) {
+        match schema {
+            SchemaType::Any => (),
+            SchemaType::Array { items: item_schema, max_items, min_items, prefix_items, unique_items } => {
+                let Some(items) = node.items() else {
+                    self.invalid_type("list", node);
+                    return;
+                };
+
+                if let Some(max_items) = max_items.filter(|max_items| items.len() > *max_items) {
+                    self.report(ViolationKind::MaxItems(max_items), &["maxItems"], node.span);
+                }
+
+                if items.len() < *min_items {
+                    self.report(ViolationKind::MinItems(*min_items), &["minItems"], node.span);
+                }
+
+                if *unique_items {
+                    let values = items.iter().map(|item| item.to_const()).collect::<Vec<_>>();
+
+                    for (index, item) in items.iter().enumerate() {
+                        if values[..index].contains(&values[index]) {
+                            self.report(ViolationKind::UniqueItems, &["uniqueItems"], item.span);
+                        }
+                    }
+                }
+
+                for (index, item) in items.into_iter().enumerate() {
+                    if let Some(prefix_item_schema) = prefix_items.get(index) {
+                        self.validate_nested(item, prefix_item_schema, &["prefixItems", &index.to_string()]);
+                    } else if let Some(item_schema) = item_schema {
+                        self.validate_nested(item, item_schema, &["items"]);
+                    }
+                }
+            },
+            SchemaType::Boolean => {
+                if !matches!(node.value, ValueRef::Compact(CompactValue::Bool(_))) {
+                    self.invalid_type("boolean", node);
+                }
+            },
+            SchemaType::Const(value) => {
+                if node.to_const() != *value {
+                    self.report(ViolationKind::Const, &["const"], node.span);
+                }
+            },
+            SchemaType::Enum(variants) => {
+                let matches_variant = |variant: &SchemaType| {
+                    let mut validator = Validator {
+                        formats: self.formats,
+                        schema_path: Vec::new(),
+                        violations: Vec::new(),
+                    };
+
+                    validator.validate(node, variant);
+                    validator.violations.is_empty()
+                };
+
+                if !variants.iter().any(matches_variant) {
+                    self.report(ViolationKind::Enum, &["enum"], node.span);
+                }
+            },
+            SchemaType::Intersection(members) => {
+                for member in members {
+                    self.validate(node, member);
+                }
+            },
+            SchemaType::Map { additional_properties, properties, max_properties, min_properties, required, dependent_required } => {
+                let Some(entries) = node.entries() else {
+                    self.invalid_type("map", node);
+                    return;
+                };
+
+                if let Some(max_properties) = max_properties.filter(|max_properties| entries.len() > *max_properties) {
+                    self.report(ViolationKind::MaxProperties(max_properties), &["maxProperties"], node.span);
+                }
+
+                if entries.len() < *min_properties {
+                    self.report(ViolationKind::MinProperties(*min_properties), &["minProperties"], node.span);
+                }
+
+                let has_key = |key: &str| entries.iter().any(|(entry_key, _)| entry_key.value == key);
+
+                for property in required {
+                    if !has_key(property) {
+                        self.report(ViolationKind::MissingProperty(property.clone()), &["required"], node.span);
+                    }
+                }
+
+                for (key, value) in &entries {
+                    for dependency in dependent_required.get(&key.value).into_iter().flatten() {
+                        if !has_key(dependency) {
+                            let kind = ViolationKind::DependentRequired { dependency: dependency.clone(), property: key.value.clone() };
+                            self.report(kind, &["dependentRequired", &key.value], key.span);
+                        }
+                    }
+
+                    if let Some((_, property_schema)) = properties.iter().find(|(property, _)| *property == key.value) {
+                        self.validate_nested(*value, property_schema, &["properties", &key.value]);
+                    } else if let Some(additional_schema) = additional_properties {
+                        // Disallowed entries are reported on their key rather than their value
+                        if matches!(additional_schema.kind, SchemaType::Never) {
+                            self.report(ViolationKind::AdditionalProperty(key.value.clone()), &["additionalProperties"], key.span);
+                        } else {
+                            self.validate_nested(*value, additional_schema, &["additionalProperties"]);
+                        }
+                    }
+                }
+            },
+            SchemaType::Never => self.report(ViolationKind::NotAllowed, &[], node.span),
+            SchemaType::Null => {
+                if !matches!(node.value, ValueRef::Compact(CompactValue::Null)) {
+                    self.invalid_type("null", node);
+                }
+            },
+            SchemaType::Numeric { integer, multiple_of, maximum, exclusive_maximum, minimum, exclusive_minimum } => {
+                let expected = if *integer { "integer" } else { "number" };
+
+                let Some(value) = node.number().filter(|value| !*integer || value.fract() == 0.0) else {
+                    self.invalid_type(expected, node);
+                    return;
+                };
+
+                if let Some(multiple_of) = *multiple_of {
+                    let quotient = value / multiple_of;
+
+                    if (quotient - quotient.round()).abs() > 1e-9 {
+                        self.report(ViolationKind::MultipleOf(multiple_of), &["multipleOf"], node.span);
+                    }
+                }
+
+                if let Some(maximum) = maximum.filter(|maximum| value > *maximum) {
+                    self.report(ViolationKind::Maximum(maximum), &["maximum"], node.span);
+                }
+
+                if let Some(maximum) = exclusive_maximum.filter(|maximum| value >= *maximum) {
+                    self.report(ViolationKind::ExclusiveMaximum(maximum), &["exclusiveMaximum"], node.span);
+                }
+
+                if let Some(minimum) = minimum.filter(|minimum| value < *minimum) {
+                    self.report(ViolationKind::Minimum(minimum), &["minimum"], node.span);
+                }
+
+                if let Some(minimum) = exclusive_minimum.filter(|minimum| value <= *minimum) {
+                    self.report(ViolationKind::ExclusiveMinimum(minimum), &["exclusiveMinimum"], node.span);
+                }
+            },
+            SchemaType::String { format, max_length, min_length, pattern } => {
+                let Some(value) = node.string() else {
+                    self.invalid_type("string", node);
+                    return;
+                };
+
+                let length = value.chars().count();
+
+                if let Some(max_length) = max_length.filter(|max_length| length > *max_length) {
+                    self.report(ViolationKind::MaxLength(max_length), &["maxLength"], node.span);
+                }
+
+                if length < *min_length {
+                    self.report(ViolationKind::MinLength(*min_length), &["minLength"], node.span);
+                }
+
+                if let Some(pattern) = pattern.as_ref().filter(|pattern| !pattern.is_match(value)) {
+                    self.report(ViolationKind::Pattern(pattern.as_str().to_string()), &["pattern"], node.span);
+                }
+
+                if let Some(format) = format.as_ref().filter(|format| self.formats.check(format, value) == Some(false)) {
+                    self.report(ViolationKind::Format(format.clone()), &["format"], node.span);
+                }
+            },
+            SchemaType::Union(members) => {
+                let branches = members.iter().map(|member| {
+                    let mut validator = Validator {
+                        formats: self.formats,
+                        schema_path: self.schema_path.clone(),
+                        violations: Vec::new(),
+                    };
+
+                    validator.validate(node, member);
+                    validator.violations
+                }).collect::<Vec<_>>();
+
+                if !branches.iter().any(Vec::is_empty) {
+                    self.report_closest(node, branches);
+                }
+            },
+        }
+    }
+}
+
+
+pub fn validate<Index: CharIndex>(object: &WithSpan<ExpandedValue<Index>, Index>, schema: &Schema) -> Vec<Violation<Index>> {
+    validate_with_formats(object, schema, &StringFormats::new())
+}
+
+pub fn validate_with_formats<Index: CharIndex>(object: &WithSpan<ExpandedValue<Index>, Index>, schema: &Schema, formats: &StringFormats) -> Vec<Violation<Index>> {
+    let mut validator = Validator {
+        formats,
+        schema_path: Vec::new(),
+        violations: Vec::new(),
+    };
+
+    validator.validate_schema(Node::expanded(object), schema);
+    validator.violations
+}
+
+
+fn matches_schema<Index: CharIndex>(node: Node<'_, Index>, schema: &Schema) -> bool {
+    let formats = StringFormats::new();
+    let mut validator = Validator {
+        formats: &formats,
+        schema_path: Vec::new(),
+        violations: Vec::new(),
+    };
+
+    validator.validate_schema(node, schema);
+    validator.violations.is_empty()
+}
+
+// Collects the schemas applying to a node: the schema itself, along with those of its reference, of allOf,
+// of the first matching branch of anyOf and oneOf, and of then or else.
+fn applicable_schemas<'a, Index: CharIndex>(node: Node<'_, Index>, schema: &'a Schema, schemas: &mut Vec<&'a Schema>) {
+    schemas.push(schema);
+
+    for applicator in &schema.applicators {
+        match applicator {
+            Applicator::AllOf(branches) => {
+                for branch in branches {
+                    applicable_schemas(node, branch, schemas);
+                }
+            },
+            Applicator::AnyOf(branches) | Applicator::OneOf(branches) => {
+                if let Some(branch) = branches.iter().find(|branch| matches_schema(node, branch)) {
+                    applicable_schemas(node, branch, schemas);
+                }
+            },
+            Applicator::Conditional { condition, then, otherwise } => {
+                let branch = if matches_schema(node, condition) { then } else { otherwise };
+
+                if let Some(branch) = branch {
+                    applicable_schemas(node, branch, schemas);
+                }
+            },
+            Applicator::Not(_) => (),
+            Applicator::Ref(reference) => applicable_schemas(node, reference.schema(), schemas),
+        }
+    }
+}
+
+fn materialize<Index: CharIndex>(node: Node<'_, Index>, schemas: &[&Schema], path: &mut Vec<String>, defaulted: &mut Vec<String>) -> RegularValue {
+    let mut applicable = Vec::new();
+
+    for schema in schemas {
+        applicable_schemas(node, schema, &mut applicable);
+    }
+
+    let map_schemas = applicable.iter().flat_map(|schema| schema.kinds()).filter_map(|kind| match kind {
+        SchemaType::Map { additional_properties, properties, required, .. } => Some((additional_properties, properties, required)),
+        _ => None,
+    }).collect::<Vec<_>>();
+
+    if let Some(entries) = node.entries() {
+        let mut map = HashMap::new();
+
+        for (key, value) in entries {
+            let children = map_schemas.iter().filter_map(|(additional_properties, properties, _)| {
+                match properties.iter().find(|(property, _)| *property == key.value) {
+                    Some((_, schema)) => Some(schema),
+                    None => additional_properties.as_deref(),
+                }
+            }).collect::<Vec<_>>();
+
+            path.push(key.value.clone());
+            map.insert(key.value.clone(), materialize(value, &children, path, defaulted));
+            path.pop();
+        }
+
+        // Required properties are not defaulted, as their absence is a violation.
+        for (_, properties, _) in &map_schemas {
+            for (property, schema) in properties.iter() {
+                let Some(default) = &schema.default else {
+                    continue;
+                };
+
+                if !map.contains_key(property) && !map_schemas.iter().any(|(_, _, required)| required.contains(property)) {
+                    map.insert(property.clone(), default.to_regular());
+                    defaulted.push(json_pointer(path.iter().map(String::as_str).chain([property.as_str()])));
+                }
+            }
+        }
+
+        return RegularValue::Map(map);
+    }
+
+    if let Some(items) = node.items() {
+        let list_schemas = applicable.iter().flat_map(|schema| schema.kinds()).filter_map(|kind| match kind {
+            SchemaType::Array { items, prefix_items, .. } => Some((items, prefix_items)),
+            _ => None,
+        }).collect::<Vec<_>>();
+
+        let items = items.into_iter().enumerate().map(|(index, item)| {
+            let children = list_schemas.iter().filter_map(|(items, prefix_items)| prefix_items.get(index).or(items.as_deref())).collect::<Vec<_>>();
+
+            path.push(index.to_string());
+            let item = materialize(item, &children, path, defaulted);
+            path.pop();
+
+            item
+        }).collect();
+
+        return RegularValue::List(items);
+    }
+
+    node.to_regular()
+}
+
+// Converts a document to a RegularValue, filling in the defaults of missing optional properties. The
+// defaulted properties are returned as JSON pointers into the value, e.g. "/servers/0/timeout".
+pub fn apply_defaults<Index: CharIndex>(object: &WithSpan<ExpandedValue<Index>, Index>, schema: &Schema) -> (RegularValue, Vec<String>) {
+    let mut defaulted = Vec::new();
+    let value = materialize(Node::expanded(object), &[schema], &mut Vec::new(), &mut defaulted);
+
+    (value, defaulted)
+}
+
+
+#[derive(Debug)]
+pub enum LoadErrorKind {
+    InvalidPattern(String),
+    InvalidType {
+        expected: &'static str,
+    },
+    // The file could not be read or is not valid JSON
+    Read(String),
+    UnknownType(String),
+    UnresolvedReference(String),
+    UnsupportedKeyword,
+}
+
+impl fmt::Display for LoadErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadErrorKind::InvalidPattern(message) => write!(f, "invalid pattern: {}", message),
+            LoadErrorKind::InvalidType { expected } => write!(f, "expected {}", expected),
+            LoadErrorKind::Read(message) => write!(f, "failed to read schema: {}", message),
+            LoadErrorKind::UnknownType(name) => write!(f, "unknown type '{}'", name),
+            LoadErrorKind::UnresolvedReference(uri) => write!(f, "unresolved reference '{}'", uri),
+            LoadErrorKind::UnsupportedKeyword => write!(f, "unsupported keyword"),
+        }
+    }
+}
+
+
+#[derive(Debug)]
+pub struct LoadError {
+    // The referenced file containing the keyword, or None for a schema loaded from a value
+    pub file: Option<PathBuf>,
+    pub kind: LoadErrorKind,
+    // JSON pointer to the keyword in the schema document, e.g. "/properties/port/format"
+    pub path: String,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}#", file.display())?;
+        }
+
+        write!(f, "{}: {}", self.path, self.kind)
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+
+const APPLICATOR_KEYWORDS: [&str; 9] = ["$defs", "$ref", "allOf", "anyOf", "else", "if", "not", "oneOf", "then"];
+const GENERIC_KEYWORDS: [&str; 7] = ["$comment", "$schema", "default", "description", "examples", "title", "type"];
+const ARRAY_KEYWORDS: [&str; 5] = ["items", "maxItems", "minItems", "prefixItems", "uniqueItems"];
+const MAP_KEYWORDS: [&str; 6] = ["additionalProperties", "dependentRequired", "maxProperties", "minProperties", "properties", "required"];
+const NUMERIC_KEYWORDS: [&str; 5] = ["exclusiveMaximum", "exclusiveMinimum", "maximum", "minimum", "multipleOf"];
+const STRING_KEYWORDS: [&str; 4] = ["format", "maxLength", "minLength", "pattern"];
+const VALUE_KEYWORDS: [&str; 2] = ["const", "enum"];
+
+fn const_from_json(value: &Value) -> ConstType {
+    match value {
+        Value::Array(items) => ConstType::Array(items.iter().map(const_from_json).collect()),
+        Value::Bool(value) => ConstType::Boolean(*value),
+        Value::Null => ConstType::Null,
+        Value::Number(value) => ConstType::Numeric(value.as_f64().unwrap()),
+        Value::Object(entries) => ConstType::Map(entries.iter().map(|(key, value)| (key.clone(), const_from_json(value))).collect()),
+        Value::String(value) => ConstType::String(value.clone()),
+    }
+}
+
+
+fn read_json(path: &Path) -> Result<Value, String> {
+    let contents = fs::read_to_string(path).map_err(|error| error.to_string())?;
+    serde_json::from_str(&contents).map_err(|error| error.to_string())
+}
+
+
+struct Loader {
+    // The file of the document being loaded, or None for the root value
+    document: Option<PathBuf>,
+    documents: HashMap<PathBuf, Arc<Value>>,
+    errors: Vec<LoadError>,
+    // JSON pointer segments from the root of the document being loaded
+    path: Vec<String>,
+    // Targets of the references, by document and JSON pointer
+    references: HashMap<(Option<PathBuf>, String), Arc<OnceLock<Schema>>>,
+    root: Arc<Value>,
+}
+
+impl Loader {
+    fn new(root: Value, document: Option<PathBuf>) -> Self {
+        let root = Arc::new(root);
+
+        Self {
+            documents: document.iter().map(|path| (path.clone(), root.clone())).collect(),
+            document,
+            errors: Vec::new(),
+            path: Vec::new(),
+            references: HashMap::new(),
+            root,
+        }
+    }
+
+    fn report(&mut self, kind: LoadErrorKind, keyword: &[&str]) {
+        self.errors.push(LoadError {
+            file: self.document.clone(),
+            kind,
+            path: json_pointer(self.path.iter().map(String::as_str).chain(keyword.iter().copied())),
+        });
+    }
+
+    // Returns the keyword's value if it has the expected type, reporting it otherwise.
+    fn keyword<'a, T>(&mut self, object: &'a serde_json::Map<String, Value>, keyword: &str, expected: &'static str, convert: impl FnOnce(&'a Value) -> Option<T>) -> Option<T> {
+        let value = object.get(keyword)?;
+        let result = convert(value);
+
+        if result.is_none() {
+            self.report(LoadErrorKind::InvalidType { expected }, &[keyword]);
+        }
+
+        result
+    }
+
+    fn count(&mut self, object: &serde_json::Map<String, Value>, keyword: &str) -> Option<usize> {
+        self.keyword(object, keyword, "non-negative integer", |value| value.as_u64().map(|value| value as usize))
+    }
+
+    fn number(&mut self, object: &serde_json::Map<String, Value>, keyword: &str) -> Option<f64> {
+        self.keyword(object, keyword, "number", Value::as_f64)
+    }
+
+    fn string_list(&mut self, object: &serde_json::Map<String, Value>, keyword: &str) -> Option<Vec<String>> {
+        self.keyword(object, keyword, "array of strings", |value| {
+            value.as_array()?.iter().map(|item| item.as_str().map(str::to_string)).collect()
+        })
+    }
+
+    fn load_nested(&mut self, value: &Value, segments: &[&str]) -> Schema {
+        let length = self.path.len();
+
+        self.path.extend(segments.iter().map(|segment| segment.to_string()));
+        let schema = self.load(value);
+        self.path.truncate(length);
+
+        schema
+    }
+
+    fn load_list(&mut self, object: &serde_json::Map<String, Value>, keyword: &str) -> Option<Vec<Schema>> {
+        let values = self.keyword(object, keyword, "array", Value::as_array)?;
+        Some(values.iter().enumerate().map(|(index, value)| self.load_nested(value, &[keyword, &index.to_string()])).collect())
+    }
+
+    // Resolves and loads a reference, e.g. "#/$defs/server" or "common.json#/$defs/port". Relative files are
+    // resolved against the file of the current document. Targets are only loaded once.
+    fn reference(&mut self, uri: &str) -> Option<Reference> {
+        let (file, pointer) = uri.split_once('#').unwrap_or((uri, ""));
+
+        let document = if file.is_empty() {
+            self.document.clone()
+        } else {
+            let base = self.document.as_deref().and_then(Path::parent).unwrap_or(Path::new(""));
+            let path = base.join(file);
+
+            let path = match fs::canonicalize(&path) {
+                Ok(path) => path,
+                Err(error) => {
+                    self.report(LoadErrorKind::Read(format!("{}: {}", path.display(), error)), &["$ref"]);
+                    return None;
+                },
+            };
+
+            if !self.documents.contains_key(&path) {
+                match read_json(&path) {
+                    Ok(value) => {
+                        self.documents.insert(path.clone(), Arc::new(value));
+                    },
+                    Err(message) => {
+                        self.report(LoadErrorKind::Read(format!("{}: {}", path.display(), message)), &["$ref"]);
+                        return None;
+                    },
+                }
+            }
+
+            Some(path)
+        };
+
+        let key = (document.clone(), pointer.to_string());
+
+        if let Some(target) = self.references.get(&key) {
+            return Some(Reference {
+                target: target.clone(),
+                uri: uri.to_string(),
+            });
+        }
+
+        let root = match &document {
+            Some(path) => self.documents[path].clone(),
+            None => self.root.clone(),
+        };
+
+        // Only JSON pointers are supported as fragments, not anchors.
+        let Some(value) = root.pointer(pointer) else {
+            self.report(LoadErrorKind::UnresolvedReference(uri.to_string()), &["$ref"]);
+            return None;
+        };
+
+        let target = Arc::new(OnceLock::new());
+        self.references.insert(key, target.clone());
+
+        let path = pointer.split('/').skip(1).map(|segment| segment.replace("~1", "/").replace("~0", "~")).collect();
+        let previous_document = std::mem::replace(&mut self.document, document);
+        let previous_path = std::mem::replace(&mut self.path, path);
+
+        let schema = self.load(value);
+
+        self.document = previous_document;
+        self.path = previous_path;
+
+        let _ = target.set(schema);
+
+        Some(Reference {
+            target,
+            uri: uri.to_string(),
+        })
+    }
+
+    fn load_applicators(&mut self, object: &serde_json::Map<String, Value>) -> Vec<Applicator> {
+        // Definitions are loaded even when unused, so that their errors are reported.
+        for name in self.keyword(object, "$defs", "object", Value::as_object).into_iter().flat_map(|definitions| definitions.keys()) {
+            let pointer = json_pointer(self.path.iter().map(String::as_str).chain(["$defs", name]));
+            self.reference(&format!("#{}", pointer));
+        }
+
+        let mut applicators = Vec::new();
+
+        if let Some(reference) = self.keyword(object, "$ref", "string", Value::as_str).and_then(|uri| self.reference(uri)) {
+            applicators.push(Applicator::Ref(reference));
+        }
+
+        if let Some(schemas) = self.load_list(object, "allOf") {
+            applicators.push(Applicator::AllOf(schemas));
+        }
+
+        if let Some(schemas) = self.load_list(object, "anyOf") {
+            applicators.push(Applicator::AnyOf(schemas));
+        }
+
+        if let Some(schemas) = self.load_list(object, "oneOf") {
+            applicators.push(Applicator::OneOf(schemas));
+        }
+
+        if let Some(value) = object.get("not") {
+            applicators.push(Applicator::Not(Box::new(self.load_nested(value, &["not"]))));
+        }
+
+        // Without if, then and else are ignored.
+        if let Some(value) = object.get("if") {
+            applicators.push(Applicator::Conditional {
+                condition: Box::new(self.load_nested(value, &["if"])),
+                then: object.get("then").map(|value| Box::new(self.load_nested(value, &["then"]))),
+                otherwise: object.get("else").map(|value| Box::new(self.load_nested(value, &["else"]))),
+            });
+        }
+
+        applicators
+    }
+
+    fn load(&mut self, value: &Value) -> Schema {
+        let object = match value {
+            Value::Bool(true) => return SchemaType::Any.into(),
+            Value::Bool(false) => return SchemaType::Never.into(),
+            Value::Object(object) => object,
+            _ => {
+                self.report(LoadErrorKind::InvalidType { expected: "object or boolean" }, &[]);
+                return SchemaType::Any.into();
+            },
+        };
+
+        for keyword in object.keys() {
+            let is_supported = [&APPLICATOR_KEYWORDS[..], &GENERIC_KEYWORDS, &ARRAY_KEYWORDS, &MAP_KEYWORDS, &NUMERIC_KEYWORDS, &STRING_KEYWORDS, &VALUE_KEYWORDS]
+                .iter()
+                .any(|keywords| keywords.contains(&keyword.as_str()));
+
+            if !is_supported {
+                self.report(LoadErrorKind::UnsupportedKeyword, &[keyword]);
+            }
+        }
+
+        // Without a type, the type is inferred from the keywords which are present.
+        let type_names = match object.get("type") {
+            Some(Value::String(name)) => vec![name.as_str()],
+            Some(Value::Array(names)) if names.iter().all(Value::is_string) => names.iter().filter_map(Value::as_str).collect(),
+            Some(_) => {
+                self.report(LoadErrorKind::InvalidType { expected: "string or array of strings" }, &["type"]);
+                Vec::new()
+            },
+            None => [("object", &MAP_KEYWORDS[..]), ("array", &ARRAY_KEYWORDS), ("string", &STRING_KEYWORDS), ("number", &NUMERIC_KEYWORDS)]
+                .into_iter()
+                .filter(|(_, keywords)| keywords.iter().any(|keyword| object.contains_key(*keyword)))
+                .map(|(name, _)| name)
+                .collect(),
+        };
+
+        let mut kinds = Vec::new();
+
+        for name in &type_names {
+            // Integers are numbers, so they need no separate variant.
+            if *name == "integer" && type_names.contains(&"number") {
+                continue;
+            }
+
+            let kind = match *name {
+                "array" => self.load_array(object),
+                "boolean" => SchemaType::Boolean,
+                "integer" | "number" => self.load_numeric(object, *name == "integer"),
+                "null" => SchemaType::Null,
+                "object" => self.load_map(object),
+                "string" => self.load_string(object),
+                _ => {
+                    self.report(LoadErrorKind::UnknownType(name.to_string()), &["type"]);
+                    continue;
+                },
+            };
+
+            kinds.push(kind);
+        }
+
+        let type_kind = match kinds.len() {
+            0 => None,
+            1 => kinds.pop(),
+            _ => Some(SchemaType::Union(kinds)),
+        };
+
+        let values_kind = if let Some(value) = object.get("const") {
+            Some(SchemaType::Const(const_from_json(value)))
+        } else {
+            self.keyword(object, "enum", "array", Value::as_array)
+                .map(|values| SchemaType::Enum(values.iter().map(|value| SchemaType::Const(const_from_json(value))).collect()))
+        };
+
+        // The type still applies next to the allowed values, e.g. with its minimum.
+        let kind = match (type_kind, values_kind) {
+            (Some(type_kind), Some(values_kind)) => SchemaType::Intersection(vec![type_kind, values_kind]),
+            (Some(kind), None) | (None, Some(kind)) => kind,
+            (None, None) => SchemaType::Any,
+        };
+
+        Schema {
+            applicators: self.load_applicators(object),
+            default: object.get("default").map(const_from_json),
+            description: self.keyword(object, "description", "string", |value| value.as_str().map(str::to_string)),
+            examples: self.keyword(object, "examples", "array", Value::as_array).into_iter().flatten().map(const_from_json).collect(),
+            kind,
+            title: self.keyword(object, "title", "string", |value| value.as_str().map(str::to_string)),
+        }
+    }
+
+    fn load_array(&mut self, object: &serde_json::Map<String, Value>) -> SchemaType {
+        let prefix_items = self.keyword(object, "prefixItems", "array", Value::as_array)
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .map(|(index, value)| self.load_nested(value, &["prefixItems", &index.to_string()]))
+            .collect();
+
+        SchemaType::Array {
+            items: object.get("items").map(|value| Box::new(self.load_nested(value, &["items"]))),
+            max_items: self.count(object, "maxItems"),
+            min_items: self.count(object, "minItems").unwrap_or(0),
+            prefix_items,
+            unique_items: self.keyword(object, "uniqueItems", "boolean", Value::as_bool).unwrap_or(false),
+        }
+    }
+
+    fn load_map(&mut self, object: &serde_json::Map<String, Value>) -> SchemaType {
+        let properties = self.keyword(object, "properties", "object", Value::as_object)
+            .into_iter()
+            .flatten()
+            .map(|(key, value)| (key.clone(), self.load_nested(value, &["properties", key])))
+            .collect();
+
+        let mut dependent_required = HashMap::new();
+
+        for (key, value) in self.keyword(object, "dependentRequired", "object", Value::as_object).into_iter().flatten() {
+            match value.as_array().and_then(|items| items.iter().map(|item| item.as_str().map(str::to_string)).collect::<Option<Vec<_>>>()) {
+                Some(dependencies) => {
+                    dependent_required.insert(key.clone(), dependencies);
+                },
+                None => self.report(LoadErrorKind::InvalidType { expected: "array of strings" }, &["dependentRequired", key]),
+            }
+        }
+
+        SchemaType::Map {
+            additional_properties: object.get("additionalProperties").map(|value| Box::new(self.load_nested(value, &["additionalProperties"]))),
+            properties,
+            max_properties: self.count(object, "maxProperties"),
+            min_properties: self.count(object, "minProperties").unwrap_or(0),
+            required: self.string_list(object, "required").unwrap_or_default(),
+            dependent_required,
+        }
+    }
+
+    fn load_numeric(&mut self, object: &serde_json::Map<String, Value>, integer: bool) -> SchemaType {
+        SchemaType::Numeric {
+            integer,
+            multiple_of: self.number(object, "multipleOf"),
+            maximum: self.number(object, "maximum"),
+            exclusive_maximum: self.number(object, "exclusiveMaximum"),
+            minimum: self.number(object, "minimum"),
+            exclusive_minimum: self.number(object, "exclusiveMinimum"),
+        }
+    }
+
+    fn load_string(&mut self, object: &serde_json::Map<String, Value>) -> SchemaType {
+        let pattern = self.keyword(object, "pattern", "string", Value::as_str).and_then(|pattern| match Regex::new(pattern) {
+            Ok(pattern) => Some(pattern),
+            Err(error) => {
+                self.report(LoadErrorKind::InvalidPattern(error.to_string()), &["pattern"]);
+                None
+            },
+        });
+
+        SchemaType::String {
+            format: self.keyword(object, "format", "string", |value| value.as_str().map(str::to_string)),
+            max_length: self.count(object, "maxLength"),
+            min_length: self.count(object, "minLength").unwrap_or(0),
+            pattern,
+        }
+    }
+}
+
+
+fn load_root(mut loader: Loader) -> (Schema, Vec<LoadError>) {
+    // The root is loaded as a reference so that references to "#" share it.
+    let schema = loader.reference("#").unwrap().schema().clone();
+    (schema, loader.errors)
+}
+
+// Loads a JSON Schema (draft 2020-12) document. Unsupported keywords and invalid values are reported
+// and otherwise ignored, so the returned schema may be more permissive than the document. References
+// to files are resolved against the current directory.
+pub fn load(value: &Value) -> (Schema, Vec<LoadError>) {
+    load_root(Loader::new(value.clone(), None))
+}
+
+// Loads a JSON Schema document from a file, resolving references to files against its directory.
+pub fn load_file(path: &Path) -> Result<(Schema, Vec<LoadError>), LoadError> {
+    let read_error = |message: String| LoadError {
+        file: Some(path.to_path_buf()),
+        kind: LoadErrorKind::Read(message),
+        path: String::new(),
+    };
+
+    let path = fs::canonicalize(path).map_err(|error| read_error(error.to_string()))?;
+    let value = read_json(&path).map_err(read_error)?;
+
+    Ok(load_root(Loader::new(value, Some(path))))
+}
+
+
+const SCHEMA_DIRECTIVE: &str = "$schema:";
+
+pub(crate) fn is_schema_directive(comment: &str) -> bool {
+    comment.starts_with(SCHEMA_DIRECTIVE)
+}
+
+// Reads the `# $schema: ./service.schema.json` directive from the first line of a document, returning the
+// path along with the span of the comment. The parser attaches the directive to the first entry or item like any
+// other comment, hence sort_keys and Document::remove take care to keep it at the top.
+pub fn schema_directive<Index: CharIndex>(object: &WithSpan<ExpandedValue<Index>, Index>) -> Option<WithSpan<String, Index>> {
+    let context = match &object.value {
+        ExpandedValue::List { items, .. } => &items.first()?.context,
+        ExpandedValue::Map { entries, .. } => &entries.first()?.context,
+        _ => return None,
+    };
+
+    // A gap means the comment is preceded by blank lines.
+    let comment = context.comments.first().filter(|comment| comment.gap == 0)?;
+    let path = comment.contents.value.strip_prefix(SCHEMA_DIRECTIVE)?.trim();
+
+    (!path.is_empty()).then(|| WithSpan {
+        span: comment.contents.span,
+        value: path.to_string(),
+    })
+}
+
+// Resolves the path of a directive, which is relative to the document's file.
+pub fn directive_path(document: &Path, directive: &str) -> PathBuf {
+    document.parent().unwrap_or(Path::new("")).join(directive)
+}
+
+// Loads the schema of a directive.
+pub fn load_directive(document: &Path, directive: &str) -> Result<(Schema, Vec<LoadError>), LoadError> {
+    load_file(&directive_path(document, directive))
+}
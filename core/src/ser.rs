@@ -0,0 +1,352 @@
+use std::fmt;
+use serde::ser::{self, Serialize};
+
+use crate::de::Spanned;
+use crate::formatter::{FormatOptions, Layout, StringContext, format_compact, format_nested, format_with_options};
+use crate::iterator::Marker;
+use crate::result::*;
+use crate::span::{Span, WithSpan};
+
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Message(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Message(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        Error::Message(message.to_string())
+    }
+}
+
+
+// Serialized values have no position in a document.
+fn spanned<T>(value: T) -> WithSpan<T, ()> {
+    WithSpan::new(value, Span::point(&Marker { byte_offset: 0, index: () }))
+}
+
+fn list(items: Vec<CompactValue<()>>) -> CompactValue<()> {
+    CompactValue::List {
+        items: items.into_iter().map(spanned).collect(),
+        item_completion_spans: Vec::new(),
+    }
+}
+
+fn map(entries: Vec<(String, CompactValue<()>)>) -> CompactValue<()> {
+    CompactValue::Map {
+        entries: entries.into_iter().map(|(key, value)| CompactMapEntry { key: spanned(key), value: spanned(value) }).collect(),
+        key_completion_spans: Vec::new(),
+        value_completion_spans: Vec::new(),
+    }
+}
+
+
+struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = CompactValue<()>;
+    type Error = Error;
+
+    type SerializeSeq = SerializeList;
+    type SerializeTuple = SerializeList;
+    type SerializeTupleStruct = SerializeList;
+    type SerializeTupleVariant = SerializeVariant<SerializeList>;
+    type SerializeMap = SerializeMap;
+    type SerializeStruct = SerializeMap;
+    type SerializeStructVariant = SerializeVariant<SerializeMap>;
+
+    fn serialize_bool(self, value: bool) -> Result<CompactValue<()>, Error> {
+        Ok(CompactValue::Bool(value))
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<CompactValue<()>, Error> {
+        Ok(CompactValue::Integer(value.into()))
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<CompactValue<()>, Error> {
+        Ok(CompactValue::Integer(value.into()))
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<CompactValue<()>, Error> {
+        Ok(CompactValue::Integer(value.into()))
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<CompactValue<()>, Error> {
+        Ok(CompactValue::Integer(value))
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<CompactValue<()>, Error> {
+        Ok(CompactValue::Integer(value.into()))
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<CompactValue<()>, Error> {
+        Ok(CompactValue::Integer(value.into()))
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<CompactValue<()>, Error> {
+        Ok(CompactValue::Integer(value.into()))
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<CompactValue<()>, Error> {
+        i64::try_from(value)
+            .map(CompactValue::Integer)
+            .map_err(|_| Error::Message(format!("integer {} is out of range", value)))
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<CompactValue<()>, Error> {
+        Ok(CompactValue::Float(value.into()))
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<CompactValue<()>, Error> {
+        Ok(CompactValue::Float(value))
+    }
+
+    fn serialize_char(self, value: char) -> Result<CompactValue<()>, Error> {
+        Ok(CompactValue::String(value.to_string()))
+    }
+
+    fn serialize_str(self, value: &str) -> Result<CompactValue<()>, Error> {
+        Ok(CompactValue::String(value.to_string()))
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<CompactValue<()>, Error> {
+        Ok(list(value.iter().map(|byte| CompactValue::Integer((*byte).into())).collect()))
+    }
+
+    fn serialize_none(self) -> Result<CompactValue<()>, Error> {
+        Ok(CompactValue::Null)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<CompactValue<()>, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<CompactValue<()>, Error> {
+        Ok(CompactValue::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<CompactValue<()>, Error> {
+        Ok(CompactValue::Null)
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _variant_index: u32, variant: &'static str) -> Result<CompactValue<()>, Error> {
+        Ok(CompactValue::String(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _name: &'static str, value: &T) -> Result<CompactValue<()>, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(self, _name: &'static str, _variant_index: u32, variant: &'static str, value: &T) -> Result<CompactValue<()>, Error> {
+        Ok(map(vec![(variant.to_string(), value.serialize(self)?)]))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeList, Error> {
+        Ok(SerializeList(Vec::with_capacity(len.unwrap_or(0))))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeList, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SerializeList, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _variant_index: u32, variant: &'static str, len: usize) -> Result<Self::SerializeTupleVariant, Error> {
+        Ok(SerializeVariant(variant, self.serialize_seq(Some(len))?))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<SerializeMap, Error> {
+        Ok(SerializeMap {
+            entries: Vec::with_capacity(len.unwrap_or(0)),
+            next_key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SerializeMap, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _variant_index: u32, variant: &'static str, len: usize) -> Result<Self::SerializeStructVariant, Error> {
+        Ok(SerializeVariant(variant, self.serialize_map(Some(len))?))
+    }
+}
+
+
+struct SerializeList(Vec<CompactValue<()>>);
+
+impl ser::SerializeSeq for SerializeList {
+    type Ok = CompactValue<()>;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.0.push(value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<CompactValue<()>, Error> {
+        Ok(list(self.0))
+    }
+}
+
+impl ser::SerializeTuple for SerializeList {
+    type Ok = CompactValue<()>;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<CompactValue<()>, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeList {
+    type Ok = CompactValue<()>;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<CompactValue<()>, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+
+struct SerializeMap {
+    entries: Vec<(String, CompactValue<()>)>,
+    next_key: Option<String>,
+}
+
+impl ser::SerializeMap for SerializeMap {
+    type Ok = CompactValue<()>;
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Error> {
+        self.next_key = Some(match key.serialize(Serializer)? {
+            CompactValue::String(key) => key,
+            CompactValue::Integer(key) => key.to_string(),
+            CompactValue::Bool(key) => key.to_string(),
+            _ => return Err(Error::Message("map keys must be strings".to_string())),
+        });
+
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.next_key.take().expect("serialize_value() called before serialize_key()");
+        self.entries.push((key, value.serialize(Serializer)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<CompactValue<()>, Error> {
+        Ok(map(self.entries))
+    }
+}
+
+impl ser::SerializeStruct for SerializeMap {
+    type Ok = CompactValue<()>;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.entries.push((key.to_string(), value.serialize(Serializer)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<CompactValue<()>, Error> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+
+struct SerializeVariant<T>(&'static str, T);
+
+impl ser::SerializeTupleVariant for SerializeVariant<SerializeList> {
+    type Ok = CompactValue<()>;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(&mut self.1, value)
+    }
+
+    fn end(self) -> Result<CompactValue<()>, Error> {
+        Ok(map(vec![(self.0.to_string(), ser::SerializeSeq::end(self.1)?)]))
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeVariant<SerializeMap> {
+    type Ok = CompactValue<()>;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        ser::SerializeStruct::serialize_field(&mut self.1, key, value)
+    }
+
+    fn end(self) -> Result<CompactValue<()>, Error> {
+        Ok(map(vec![(self.0.to_string(), ser::SerializeMap::end(self.1)?)]))
+    }
+}
+
+
+impl<T: Serialize> Serialize for Spanned<T> {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value.serialize(serializer)
+    }
+}
+
+
+// Collections are written in compact form where they fit, as with Layout::Auto.
+pub fn to_string<T: ?Sized + Serialize>(value: &T) -> Result<String, Error> {
+    to_string_with_options(value, &FormatOptions { layout: Layout::Auto, ..Default::default() })
+}
+
+// Serialized values have no layout of their own, hence Layout::Preserve writes every non-empty collection in
+// expanded form.
+pub fn to_string_with_options<T: ?Sized + Serialize>(value: &T, options: &FormatOptions) -> Result<String, Error> {
+    match value.serialize(Serializer)? {
+        // Neither would parse back, as an empty document has no value.
+        CompactValue::List { items, .. } if items.is_empty() => {
+            Err(Error::Message("an empty list cannot be written as a document".to_string()))
+        },
+        CompactValue::Map { entries, .. } if entries.is_empty() => {
+            Err(Error::Message("an empty map cannot be written as a document".to_string()))
+        },
+        value @ (CompactValue::List { .. } | CompactValue::Map { .. }) => {
+            Ok(format_with_options(&spanned(ExpandedValue::Compact(value).into_expanded()), options))
+        },
+        _ => {
+            Err(Error::Message("the root value must be a map or a list".to_string()))
+        },
+    }
+}
+
+// Writes what follows "key:" or "-" for a value nested in a line indented by `indent`, without the final line break.
+pub(crate) fn to_nested_string<T: ?Sized + Serialize>(value: &T, indent: usize, options: &FormatOptions, list_item: bool) -> Result<String, Error> {
+    let context = if list_item { StringContext::ListItem } else { StringContext::MapValue };
+    Ok(format_nested(&ExpandedValue::Compact(value.serialize(Serializer)?).into_expanded(), indent, options, context))
+}
+
+// Writes a value which can appear inside a compact collection.
+pub(crate) fn to_compact_string<T: ?Sized + Serialize>(value: &T, options: &FormatOptions) -> Result<String, Error> {
+    Ok(format_compact(&ExpandedValue::Compact(value.serialize(Serializer)?), options))
+}
+
+pub fn to_writer<W: std::io::Write, T: ?Sized + Serialize>(mut writer: W, value: &T) -> Result<(), Error> {
+    writer.write_all(to_string(value)?.as_bytes()).map_err(Error::Io)
+}
@@ -0,0 +1,182 @@
+use std::cmp::Ordering;
+
+use crate::find::FindPathItem;
+use crate::iterator::CharIndex;
+use crate::result::*;
+use crate::schema::{Schema, SchemaType, is_schema_directive};
+use crate::span::WithSpan;
+
+
+pub enum KeyOrder<'a> {
+    Alphabetical,
+    Comparator(&'a dyn Fn(&str, &str) -> Ordering),
+    // Listed keys come first, followed by the other keys in their original order.
+    Listed(&'a [&'a str]),
+    // Keys listed in the properties of the schema at the map's path come first, in the schema's order, followed by
+    // the other keys in their original order.
+    Schema(&'a Schema),
+}
+
+impl KeyOrder<'_> {
+    fn sort<T>(&self, entries: &mut [T], key: impl Fn(&T) -> &str, path: &[FindPathItem<'_>]) {
+        let position = |keys: &[&str], key: &str| keys.iter().position(|listed_key| *listed_key == key).unwrap_or(keys.len());
+
+        match self {
+            KeyOrder::Alphabetical => entries.sort_by(|a, b| key(a).cmp(key(b))),
+            KeyOrder::Comparator(comparator) => entries.sort_by(|a, b| comparator(key(a), key(b))),
+            KeyOrder::Listed(keys) => entries.sort_by_key(|entry| position(keys, key(entry))),
+            KeyOrder::Schema(schema) => {
+                // Keys listed by several variants are ordered by their first occurrence.
+                let keys = schema
+                    .schemas_at(path)
+                    .into_iter()
+                    .flat_map(Schema::kinds)
+                    .flat_map(|kind| match kind {
+                        SchemaType::Map { properties, .. } => properties.iter().map(|(property, _)| property.as_str()).collect(),
+                        _ => Vec::new(),
+                    })
+                    .collect::<Vec<_>>();
+
+                entries.sort_by_key(|entry| position(&keys, key(entry)));
+            },
+        }
+    }
+}
+
+fn child_path<'a>(path: &[FindPathItem<'a>], path_item: FindPathItem<'a>) -> Vec<FindPathItem<'a>> {
+    let mut child_path = path.to_vec();
+    child_path.push(path_item);
+    child_path
+}
+
+
+// Sorts map entries, which carry their comments and gap along. The sort is stable and the entry which ends up first
+// loses any blank line above it. The comments at the top of the document, including a schema directive, stay there.
+pub fn sort_keys<Index: CharIndex>(value: &mut ExpandedValue<Index>, order: &KeyOrder<'_>, recursive: bool) {
+    sort_expanded_keys(value, order, recursive, &[]);
+}
+
+fn sort_expanded_keys<Index: CharIndex>(value: &mut ExpandedValue<Index>, order: &KeyOrder<'_>, recursive: bool, path: &[FindPathItem<'_>]) {
+    match value {
+        ExpandedValue::Compact(value) => sort_compact_keys(value, order, recursive, path),
+        ExpandedValue::List { items, .. } if recursive => {
+            for (index, item) in items.iter_mut().enumerate() {
+                sort_expanded_keys(&mut item.value.value, order, recursive, &child_path(path, FindPathItem::ListIndex(index)));
+            }
+        },
+        ExpandedValue::Map { entries, .. } => {
+            let header = entries.first_mut().filter(|_| path.is_empty()).map(|entry| take_header(&mut entry.context));
+
+            order.sort(entries, |entry| &entry.key.value, path);
+
+            if let Some(entry) = entries.first_mut() {
+                match entry.context.comments.first_mut() {
+                    Some(comment) => comment.gap = 0,
+                    None => entry.context.gap = 0,
+                }
+
+                if let Some((comments, gap)) = header {
+                    restore_header(&mut entry.context, comments, gap);
+                }
+            }
+
+            if recursive {
+                for entry in entries {
+                    let key = entry.key.value.clone();
+                    sort_expanded_keys(&mut entry.value.value, order, recursive, &child_path(path, FindPathItem::MapKey(&key)));
+                }
+            }
+        },
+        _ => (),
+    }
+}
+
+// Detaches the comments at the top of the document from the first entry, i.e. those up to the first blank line, or a
+// schema directive directly above the entry. Also returns the number of blank lines after them.
+fn take_header<Index: CharIndex>(context: &mut Context<Index>) -> (Vec<StandaloneComment<Index>>, usize) {
+    let (count, gap) = match context.comments.iter().skip(1).position(|comment| comment.gap > 0) {
+        Some(index) => (index + 1, context.comments[index + 1].gap),
+        None if context.gap > 0 => (context.comments.len(), context.gap),
+        None if context.comments.first().is_some_and(|comment| is_schema_directive(&comment.contents.value)) => (1, 0),
+        None => (0, 0),
+    };
+
+    if count == context.comments.len() {
+        context.gap = 0;
+    }
+
+    (context.comments.drain(..count).collect(), gap)
+}
+
+fn restore_header<Index: CharIndex>(context: &mut Context<Index>, header: Vec<StandaloneComment<Index>>, gap: usize) {
+    if header.is_empty() {
+        return;
+    }
+
+    match context.comments.first_mut() {
+        Some(comment) => comment.gap = gap,
+        None => context.gap = gap,
+    }
+
+    context.comments.splice(0..0, header);
+}
+
+// Sorts the map at the given path, returning false if there is no such value.
+pub fn sort_keys_at<Index: CharIndex>(object: &mut WithSpan<ExpandedValue<Index>, Index>, path: &[FindPathItem<'_>], order: &KeyOrder<'_>, recursive: bool) -> bool {
+    let mut value = &mut object.value;
+
+    for (depth, path_item) in path.iter().enumerate() {
+        value = match (value, path_item) {
+            (ExpandedValue::Compact(value), _) => return sort_compact_keys_at(value, path, depth, order, recursive),
+            (ExpandedValue::List { items, .. }, FindPathItem::ListIndex(index)) if *index < items.len() =>
+                &mut items[*index].value.value,
+            (ExpandedValue::Map { entries, .. }, FindPathItem::MapKey(key)) => match entries.iter_mut().find(|entry| entry.key.value == *key) {
+                Some(entry) => &mut entry.value.value,
+                None => return false,
+            },
+            _ => return false,
+        };
+    }
+
+    sort_expanded_keys(value, order, recursive, path);
+    true
+}
+
+// Continues sort_keys_at() in a compact value, from the path item at the given depth.
+fn sort_compact_keys_at<Index: CharIndex>(mut value: &mut CompactValue<Index>, path: &[FindPathItem<'_>], depth: usize, order: &KeyOrder<'_>, recursive: bool) -> bool {
+    for path_item in &path[depth..] {
+        value = match (value, path_item) {
+            (CompactValue::List { items, .. }, FindPathItem::ListIndex(index)) if *index < items.len() =>
+                &mut items[*index].value,
+            (CompactValue::Map { entries, .. }, FindPathItem::MapKey(key)) => match entries.iter_mut().find(|entry| entry.key.value == *key) {
+                Some(entry) => &mut entry.value.value,
+                None => return false,
+            },
+            _ => return false,
+        };
+    }
+
+    sort_compact_keys(value, order, recursive, path);
+    true
+}
+
+fn sort_compact_keys<Index: CharIndex>(value: &mut CompactValue<Index>, order: &KeyOrder<'_>, recursive: bool, path: &[FindPathItem<'_>]) {
+    match value {
+        CompactValue::List { items, .. } if recursive => {
+            for (index, item) in items.iter_mut().enumerate() {
+                sort_compact_keys(&mut item.value, order, recursive, &child_path(path, FindPathItem::ListIndex(index)));
+            }
+        },
+        CompactValue::Map { entries, .. } => {
+            order.sort(entries, |entry| &entry.key.value, path);
+
+            if recursive {
+                for entry in entries {
+                    let key = entry.key.value.clone();
+                    sort_compact_keys(&mut entry.value.value, order, recursive, &child_path(path, FindPathItem::MapKey(&key)));
+                }
+            }
+        },
+        _ => (),
+    }
+}
@@ -1,5 +1,60 @@
 #![cfg(test)]
 
+use crate::iterator::CharIndex;
+use crate::parser::ParseResult;
+use crate::result::*;
+
+
+impl<Index: CharIndex> ParseResult<Index> {
+    fn json(&self) -> Option<String> {
+        self.object.as_ref().map(|object| json_expanded(&object.value))
+    }
+}
+
+fn json_expanded<Index: CharIndex>(value: &ExpandedValue<Index>) -> String {
+    match value {
+        ExpandedValue::Compact(value) => json_compact(value),
+        ExpandedValue::List { items, .. } =>
+            json_list(items.iter().map(|item| json_expanded(&item.value.value))),
+        ExpandedValue::Map { entries, .. } =>
+            json_map(entries.iter().map(|entry| (&entry.key.value[..], json_expanded(&entry.value.value)))),
+        ExpandedValue::String { string, .. } => serde_json::to_string(string).unwrap(),
+    }
+}
+
+fn json_compact<Index: CharIndex>(value: &CompactValue<Index>) -> String {
+    match value {
+        CompactValue::Bool(value) => value.to_string(),
+        CompactValue::Float(value) if value.is_nan() => "NaN".to_string(),
+        CompactValue::Float(value) if value.is_infinite() =>
+            (if *value > 0.0 { "Infinity" } else { "-Infinity" }).to_string(),
+        CompactValue::Float(value) => value.to_string(),
+        CompactValue::Integer(value) => value.to_string(),
+        CompactValue::List { items, .. } =>
+            json_list(items.iter().map(|item| json_compact(&item.value))),
+        CompactValue::Map { entries, .. } =>
+            json_map(entries.iter().map(|entry| (&entry.key.value[..], json_compact(&entry.value.value)))),
+        CompactValue::Null => "null".to_string(),
+        CompactValue::String(value) => serde_json::to_string(value).unwrap(),
+    }
+}
+
+fn json_list(items: impl Iterator<Item = String>) -> String {
+    format!("[{}]", items.collect::<Vec<_>>().join(", "))
+}
+
+fn json_map<'a>(entries: impl Iterator<Item = (&'a str, String)>) -> String {
+    let entries = entries
+        .map(|(key, value)| format!("{}: {}", serde_json::to_string(key).unwrap(), value))
+        .collect::<Vec<_>>();
+
+    if entries.is_empty() {
+        "{}".to_string()
+    } else {
+        format!("{{ {} }}", entries.join(", "))
+    }
+}
+
 
 #[test]
 fn entries() {
@@ -10,6 +65,7 @@ fn entries() {
         ("a: b", r#"{ "a": "b" }"#),
         ("- a : 3", r#"[{ "a": 3 }]"#),
         ("- a: 3\n- b", r#"[{ "a": 3 }, "b"]"#),
+        ("- a: 3\n- b: 4\n  c: 5", r#"[{ "a": 3 }, { "b": 4, "c": 5 }]"#),
         ("- a:\n    - b: c", r#"[{ "a": [{ "b": "c" }] }]"#),
 
         ("- 3", r#"[3]"#),
@@ -17,14 +73,21 @@ fn entries() {
         ("- inf", r#"[Infinity]"#),
         ("- -inf", r#"[-Infinity]"#),
         ("- nan", r#"[NaN]"#),
-        // ("- [inf, -inf]", r#"[[Infinity, -Infinity]]"#),
+        ("- [inf, -inf]", r#"[[Infinity, -Infinity]]"#),
         ("- true", r#"[true]"#),
         ("- false", r#"[false]"#),
         // ("- a", r#"["a"]"#),
-        // ("- [3, 4, 5]", r#"[[3, 4, 5]]"#),
-        // ("- [3, 4, 5, ]", r#"[[3, 4, 5]]"#),
-        // ("- [a, b ]", r#"[["a", "b"]]"#),
-        // ("- [a, b, [c, 61]]", r#"[["a", "b", ["c", 61]]]"#),
+        ("- [3, 4, 5]", r#"[[3, 4, 5]]"#),
+        ("- [3, 4, 5, ]", r#"[[3, 4, 5]]"#),
+        ("- [a, b ]", r#"[["a", "b"]]"#),
+        ("- [a, b, [c, 61]]", r#"[["a", "b", ["c", 61]]]"#),
+        ("- []", r#"[[]]"#),
+        ("a: { cpu: 79, case: 72 }", r#"{ "a": { "cpu": 79, "case": 72 } }"#),
+        ("a: {x y: [1, 2],}", r#"{ "a": { "x y": [1, 2] } }"#),
+        ("a: {}", r#"{ "a": {} }"#),
+        ("\"a b\": \"x # \\\"y\\u00e9\"", r#"{ "a b": "x # \"yé" }"#),
+        ("- \"c: d\"\n- [\"]\", \"\"]", r#"["c: d", ["]", ""]]"#),
+        ("a: { \"x, y\": 1 }", r#"{ "a": { "x, y": 1 } }"#),
     ];
 
     for (input, expected) in &entries {
@@ -44,6 +107,15 @@ fn entries() {
         assert!(result.errors.is_empty());
         assert_eq!(&result.json().unwrap(), expected);
     }
+
+    // Unterminated quoted strings at the start of a line are reported once, without affecting the next lines.
+    for (input, expected) in [("\"abc\n", None), ("- \"abc\n- x\n", Some(r#"["x"]"#)), ("a: 1\n\"b: 2\nc: 3\n", Some(r#"{ "a": 1, "c": 3 }"#))] {
+        let result = super::parse::<super::indexers::Empty>(input);
+        let errors = result.errors.iter().map(|error| format!("{:?}", error.value)).collect::<Vec<_>>();
+
+        assert_eq!(errors, ["MissingStringClose"]);
+        assert_eq!(result.json().as_deref(), expected);
+    }
 }
 
 // #[test]
@@ -70,3 +142,1113 @@ fn entries() {
 //     assert!(result.errors.is_empty());
 //     assert_eq!(&result.json().unwrap(), r#"["a", "b", "c"]"#);
 // }
+
+#[test]
+fn deserialize() {
+    use std::collections::HashMap;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Role {
+        Backend,
+        Frontend,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Server {
+        ip: String,
+        port: u16,
+        role: Role,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Config {
+        ports: Vec<u16>,
+        servers: HashMap<String, Server>,
+        temp_targets: HashMap<String, f64>,
+        title: Option<String>,
+    }
+
+    let input = "ports: [8000, 8001]\ntemp_targets: { cpu: 79, case: 72.5 }\nservers:\n  alpha:\n    ip: alpha.local\n    port: 80\n    role: frontend\n";
+    let config: Config = super::from_str(input).unwrap();
+
+    assert_eq!(config.ports, vec![8000, 8001]);
+    assert_eq!(config.servers["alpha"], Server { ip: "alpha.local".to_string(), port: 80, role: Role::Frontend });
+    assert_eq!(config.temp_targets["case"], 72.5);
+    assert_eq!(config.title, None);
+
+    let input = "ports: []\ntemp_targets: {}\nservers:\n  alpha:\n    ip: alpha.local\n    port: eighty\n    role: backend\n";
+    let error = super::from_str::<Config>(input).unwrap_err();
+
+    assert_eq!(error.to_string(), "expected integer, found string at servers.alpha.port");
+    assert_eq!(&input[error.span.unwrap().0.byte_offset..error.span.unwrap().1.byte_offset], "eighty");
+
+    let error = super::from_str::<Vec<Server>>("- ip: x\n  port: 3\n  role: backend\n- ip: y\n  role: backend\n").unwrap_err();
+
+    assert_eq!(error.to_string(), "missing field `port` at [1]");
+
+    let error = super::from_str::<Config>("ports: [8000, 8001\n").unwrap_err();
+
+    assert_eq!(error.to_string(), "missing ']'");
+}
+
+#[test]
+fn deserialize_spanned() {
+    use serde::Deserialize;
+    use super::Spanned;
+
+    #[derive(Debug, Deserialize)]
+    struct Config {
+        name: Spanned<String>,
+        ports: Spanned<Vec<Spanned<u16>>>,
+    }
+
+    let input = "name: web\nports: [80, 443]\n";
+    let config: Config = super::from_str(input).unwrap();
+    let text = |span: super::Span<usize>| &input[span.0.byte_offset..span.1.byte_offset];
+
+    assert_eq!(config.name.value, "web");
+    assert_eq!(text(config.name.span), "web");
+    assert_eq!(text(config.ports.span), "[80, 443]");
+    assert_eq!(config.ports.value[1].value, 443);
+    assert_eq!(text(config.ports.value[1].span), "443");
+}
+
+#[test]
+fn serialize() {
+    use std::collections::BTreeMap;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    enum Kind {
+        Http { port: u16 },
+        Static(String),
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Server {
+        name: String,
+        kind: Kind,
+        tags: Vec<String>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Config {
+        title: String,
+        enabled: bool,
+        ratio: f64,
+        limits: BTreeMap<String, i64>,
+        servers: Vec<Server>,
+        matrix: Vec<Vec<i64>>,
+        notes: Vec<String>,
+    }
+
+    let config = Config {
+        title: "Example # 1".to_string(),
+        enabled: true,
+        ratio: 3.0,
+        limits: [("cpu".to_string(), 79), ("max-case".to_string(), 72)].into_iter().collect(),
+        servers: vec![
+            Server { name: "alpha".to_string(), kind: Kind::Http { port: 80 }, tags: vec!["a, b".to_string(), "web".to_string()] },
+            Server { name: "null".to_string(), kind: Kind::Static("/srv".to_string()), tags: Vec::new() },
+        ],
+        matrix: vec![vec![1, 2], vec![3]],
+        notes: vec!["key: value".to_string(), "plain".to_string(), "".to_string()],
+    };
+
+    assert_eq!(super::to_string(&vec!["key: value", "tab\there"]).unwrap(), "- \"key: value\"\n- \"tab\\there\"\n");
+    assert_eq!(super::from_str::<Vec<String>>("- \"key: value\"\n- \"tab\\there\"\n").unwrap(), vec!["key: value", "tab\there"]);
+
+    let output = super::to_string(&config).unwrap();
+
+    assert_eq!(output, r#"title: "Example # 1"
+enabled: true
+ratio: 3.0
+limits: { cpu: 79, "max-case": 72 }
+servers:
+  - { name: alpha, kind: { Http: { port: 80 } }, tags: ["a, b", web] }
+  - { name: "null", kind: { Static: /srv }, tags: [] }
+matrix: [[1, 2], [3]]
+notes: [key: value, plain, ""]
+"#);
+
+    assert_eq!(super::from_str::<Config>(&output).unwrap(), config);
+
+    let options = super::FormatOptions { indent_width: 4, layout: super::Layout::Auto, max_width: 30, trailing_commas: false };
+    let output = super::to_string_with_options(&config, &options).unwrap();
+
+    assert_eq!(output, r#"title: "Example # 1"
+enabled: true
+ratio: 3.0
+limits:
+    cpu: 79
+    "max-case": 72
+servers:
+    - name: alpha
+      kind:
+          Http: { port: 80 }
+      tags: ["a, b", web]
+    - name: "null"
+      kind: { Static: /srv }
+      tags: []
+matrix: [[1, 2], [3]]
+notes: [key: value, plain, ""]
+"#);
+
+    assert_eq!(super::from_str::<Config>(&output).unwrap(), config);
+
+    assert!(super::to_string(&Vec::<u8>::new()).is_err());
+    assert!(super::to_string(&std::collections::BTreeMap::<String, u8>::new()).is_err());
+}
+
+#[test]
+fn format() {
+    let input = "# Header\n\ntitle: Example   # trailing\ndatabase:\n    # Ports\n    ports: [8000, 8001]\n    data:\n        - [delta, phi]\n        - 3.14\n\nservers:\n    # First\n    - name: alpha\n      role: frontend\n    -\n        - x\n";
+    let mut object = super::parse::<super::indexers::Empty>(input).object.unwrap();
+
+    assert_eq!(super::format(&object), "# Header\n\ntitle: Example # trailing\ndatabase:\n  # Ports\n  ports: [8000, 8001]\n  data:\n    - [delta, phi]\n    - 3.14\n\nservers:\n  # First\n  - name: alpha\n    role: frontend\n  -\n    - x\n");
+
+    let ExpandedValue::Map { entries, .. } = &mut object.value else { unreachable!() };
+    let ExpandedValue::Map { entries: database_entries, .. } = &mut entries[1].value.value else { unreachable!() };
+    let data = std::mem::replace(&mut database_entries[1].value.value, ExpandedValue::Compact(CompactValue::Null));
+
+    database_entries[1].value.value = ExpandedValue::Compact(data.into_compact().unwrap());
+    database_entries[0].value.value = std::mem::replace(&mut database_entries[0].value.value, ExpandedValue::Compact(CompactValue::Null)).into_expanded();
+
+    assert_eq!(super::format(&object), "# Header\n\ntitle: Example # trailing\ndatabase:\n  # Ports\n  ports:\n    - 8000\n    - 8001\n  data: [[delta, phi], 3.14]\n\nservers:\n  # First\n  - name: alpha\n    role: frontend\n  -\n    - x\n");
+
+    let ExpandedValue::Map { entries, .. } = object.value else { unreachable!() };
+    assert!(entries.into_iter().nth(2).unwrap().value.value.into_compact().is_err());
+    // Comments after the last value are kept at the end of the document.
+    let format_document = |input: &str| super::format_document(&super::parse::<super::indexers::Empty>(input), &Default::default());
+
+    assert_eq!(format_document("a: 1\n# end\n").as_deref(), Some("a: 1\n# end\n"));
+    assert_eq!(format_document("a:\n    b: 1\n\n    # b\n\n# c\n").as_deref(), Some("a:\n  b: 1\n\n# b\n\n# c\n"));
+    assert_eq!(format_document("# only\n").as_deref(), Some("# only\n"));
+    assert_eq!(format_document("a: [1\n# end\n"), None);
+}
+
+#[test]
+fn format_auto_layout() {
+    use super::{FormatOptions, Layout};
+
+    let input = "ports:\n  - 8000\n  - 8001\ndata: [[delta, phi], [alpha, beta, gamma, delta, epsilon, zeta, eta, theta, iota], 3.14]\nowner:\n  # Name\n  name: Tom\n  tags: { a: 1 }\n";
+    let object = super::parse::<super::indexers::Empty>(input).object.unwrap();
+
+    let options = FormatOptions {
+        indent_width: 4,
+        layout: Layout::Auto,
+        max_width: 40,
+        trailing_commas: false,
+    };
+
+    assert_eq!(super::format_with_options(&object, &options), "ports: [8000, 8001]\ndata:\n    - [delta, phi]\n    -\n        - alpha\n        - beta\n        - gamma\n        - delta\n        - epsilon\n        - zeta\n        - eta\n        - theta\n        - iota\n    - 3.14\nowner:\n    # Name\n    name: Tom\n    tags: { a: 1 }\n");
+
+    let options = FormatOptions {
+        trailing_commas: true,
+        max_width: 80,
+        ..options
+    };
+
+    assert_eq!(super::format_with_options(&object, &options), "ports: [8000, 8001,]\ndata:\n    - [delta, phi,]\n    - [alpha, beta, gamma, delta, epsilon, zeta, eta, theta, iota,]\n    - 3.14\nowner:\n    # Name\n    name: Tom\n    tags: { a: 1, }\n");
+    assert!(super::parse::<super::indexers::Empty>(&super::format_with_options(&object, &options)).errors.is_empty());
+
+    // Entries of maps in list items are aligned with the first key, whatever the indent width.
+    let input = "- a: 1\n  b:\n    - x\n- c: 3\n";
+    let object = super::parse::<super::indexers::Empty>(input).object.unwrap();
+
+    for (layout, expected) in [(Layout::Preserve, "- a: 1\n  b:\n      - x\n- c: 3\n"), (Layout::Auto, "- a: 1\n  b: [x]\n- { c: 3 }\n")] {
+        let options = FormatOptions { indent_width: 4, layout, max_width: 10, trailing_commas: false };
+        let output = super::format_with_options(&object, &options);
+
+        assert_eq!(output, expected);
+        assert!(super::parse::<super::indexers::Empty>(&output).errors.is_empty());
+    }
+
+    // Lines are measured in terminal cells, including their trailing comment.
+    for (input, expected) in [
+        ("names:\n  - 日本\n  - 中文\n", "names:\n  - 日本\n  - 中文\n"),
+        ("names:\n  - ab\n  - cd\n", "names: [ab, cd]\n"),
+        ("ports:  # ☕☕\n  - 1\n", "ports: # ☕☕\n  - 1\n"),
+        ("ports:  # ab\n  - 1\n", "ports: [1] # ab\n"),
+    ] {
+        let object = super::parse::<super::indexers::Empty>(input).object.unwrap();
+        let options = FormatOptions { indent_width: 2, layout: Layout::Auto, max_width: 15, trailing_commas: false };
+
+        assert_eq!(super::format_with_options(&object, &options), expected, "{:?}", input);
+    }
+}
+
+
+#[test]
+fn edit_document() {
+    use super::{Document, FindPathItem::*};
+
+    let mut document = Document::new("# Service\nname: api  # display name\nversion: 1\n\nservers:\n  # Primary\n  - host: alpha.local\n    port: 80\n  - host: beta.local  # backup\n    port: 81\ntags: [web, internal]\n");
+
+    let edit = document.set(&[MapKey("version")], &2).unwrap();
+    assert_eq!(edit.text, "2");
+    assert_eq!(document.text(), "# Service\nname: api  # display name\nversion: 2\n\nservers:\n  # Primary\n  - host: alpha.local\n    port: 80\n  - host: beta.local  # backup\n    port: 81\ntags: [web, internal]\n");
+
+    document.set(&[MapKey("servers"), ListIndex(1), MapKey("port")], &8080).unwrap();
+    document.insert_after(&[], "name", "owner", "ops").unwrap();
+    document.push(&[MapKey("servers")], &serde_json::json!({ "host": "gamma.local", "port": 82 })).unwrap();
+    document.push(&[MapKey("tags")], "public").unwrap();
+    document.remove(&[MapKey("servers"), ListIndex(0)]).unwrap();
+
+    assert_eq!(document.text(), "# Service\nname: api  # display name\nowner: ops\nversion: 2\n\nservers:\n  - host: beta.local  # backup\n    port: 8080\n  - { host: gamma.local, port: 82 }\ntags: [web, internal, public]\n");
+
+    document.set(&[MapKey("name")], &["a", "b"]).unwrap();
+    document.set(&[MapKey("servers")], "none").unwrap();
+    document.remove(&[MapKey("tags"), ListIndex(0)]).unwrap();
+    document.insert(&[], "limits", &serde_json::json!({ "cpu": [1, 2, 3], "mem": { "soft": 1, "hard": { "x": 1, "y": [1, { "a": 1 }] } } })).unwrap();
+    document.remove(&[MapKey("servers")]).unwrap();
+
+    assert_eq!(document.text(), "# Service\nname: [a, b]  # display name\nowner: ops\nversion: 2\n\ntags: [internal, public]\nlimits: { cpu: [1, 2, 3], mem: { soft: 1, hard: { x: 1, y: [1, { a: 1 }] } } }\n");
+    assert!(matches!(document.insert(&[], "owner", &1), Err(super::document::Error::DuplicateKey(_))));
+    assert!(matches!(document.set(&[MapKey("missing")], &1), Err(super::document::Error::NotFound)));
+
+    // The document's indentation is reused and comments which cannot follow "key:" are moved above it.
+    let mut document = Document::new("a:\n    b: 1\nd: 3  # d\nl:\n    - x\n    - p: 1\n      # q comment\n      q: 2\n");
+
+    document.set(&[MapKey("d")], &serde_json::json!({ "k": [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25] })).unwrap();
+    document.remove(&[MapKey("a"), MapKey("b")]).unwrap();
+    document.remove(&[MapKey("l"), ListIndex(1), MapKey("p")]).unwrap();
+
+    assert_eq!(document.text(), "a: {}\n# d\nd:\n    k:\n        - 1\n        - 2\n        - 3\n        - 4\n        - 5\n        - 6\n        - 7\n        - 8\n        - 9\n        - 10\n        - 11\n        - 12\n        - 13\n        - 14\n        - 15\n        - 16\n        - 17\n        - 18\n        - 19\n        - 20\n        - 21\n        - 22\n        - 23\n        - 24\n        - 25\nl:\n    - x\n    # q comment\n    - q: 2\n");
+
+    // Empty nested collections, e.g. floating keys and handles, do not determine the indentation.
+    for text in ["a:\n  b:\n", "a:\n  -\n"] {
+        let mut document = Document::new(text);
+        assert!(matches!(document.insert(&[], "c", &1), Err(super::document::Error::Parse)));
+    }
+}
+
+
+#[test]
+fn sort_keys() {
+    use super::{FindPathItem::*, KeyOrder};
+
+    let input = "# Name\nname: api  # display\n\nversion: 2\nservers:\n  - port: 80\n    # Host\n    host: alpha.local\nlimits: { mem: 1, cpu: 2 }\n";
+    let mut object = super::parse::<super::indexers::Empty>(input).object.unwrap();
+
+    assert!(super::sort_keys_at(&mut object, &[MapKey("servers"), ListIndex(0)], &KeyOrder::Alphabetical, false));
+    assert!(!super::sort_keys_at(&mut object, &[MapKey("missing")], &KeyOrder::Alphabetical, false));
+    assert_eq!(super::format(&object), "# Name\nname: api # display\n\nversion: 2\nservers:\n  # Host\n  - host: alpha.local\n    port: 80\nlimits: { mem: 1, cpu: 2 }\n");
+
+    super::sort_keys(&mut object.value, &KeyOrder::Comparator(&|a, b| b.cmp(a)), true);
+    assert_eq!(super::format(&object), "version: 2\nservers:\n  - port: 80\n    # Host\n    host: alpha.local\n# Name\nname: api # display\nlimits: { mem: 1, cpu: 2 }\n");
+
+    super::sort_keys(&mut object.value, &KeyOrder::Listed(&["name", "version"]), false);
+    assert_eq!(super::format(&object), "# Name\nname: api # display\nversion: 2\nservers:\n  - port: 80\n    # Host\n    host: alpha.local\nlimits: { mem: 1, cpu: 2 }\n");
+
+    // Nested maps follow the properties of their own schema.
+    let (schema, _) = super::schema::load(&serde_json::json!({
+        "properties": {
+            "limits": { "properties": { "cpu": true, "mem": true } },
+            "servers": { "items": { "$ref": "#/$defs/server" } },
+            "name": true
+        },
+        "$defs": { "server": { "properties": { "host": true, "port": true } } }
+    }));
+
+    super::sort_keys(&mut object.value, &KeyOrder::Schema(&schema), true);
+    assert_eq!(super::format(&object), "limits: { cpu: 2, mem: 1 }\nservers:\n  # Host\n  - host: alpha.local\n    port: 80\n# Name\nname: api # display\nversion: 2\n");
+
+    // The comments at the top of the document stay there.
+    for (input, output) in [
+        ("# $schema: ./a.json\nb: 1\na: 2\n", "# $schema: ./a.json\na: 2\nb: 1\n"),
+        ("# $schema: ./a.json\n# B\nb: 1\na: 2\n", "# $schema: ./a.json\na: 2\n# B\nb: 1\n"),
+        ("# Header\n\n# B\nb: 1\n# A\na: 2\n", "# Header\n\n# A\na: 2\n\n# B\nb: 1\n"),
+        ("# Header\n\nb: 1\n\na: 2\n", "# Header\n\na: 2\nb: 1\n"),
+    ] {
+        let mut object = super::parse::<super::indexers::Character>(input).object.unwrap();
+        super::sort_keys(&mut object.value, &KeyOrder::Alphabetical, false);
+
+        assert_eq!(super::format(&object), output, "{:?}", input);
+    }
+}
+
+
+#[test]
+fn syntax_tree() {
+    let inputs = [
+        "# Header\n\ntitle: Example   # trailing\ndatabase:\n    # Ports\n    ports: [8000, 8001]\n    data:\n        - [delta, phi]\n        - 3.14\n\nservers:\n    # First\n    - name: alpha\n      role: frontend\n    - { \"a b\": 1, c: [x, \"y\\n\"] }\n",
+        "- a\n-  b  # c\n- x: 1\n  y: 2\n\n# end\n",
+        "a: 1\n  b: 2\nc: [1, 2\nd: 3.4.5\ne: \"x\ny\n\tz: 4",
+        "x: nullable\n",
+        "a:\n- b\n",
+        "a:\n    b: 1\n  c: 2\n",
+        "a:\n  -\nb:\n# end",
+        "",
+    ];
+
+    for input in inputs {
+        let tree = super::parse_tree::<super::indexers::Empty>(input);
+        let result = super::parse::<super::indexers::Empty>(input);
+
+        // Printing the tree reproduces the source, and the typed view and the errors are those of the parser.
+        assert_eq!(tree.to_string(), input);
+        assert_eq!(format!("{:?}", tree.errors), format!("{:?}", result.errors));
+        assert_eq!(tree.value().map(super::format), result.object.as_ref().map(super::format));
+    }
+
+    // Comments following a key with a nested value are kept.
+    let tree = super::parse_tree::<super::indexers::Character>("a:  # note\n  b: 1\n");
+    let ExpandedValue::Map { entries, .. } = &tree.value().unwrap().value else { unreachable!() };
+
+    assert!(tree.errors.is_empty());
+    assert_eq!(entries[0].comment.as_ref().map(|comment| &comment.value[..]), Some("note"));
+    assert_eq!(tree.root.children.len(), 1);
+    assert_eq!(tree.root.tokens().iter().map(|token| tree.text(&token.span)).collect::<Vec<_>>(), ["a", ":", "  ", "# note", "\n", "  ", "b", ":", " ", "1", "\n"]);
+
+    // The tokens are those read by the parser, e.g. a constant followed by extraneous characters.
+    let tree = super::parse_tree::<super::indexers::Empty>("x: nullable\n");
+    assert_eq!(tree.root.tokens().iter().map(|token| (token.kind, tree.text(&token.span))).collect::<Vec<_>>(), [
+        (super::TokenKind::Key, "x"), (super::TokenKind::Colon, ":"), (super::TokenKind::Whitespace, " "), (super::TokenKind::Scalar, "null"), (super::TokenKind::Error, "able"), (super::TokenKind::Newline, "\n"),
+    ]);
+}
+
+
+#[test]
+fn tokenize() {
+    use super::TokenKind::*;
+
+    let input = "- key: [1, \"a\"]  # note\n  map: { x: y }\n  bad: [1] ]\n";
+    let tokens = super::tokenize::<super::indexers::Character>(input);
+
+    assert_eq!(tokens.iter().map(|token| &input[token.span.0.index..token.span.1.index]).collect::<std::string::String>(), input);
+    assert_eq!(tokens.iter().map(|token| token.kind).collect::<Vec<_>>(), [
+        Dash, Whitespace, Key, Colon, Whitespace, LeftBracket, Scalar, Comma, Whitespace, String, RightBracket, Whitespace, Comment, Newline,
+        Whitespace, Key, Colon, Whitespace, LeftBrace, Whitespace, Key, Colon, Whitespace, Scalar, Whitespace, RightBrace, Newline,
+        Whitespace, Key, Colon, Whitespace, LeftBracket, Scalar, RightBracket, Whitespace, Error, Newline,
+    ]);
+
+    // Text skipped by the parser, e.g. an unterminated string, is an error token up to the end of the line.
+    let input = "a: # note\nb: \"x y\n";
+    let tokens = super::tokenize::<super::indexers::Character>(input);
+
+    assert_eq!(tokens.iter().map(|token| (token.kind, &input[token.span.0.index..token.span.1.index])).collect::<Vec<_>>(), [
+        (Key, "a"), (Colon, ":"), (Whitespace, " "), (Comment, "# note"), (Newline, "\n"),
+        (Key, "b"), (Colon, ":"), (Whitespace, " "), (Error, "\"x y"), (Newline, "\n"),
+    ]);
+}
+
+
+#[test]
+fn validate() {
+    use std::collections::HashMap;
+    use super::schema::{ConstType, Schema, SchemaType};
+
+    let numeric = |minimum, maximum| SchemaType::Numeric { integer: false, multiple_of: None, maximum, exclusive_maximum: None, minimum, exclusive_minimum: None };
+
+    let schema: Schema = SchemaType::Map {
+        additional_properties: None,
+        properties: vec![
+            ("name".to_string(), SchemaType::String { format: None, max_length: Some(8), min_length: 1, pattern: Some(regex::Regex::new("^[a-z]+$").unwrap()) }.into()),
+            ("mode".to_string(), SchemaType::Enum(vec![SchemaType::Const(ConstType::String("fast".to_string())), SchemaType::Const(ConstType::Null)]).into()),
+            ("servers".to_string(), SchemaType::Array {
+                items: Some(Box::new(SchemaType::Map {
+                    additional_properties: None,
+                    properties: vec![("port".to_string(), numeric(Some(1.0), Some(65535.0)).into())],
+                    max_properties: None,
+                    min_properties: 0,
+                    required: vec!["port".to_string()],
+                    dependent_required: HashMap::new(),
+                }.into())),
+                max_items: Some(2),
+                min_items: 0,
+                prefix_items: Vec::new(),
+                unique_items: true,
+            }.into()),
+        ],
+        max_properties: None,
+        min_properties: 0,
+        required: vec!["name".to_string()],
+        dependent_required: HashMap::from([("tls".to_string(), vec!["cert".to_string()])]),
+    }.into();
+
+    let input = "name: Api\nmode: slow\ntls: true\nservers:\n  - port: 80\n  - port: 80\n  - { port: 70000 }\n  - host: x\n";
+    let object = super::parse::<super::indexers::Character>(input).object.unwrap();
+    let violations = super::validate(&object, &schema);
+
+    assert_eq!(violations.iter().map(|violation| (violation.schema_path.as_str(), &input[violation.span.0.index..violation.span.1.index], violation.kind.to_string())).collect::<Vec<_>>(), [
+        ("/properties/name/pattern", "Api", "string does not match pattern '^[a-z]+$'".to_string()),
+        ("/properties/mode/enum", "slow", "value is not one of the allowed values".to_string()),
+        ("/dependentRequired/tls", "tls", "missing property 'cert', required by 'tls'".to_string()),
+        ("/properties/servers/maxItems", "- port: 80\n  - port: 80\n  - { port: 70000 }\n  - host: x", "list must have at most 2 items".to_string()),
+        ("/properties/servers/uniqueItems", "port: 80", "list items must be unique".to_string()),
+        ("/properties/servers/items/properties/port/maximum", "70000", "value must be at most 65535".to_string()),
+        ("/properties/servers/items/required", "host: x", "missing property 'port'".to_string()),
+    ]);
+
+    let object = super::parse::<super::indexers::Character>("name: abc\nservers: [1]\n").object.unwrap();
+    let violations = super::validate(&object, &schema);
+
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].kind.to_string(), "expected map, found integer");
+    assert_eq!(violations[0].schema_path, "/properties/servers/items/type");
+}
+
+#[test]
+fn load_schema() {
+    use super::schema::{self, ConstType, SchemaType};
+
+    let (schema, errors) = schema::load(&serde_json::json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "type": "object",
+        "properties": {
+            "name": { "type": "string", "description": "Name of the service", "contentEncoding": "base64" },
+            "port": { "type": "integer", "maximum": 65535, "default": 80 },
+            "mode": { "enum": ["fast", null] },
+            "origin": { "prefixItems": [{ "type": "number" }, { "type": "number" }], "items": false },
+            "timeout": { "type": ["integer", "null"], "minimum": "1" }
+        },
+        "additionalProperties": false,
+        "patternProperties": { "^x-": true }
+    }));
+
+    assert_eq!(errors.iter().map(|error| error.to_string()).collect::<Vec<_>>(), [
+        "/patternProperties: unsupported keyword",
+        "/properties/name/contentEncoding: unsupported keyword",
+        "/properties/timeout/minimum: expected number",
+    ]);
+
+    let SchemaType::Map { properties, .. } = &schema.kind else { panic!() };
+
+    assert_eq!(properties.iter().map(|(key, _)| key.as_str()).collect::<Vec<_>>(), ["name", "port", "mode", "origin", "timeout"]);
+    assert_eq!(properties[0].1.description.as_deref(), Some("Name of the service"));
+    assert_eq!(properties[1].1.default, Some(ConstType::Numeric(80.0)));
+    assert!(matches!(properties[3].1.kind, SchemaType::Array { ref prefix_items, .. } if prefix_items.len() == 2));
+
+    let input = "name: api\nport: 80.5\nmode: slow\norigin: [1, 2, 3]\ntimeout: null\nextra: 1\n";
+    let object = super::parse::<super::indexers::Character>(input).object.unwrap();
+    let violations = super::validate(&object, &schema);
+
+    assert_eq!(violations.iter().map(|violation| (violation.schema_path.as_str(), &input[violation.span.0.index..violation.span.1.index], violation.kind.to_string())).collect::<Vec<_>>(), [
+        ("/properties/port/type", "80.5", "expected integer, found number".to_string()),
+        ("/properties/mode/enum", "slow", "value is not one of the allowed values".to_string()),
+        ("/properties/origin/items", "3", "value is not allowed".to_string()),
+        ("/additionalProperties", "extra", "property 'extra' is not allowed".to_string()),
+    ]);
+
+    // Type arrays report the violations of the closest type, and types apply next to the allowed values.
+    let (schema, _) = schema::load(&serde_json::json!({
+        "properties": {
+            "server": { "type": ["object", "null"], "properties": { "port": { "maximum": 100 } } },
+            "name": { "type": ["string", "null"] },
+            "level": { "type": "integer", "minimum": 5, "enum": [1, 10] }
+        }
+    }));
+
+    assert_eq!(schema.to_json()["properties"]["server"]["type"], serde_json::json!(["object", "null"]));
+
+    let input = "server:
+  port: 8080
+name: 3
+level: 1
+";
+    let object = super::parse::<super::indexers::Character>(input).object.unwrap();
+    let violations = super::validate(&object, &schema);
+
+    assert_eq!(violations.iter().map(|violation| (violation.schema_path.as_str(), &input[violation.span.0.index..violation.span.1.index], violation.kind.to_string())).collect::<Vec<_>>(), [
+        ("/properties/server/properties/port/maximum", "8080", "value must be at most 100".to_string()),
+        ("/properties/name/type", "3", "expected string, found integer".to_string()),
+        ("/properties/level/minimum", "1", "value must be at least 5".to_string()),
+    ]);
+}
+
+#[test]
+fn schema_composition() {
+    use super::schema::{self, LoadErrorKind};
+
+    let directory = std::env::temp_dir().join("pcrl-schema-composition");
+    std::fs::create_dir_all(&directory).unwrap();
+
+    std::fs::write(directory.join("common.json"), serde_json::json!({
+        "$defs": {
+            "unix": { "type": "object", "properties": { "kind": { "const": "unix" }, "path": { "type": "string", "minLength": 1 } }, "required": ["kind", "path"] }
+        }
+    }).to_string()).unwrap();
+
+    std::fs::write(directory.join("schema.json"), serde_json::json!({
+        "$defs": {
+            "port": { "type": "integer", "maximum": 65535 },
+            "http": { "type": "object", "properties": { "kind": { "const": "http" }, "port": { "$ref": "#/$defs/port" } }, "required": ["kind", "port"] },
+            "tree": { "type": "object", "properties": { "name": { "type": "string" }, "children": { "type": "array", "items": { "$ref": "#/$defs/tree" } } } }
+        },
+        "type": "object",
+        "properties": {
+            "servers": { "type": "array", "items": { "oneOf": [{ "$ref": "#/$defs/http" }, { "$ref": "common.json#/$defs/unix" }] } },
+            "tree": { "$ref": "#/$defs/tree" },
+            "mode": { "not": { "const": "debug" } },
+            "tls": { "type": "object", "if": { "properties": { "enabled": { "const": true } } }, "then": { "required": ["cert"] } },
+            "id": { "anyOf": [{ "type": "integer" }, { "type": "string", "pattern": "^[a-z]+$" }] },
+            "level": { "allOf": [{ "minimum": 1 }, { "maximum": 5 }] },
+            "missing": { "$ref": "#/$defs/missing" }
+        }
+    }).to_string()).unwrap();
+
+    let (schema, errors) = schema::load_file(&directory.join("schema.json")).unwrap();
+
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].path, "/properties/missing/$ref");
+    assert!(matches!(&errors[0].kind, LoadErrorKind::UnresolvedReference(uri) if uri == "#/$defs/missing"));
+
+    let input = "servers:\n  - kind: http\n    port: 70000\n  - kind: unix\n    path: \"\"\n  - { kind: ftp }\ntree:\n  name: root\n  children:\n    - name: a\n      children:\n        - name: 1\nmode: debug\ntls:\n  enabled: true\nid: ABC\nlevel: 7\n";
+    let object = super::parse::<super::indexers::Character>(input).object.unwrap();
+    let violations = super::validate(&object, &schema);
+
+    assert_eq!(violations.iter().map(|violation| (violation.schema_path.as_str(), &input[violation.span.0.index..violation.span.1.index], violation.kind.to_string())).collect::<Vec<_>>(), [
+        ("/properties/servers/items/oneOf/0/$ref/properties/port/$ref/maximum", "70000", "value must be at most 65535".to_string()),
+        ("/properties/servers/items/oneOf/1/$ref/properties/path/minLength", "\"\"", "string must have at least 1 characters".to_string()),
+        ("/properties/servers/items/oneOf/0/$ref/required", "{ kind: ftp }", "missing property 'port'".to_string()),
+        ("/properties/servers/items/oneOf/0/$ref/properties/kind/const", "ftp", "value does not match the constant".to_string()),
+        ("/properties/tree/$ref/properties/children/items/$ref/properties/children/items/$ref/properties/name/type", "1", "expected string, found integer".to_string()),
+        ("/properties/mode/not", "debug", "value must not match the schema".to_string()),
+        ("/properties/tls/then/required", "enabled: true", "missing property 'cert'".to_string()),
+        ("/properties/id/anyOf/1/pattern", "ABC", "string does not match pattern '^[a-z]+$'".to_string()),
+        ("/properties/level/allOf/1/maximum", "7", "value must be at most 5".to_string()),
+    ]);
+}
+
+#[test]
+fn string_formats() {
+    use super::schema::{self, StringFormats};
+
+    let formats = StringFormats::new();
+
+    for (format, valid, invalid) in [
+        ("date-time", "2024-02-29T23:59:60.5+02:00", "2023-02-29T12:00:00Z"),
+        ("email", "ops.team@example.com", "ops..team@example.com"),
+        ("hostname", "api-1.example.com", "-api.example.com"),
+        ("ipv4", "192.168.0.1", "192.168.0.256"),
+        ("ipv6", "fe80::1", "fe80:::1"),
+        ("regex", "^[a-z]+$", "[a-z"),
+        ("uri", "https://example.com/a%20b?q=1#top", "example.com/a b"),
+        ("uuid", "123e4567-e89b-12d3-a456-426614174000", "123e4567-e89b-12d3-a456-42661417400"),
+    ] {
+        assert_eq!(formats.check(format, valid), Some(true), "{} {}", format, valid);
+        assert_eq!(formats.check(format, invalid), Some(false), "{} {}", format, invalid);
+    }
+
+    assert_eq!(formats.check("semver", "1.0"), None);
+
+    let (schema, errors) = schema::load(&serde_json::json!({
+        "properties": {
+            "host": { "type": "string", "format": "hostname" },
+            "version": { "type": "string", "format": "semver" }
+        }
+    }));
+
+    assert!(errors.is_empty());
+
+    let input = "host: api_1\nversion: \"1.0\"\n";
+    let object = super::parse::<super::indexers::Character>(input).object.unwrap();
+
+    let mut formats = StringFormats::new();
+    formats.register("semver", |value| value.split('.').count() == 3 && value.split('.').all(|part| part.parse::<u64>().is_ok()));
+
+    assert_eq!(super::validate(&object, &schema).len(), 1);
+    assert_eq!(super::validate_with_formats(&object, &schema, &formats).iter().map(|violation| (violation.schema_path.as_str(), &input[violation.span.0.index..violation.span.1.index], violation.kind.to_string())).collect::<Vec<_>>(), [
+        ("/properties/host/format", "api_1", "string is not a valid hostname".to_string()),
+        ("/properties/version/format", "\"1.0\"", "string is not a valid semver".to_string()),
+    ]);
+}
+
+#[test]
+fn schema_directive() {
+    use super::schema;
+
+    let directory = std::env::temp_dir().join("pcrl-schema-directive");
+    std::fs::create_dir_all(directory.join("schemas")).unwrap();
+    std::fs::write(directory.join("schemas/service.json"), r#"{ "properties": { "port": { "type": "integer" } } }"#).unwrap();
+
+    let input = "# $schema: ./schemas/service.json\n# Service\nport: http\n";
+    let object = super::parse::<super::indexers::Character>(input).object.unwrap();
+    let directive = schema::schema_directive(&object).unwrap();
+
+    assert_eq!(directive.value, "./schemas/service.json");
+    assert_eq!(&input[directive.span.0.index..directive.span.1.index], "$schema: ./schemas/service.json");
+
+    let (schema, errors) = schema::load_directive(&directory.join("service.pcrl"), &directive.value).unwrap();
+    let violations = super::validate(&object, &schema);
+
+    assert!(errors.is_empty());
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].kind.to_string(), "expected integer, found string");
+
+    assert!(schema::load_directive(&directory.join("service.pcrl"), "./missing.json").is_err());
+
+    for input in ["# Service\n# $schema: ./schemas/service.json\nport: 80\n", "\n# $schema: ./schemas/service.json\nport: 80\n", "port: 80  # $schema: ./schemas/service.json\n"] {
+        let object = super::parse::<super::indexers::Character>(input).object.unwrap();
+        assert!(schema::schema_directive(&object).is_none(), "{:?}", input);
+    }
+
+    // The directive is a comment of the first entry, which must stay at the top through edits.
+    let input = "# $schema: ./a.json\n# B\nb: 1\na: 2\n";
+    let mut object = super::parse::<super::indexers::Character>(input).object.unwrap();
+    super::sort_keys(&mut object.value, &super::KeyOrder::Alphabetical, false);
+    assert_eq!(schema::schema_directive(&object).unwrap().value, "./a.json");
+
+    let mut document = super::Document::new(input);
+    document.insert_after(&[], "b", "c", &3).unwrap();
+    document.insert(&[], "d", &4).unwrap();
+    document.remove(&[super::FindPathItem::MapKey("b")]).unwrap();
+    assert_eq!(document.text(), "# $schema: ./a.json\nc: 3\na: 2\nd: 4\n");
+    assert_eq!(schema::schema_directive(document.result().object.as_ref().unwrap()).unwrap().value, "./a.json");
+}
+
+#[test]
+fn infer_schema() {
+    let samples = [
+        "# $schema: ./service.json\n# Name of the service\nname: api\nmode: fast\nport: 80\nservers:\n  - { host: alpha, weight: 0.5 }\n",
+        "name: web\nmode: slow\n\n# Unrelated\n\nport: 8080\ntimeout: null\nservers: []\n",
+        "name: db\nmode: fast\nport: 5432\ntimeout: 30\nservers:\n  - host: beta\n",
+        "name: cache\nmode: slow\nport: 6379\nservers: []\n",
+    ].map(|input| super::parse::<super::indexers::Character>(input).object.unwrap());
+
+    let schema = super::infer_schema(samples.iter().map(|sample| &sample.value));
+
+    assert_eq!(schema.to_json(), serde_json::json!({
+        "type": "object",
+        "properties": {
+            "name": { "description": "Name of the service", "type": "string" },
+            "mode": { "enum": ["fast", "slow"] },
+            "port": { "type": "integer", "maximum": 8080, "minimum": 80 },
+            "servers": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "host": { "type": "string" },
+                        "weight": { "type": "number", "maximum": 0.5, "minimum": 0.5 }
+                    },
+                    "required": ["host"]
+                }
+            },
+            "timeout": { "type": ["integer", "null"], "maximum": 30, "minimum": 30 }
+        },
+        "required": ["name", "mode", "port", "servers"]
+    }));
+
+    for sample in &samples {
+        assert!(super::validate(sample, &schema).is_empty());
+    }
+}
+
+#[test]
+fn apply_defaults() {
+    use super::schema;
+
+    let (schema, errors) = schema::load(&serde_json::json!({
+        "$defs": {
+            "server": {
+                "properties": {
+                    "host": { "type": "string" },
+                    "port": { "type": "integer", "default": 80 },
+                    "weight": { "type": "number", "default": 0.5 }
+                },
+                "required": ["host"]
+            }
+        },
+        "properties": {
+            "name": { "type": "string", "default": "service" },
+            "mode": { "enum": ["fast", "slow"], "default": "fast" },
+            "tags": { "type": "array", "default": ["internal"] },
+            "servers": { "type": "array", "items": { "$ref": "#/$defs/server" } }
+        },
+        "allOf": [{ "properties": { "retries": { "type": "integer", "default": 3 } } }],
+        "required": ["name"]
+    }));
+
+    assert!(errors.is_empty());
+
+    let object = super::parse::<super::indexers::Character>("mode: slow\nservers:\n  - host: alpha\n  - { host: beta, port: 8080 }\n").object.unwrap();
+    let (value, defaulted) = super::apply_defaults(&object, &schema);
+
+    assert_eq!(serde_json::Value::from(value), serde_json::json!({
+        "mode": "slow",
+        "retries": 3,
+        "servers": [
+            { "host": "alpha", "port": 80, "weight": 0.5 },
+            { "host": "beta", "port": 8080, "weight": 0.5 }
+        ],
+        "tags": ["internal"]
+    }));
+
+    assert_eq!(defaulted, ["/servers/0/port", "/servers/0/weight", "/servers/1/weight", "/tags", "/retries"]);
+}
+
+#[test]
+fn find_completion() {
+    use super::{Completion, FindPathItem::*};
+    use super::schema::{self, SchemaType};
+
+    let input = "name: x\nserver:\n  host: a\n  \nmode: \ntags:\n  - \nlevel:\n    \npor\n";
+    let result = super::parse::<super::indexers::Character>(input);
+
+    let describe = |index: usize| match super::find_completion(&result, index)? {
+        Completion::Item { path, .. } => Some(format!("item {:?}", path)),
+        Completion::Key { path, .. } => Some(format!("key {:?}", path)),
+        Completion::Value { key, nested, path, .. } => Some(format!("value {} {} {:?}", key, nested, path)),
+    };
+
+    assert_eq!(describe(3), None);
+    assert_eq!(describe(28).as_deref(), Some(r#"key [MapKey("server")]"#));
+    assert_eq!(describe(35).as_deref(), Some("value mode false []"));
+    assert_eq!(describe(46).as_deref(), Some(r#"item [MapKey("tags")]"#));
+    assert_eq!(describe(56).as_deref(), Some("value level true []"));
+    assert_eq!(describe(61).as_deref(), Some("key []"));
+    assert_eq!(describe(63).as_deref(), Some("key []"));
+
+    // Half-typed lists reopened by a later line do not crash the parser.
+    for input in ["-\n  -\na\n    -", "-\n  -\na:\n    -", "-\n  - x\na\n    -", "a:\n  -\nb\n    -", "-\n  -\n-\n    -", "- a:\n    -\nb\n      -", "-\n  -\na\n    - x: 1\n"] {
+        let result = super::parse::<super::indexers::Character>(input);
+
+        assert!(!result.errors.is_empty(), "{:?}", input);
+        super::find_completion(&result, input.chars().count());
+    }
+
+    let (schema, errors) = schema::load(&serde_json::json!({
+        "$defs": { "server": { "properties": { "host": { "type": "string" } } } },
+        "properties": {
+            "server": { "$ref": "#/$defs/server" },
+            "tags": { "type": "array", "items": { "enum": ["a", "b"] } }
+        }
+    }));
+
+    assert!(errors.is_empty());
+
+    let schemas = schema.schemas_at(&[MapKey("server"), MapKey("host")]);
+    assert!(matches!(schemas[..], [schema] if matches!(schema.kind, SchemaType::String { .. })));
+
+    let schemas = schema.schemas_at(&[MapKey("tags"), ListIndex(0)]);
+    assert!(matches!(schemas[..], [schema] if matches!(&schema.kind, SchemaType::Enum(values) if values.len() == 2)));
+}
+
+#[test]
+fn path_to_string() {
+    use super::FindPathItem::*;
+
+    assert_eq!(super::path_to_string(&[]), "");
+    assert_eq!(super::path_to_string(&[MapKey("servers"), ListIndex(2), MapKey("port")]), "servers[2].port");
+    assert_eq!(super::path_to_string(&[ListIndex(0), MapKey("a b"), MapKey("c")]), r#"[0]["a b"].c"#);
+
+    let result = super::parse::<super::indexers::Empty>("# $schema: a.json\n# Name\n# of the service\nname: x\n\n# Unrelated\n\nport: 80\n");
+    let Some(ExpandedValue::Map { entries, .. }) = result.object.as_ref().map(|object| &object.value) else {
+        panic!();
+    };
+
+    assert_eq!(entries[0].context.doc_comment().as_deref(), Some("Name\nof the service"));
+    assert_eq!(entries[1].context.doc_comment(), None);
+}
+
+#[test]
+fn find() {
+    use super::FindResult;
+
+    let input = "a: { b: [1, { c: x }] }\nd: [2, 3]\n";
+    let result = super::parse::<super::indexers::Character>(input);
+
+    let describe = |index: usize| match super::find(&result, index, false)? {
+        FindResult::CompactMapKey { entry, path, .. } => Some(format!("compact key {} {}", entry.key.value, super::path_to_string(&path))),
+        FindResult::CompactValue { object, path, .. } => Some(format!("compact value {:?} {}", object.span.0.index, super::path_to_string(&path))),
+        FindResult::MapKey { entry, path, .. } => Some(format!("key {} {}", entry.key.value, super::path_to_string(&path))),
+        FindResult::Value { object, path, .. } => Some(format!("value {:?} {}", object.span.0.index, super::path_to_string(&path))),
+    };
+
+    assert_eq!(describe(0).as_deref(), Some("key a "));
+    assert_eq!(describe(5).as_deref(), Some("compact key b a"));
+    assert_eq!(describe(9).as_deref(), Some("compact value 9 a.b[0]"));
+    assert_eq!(describe(14).as_deref(), Some("compact key c a.b[1]"));
+    assert_eq!(describe(17).as_deref(), Some("compact value 17 a.b[1].c"));
+    assert_eq!(describe(10), None);
+    assert_eq!(describe(31).as_deref(), Some("compact value 31 d[1]"));
+
+    assert!(super::find(&super::parse::<super::indexers::Character>(""), 0, true).is_none());
+
+    // 1 → [1, { c: x }] → b: [..] → { b: .. } → a: { .. } → root
+    let ancestors = super::find(&result, 9, false).unwrap().ancestors().iter().map(|span| (span.0.index, span.1.index)).collect::<Vec<_>>();
+    assert_eq!(ancestors, [(8, 21), (5, 21), (3, 23), (0, 23), (0, 33)]);
+
+    // Values of expanded map entries come with their entry, e.g. for its doc comment
+    let result = super::parse::<super::indexers::Character>("# Port\nport: 80\nhosts:\n  - x\n");
+
+    let doc_comment = |index: usize| match super::find(&result, index, false) {
+        Some(FindResult::Value { entry, .. }) => entry.map(|entry| entry.context.doc_comment()),
+        _ => panic!(),
+    };
+
+    assert_eq!(doc_comment(13), Some(Some("Port".to_string())));
+    assert_eq!(doc_comment(27), None);
+}
+
+#[test]
+fn query() {
+    use super::{FindPathItem::*, NodeRef, QueryErrorKind};
+
+    let input = "name: app\ndatabase:\n  ports: [8000, 8001]\nservers:\n  - name: alpha\n    role: frontend\n    port: 80\n  - { name: beta, role: backend, port: 8080 }\n  - name: gamma\n    role: backend\n    port: 8081\n";
+    let result = super::parse::<super::indexers::Character>(input);
+
+    let text = |query: &str| super::query(&result, query).unwrap().iter().map(|node| &input[node.span.0.index..node.span.1.index]).collect::<Vec<_>>();
+
+    assert_eq!(text("database.ports[0]"), ["8000"]);
+    assert_eq!(text(".database.ports[*]"), ["8000", "8001"]);
+    assert_eq!(text("servers.*.port"), ["80", "8080", "8081"]);
+    assert_eq!(text("..name"), ["app", "alpha", "beta", "gamma"]);
+    assert_eq!(text(r#"servers[?role == "backend"].name"#), ["beta", "gamma"]);
+    assert_eq!(text("servers[?role != backend].name"), ["alpha"]);
+    assert_eq!(text("servers[?port >= 8081].name"), ["gamma"]);
+    assert_eq!(text("servers[?missing]"), Vec::<&str>::new());
+
+    let node = super::get(&result, &[MapKey("servers"), ListIndex(1), MapKey("role")]).unwrap();
+    assert_eq!(&input[node.key_span.unwrap().0.index..node.span.1.index], "role: backend");
+    assert!(matches!(node.value, NodeRef::Compact(CompactValue::String(value)) if value == "backend"));
+    assert!(super::get(&result, &[MapKey("servers"), ListIndex(3)]).is_none());
+
+    let error = super::query(&result, "servers[0").unwrap_err();
+    assert!(matches!(error.kind, QueryErrorKind::MissingBracketClose));
+    assert_eq!(error.offset, 9);
+}
+
+#[test]
+fn visitor() {
+    use super::{FindPathItem, Span, Visitor, VisitorMut, WithSpan};
+    use super::visit::walk_compact_value_mut;
+
+    struct Collector {
+        comments: Vec<String>,
+        scalars: Vec<String>,
+    }
+
+    impl<'a, Index: CharIndex> Visitor<'a, Index> for Collector {
+        fn visit_compact_value(&mut self, value: &'a CompactValue<Index>, span: Span<Index>, path: &[FindPathItem<'a>]) {
+            if let CompactValue::Integer(value) = value {
+                self.scalars.push(format!("{}={}", super::path_to_string(path), value));
+            }
+
+            super::visit::walk_compact_value(self, value, span, path);
+        }
+
+        fn visit_standalone_comment(&mut self, comment: &'a StandaloneComment<Index>, path: &[FindPathItem<'a>]) {
+            self.comments.push(format!("{}: {}", super::path_to_string(path), comment.contents.value));
+        }
+
+        fn visit_trailing_comment(&mut self, comment: &'a WithSpan<String, Index>, path: &[FindPathItem<'a>]) {
+            self.comments.push(format!("{}: {}", super::path_to_string(path), comment.value));
+        }
+
+        fn visit_string_line_comment(&mut self, comment: &'a str, path: &[FindPathItem<'a>]) {
+            self.comments.push(format!("{}: {}", super::path_to_string(path), comment));
+        }
+    }
+
+    struct Incrementer;
+
+    impl<Index: CharIndex> VisitorMut<Index> for Incrementer {
+        fn visit_compact_value_mut(&mut self, value: &mut CompactValue<Index>, span: Span<Index>, path: &[FindPathItem<'_>]) {
+            if let CompactValue::Integer(value) = value {
+                *value += 1;
+            }
+
+            walk_compact_value_mut(self, value, span, path);
+        }
+
+        fn visit_string_line_comment_mut(&mut self, comment: &mut String, _path: &[FindPathItem<'_>]) {
+            comment.push('!');
+        }
+    }
+
+    let mut object = super::parse::<super::indexers::Empty>("a: 1 # one\nb:\n  # Ports\n  - [2, { c: 3 }]\n").object.unwrap();
+
+    let mut collector = Collector { comments: Vec::new(), scalars: Vec::new() };
+    collector.visit_value(&object.value, object.span, &[]);
+
+    assert_eq!(collector.scalars, ["a=1", "b[0][0]=2", "b[0][1].c=3"]);
+    assert_eq!(collector.comments, ["a: one", "b[0]: Ports"]);
+
+    Incrementer.visit_value_mut(&mut object.value, object.span, &[]);
+    assert_eq!(json_expanded(&object.value), r#"{ "a": 2, "b": [[3, { "c": 4 }]] }"#);
+
+    // The parser does not produce multiline strings yet
+    let line = |text: &str, comment: Option<&str>| super::MultilineStringLine {
+        comment: comment.map(str::to_string),
+        context: super::Context { comments: Vec::new(), gap: 0, indent: 0 },
+        text: text.to_string(),
+    };
+
+    let mut string = super::ExpandedValue::<()>::String {
+        lines: vec![line("x", Some("first")), line("y", None)],
+        string: "x\ny".to_string(),
+    };
+
+    let span = Span::point(&super::Marker { byte_offset: 0, index: () });
+    Incrementer.visit_value_mut(&mut string, span, &[]);
+
+    let mut collector = Collector { comments: Vec::new(), scalars: Vec::new() };
+    collector.visit_value(&string, span, &[]);
+
+    assert_eq!(collector.comments, [": first!"]);
+}
+
+#[test]
+fn diagnostic() {
+    use super::{Diagnostic, RenderOptions, Span};
+
+    let input = "name: app\na: 1\nport: 80\ndebug: true\na: 2\ntags:\n  - x\n  - y\n";
+    let result = super::parse::<super::indexers::Empty>(input);
+
+    let ExpandedValue::Map { entries, .. } = &result.object.as_ref().unwrap().value else {
+        panic!();
+    };
+
+    let diagnostic = Diagnostic::error("duplicate key 'a'")
+        .with_code("E001")
+        .with_primary(entries[4].key.span, "duplicate key")
+        .with_secondary(entries[1].key.span, "first defined here")
+        .with_help("remove one of the entries");
+
+    assert_eq!(diagnostic.render(input, &RenderOptions::default()), [
+        "error[E001]: duplicate key 'a'",
+        " --> 5:1",
+        "  |",
+        "1 | name: app",
+        "2 | a: 1",
+        "  | - first defined here",
+        "3 | port: 80",
+        "4 | debug: true",
+        "5 | a: 2",
+        "  | ^ duplicate key",
+        "6 | tags:",
+        "  |",
+        "  = help: remove one of the entries",
+        "",
+    ].join("\n"));
+
+    let tags = Span(entries[5].key.span.0, entries[5].value.span.1);
+    let diagnostic = Diagnostic::warning("unused").with_primary(tags, "never read").with_note("tags are ignored");
+
+    let options = RenderOptions { context_lines: 0, path: Some("app.pcrl".to_string()), ..Default::default() };
+
+    assert_eq!(diagnostic.render(input, &options), [
+        "warning: unused",
+        " --> app.pcrl:6:1",
+        "  |",
+        "6 | tags:",
+        "  | ^^^^^",
+        "7 |   - x",
+        "  | ^^^^^",
+        "8 |   - y",
+        "  | ^^^^^ never read",
+        "  |",
+        "  = note: tags are ignored",
+        "",
+    ].join("\n"));
+
+    assert!(diagnostic.render(input, &RenderOptions { color: true, ..Default::default() }).starts_with("\x1b[1;33mwarning\x1b[0m"));
+}
+
+#[test]
+fn display_width() {
+    use super::{Diagnostic, RenderOptions, Span};
+    use crate::iterator::Marker;
+
+    let span = |start: usize, end: usize| Span(Marker { byte_offset: start, index: () }, Marker { byte_offset: end, index: () });
+
+    // A tab, three wide characters of three bytes and a combining mark
+    let input = "a:\t\"日本語\" # e\u{0301}x\nb: 2\n";
+    let diagnostic = Diagnostic::error("wide").with_primary(span(4, 13), "here").with_secondary(span(17, 20), "mark");
+
+    assert_eq!(diagnostic.render(input, &RenderOptions { context_lines: 0, ..Default::default() }), [
+        "error: wide",
+        " --> 1:5",
+        "  |",
+        "1 | a:  \"日本語\" # e\u{0301}x",
+        "  |      ^^^^^^ here",
+        "  |                - mark",
+        "",
+    ].join("\n"));
+
+    assert_eq!(diagnostic.render(input, &RenderOptions { context_lines: 0, tab_width: 8, ..Default::default() }).lines().nth(4), Some("  |          ^^^^^^ here"));
+
+    let mut output = Vec::new();
+    span(4, 23).format(input, &mut output).unwrap();
+
+    assert_eq!(String::from_utf8(output).unwrap(), [
+        " --> 1:5",
+        "  |",
+        "1 | a:  \"日本語\" # e\u{0301}x",
+        "  |      ^^^^^^^^^^^^",
+        "2 | b: 2",
+        "  | ^",
+        "",
+    ].join("\n"));
+
+    // CRLF line breaks are not part of the lines, including at the end of a span.
+    let input = "a: 1\r\nb: x\r\n";
+    let diagnostic = Diagnostic::error("crlf").with_primary(span(6, 12), "here");
+
+    assert_eq!(diagnostic.render(input, &RenderOptions { context_lines: 0, ..Default::default() }), [
+        "error: crlf",
+        " --> 2:1",
+        "  |",
+        "2 | b: x",
+        "  | ^^^^ here",
+        "",
+    ].join("\n"));
+
+    // Emoji from the symbol blocks and the supplementary planes, and ZWJ sequences, which take two cells as a whole
+    assert_eq!(super::width::display_width("☕⚡", 4), 4);
+    assert_eq!(super::width::display_width("🀄🫠", 4), 4);
+    assert_eq!(super::width::display_width("👩\u{200D}👩\u{200D}👧", 4), 2);
+    assert_eq!(super::width::display_width("a\t☕\tb", 4), 9);
+    assert_eq!(super::width::expand_tabs("☕\tb", 4), "☕  b");
+}
+
+#[test]
+fn line_index() {
+    use super::{ColumnEncoding::*, LineIndex, indexers::LineColumnIndex};
+
+    let position = |line: usize, column: usize| LineColumnIndex { column, line };
+
+    // "é" takes 2 bytes, "日" 3 bytes and "😀" 4 bytes or 2 UTF-16 code units
+    let contents = "a: é\r\nb: 日😀x\rc\n";
+    let index = LineIndex::new(contents);
+
+    assert_eq!(index.line_count(), 4);
+    assert_eq!(index.line_range(1), Some(7..18));
+    assert_eq!(index.line_range(3), Some(21..21));
+    assert_eq!(index.line_range(4), None);
+
+    let x = contents.find('x').unwrap();
+
+    assert_eq!(index.position(x, Utf8), position(1, 10));
+    assert_eq!(index.position(x, Utf16), position(1, 6));
+    assert_eq!(index.position(x, Utf32), position(1, 5));
+    assert_eq!(index.position(contents.find('c').unwrap(), Utf16), position(2, 0));
+
+    // Inside a character, inside "\r\n" and past the end
+    assert_eq!(index.position(x - 1, Utf16), position(1, 4));
+    assert_eq!(index.position(6, Utf8), position(0, 5));
+    assert_eq!(index.position(100, Utf8), position(3, 0));
+
+    for encoding in [Utf8, Utf16, Utf32] {
+        for (byte_offset, _) in contents.char_indices().filter(|(_, ch)| *ch != '\n') {
+            assert_eq!(index.offset(index.position(byte_offset, encoding), encoding), Some(byte_offset));
+        }
+    }
+
+    assert_eq!(index.offset(position(1, 5), Utf16), Some(x - 4));
+    assert_eq!(index.offset(position(1, 100), Utf32), Some(18));
+    assert_eq!(index.offset(position(4, 0), Utf8), None);
+
+    let result = super::parse::<super::indexers::Empty>("a:\n  b: ü\n");
+    let ExpandedValue::Map { entries, .. } = &result.object.as_ref().unwrap().value else {
+        panic!();
+    };
+
+    let span = LineIndex::new("a:\n  b: ü\n").span(entries[0].value.span, Utf16);
+    assert_eq!((span.0.index, span.1.index), (position(1, 2), position(1, 6)));
+}
@@ -0,0 +1,256 @@
+use crate::find::FindPathItem;
+use crate::iterator::CharIndex;
+use crate::result::*;
+use crate::span::{Span, WithSpan};
+
+
+// Traversal of the value tree, starting with visit_value() on the root object. Implementors override the
+// hooks they are interested in and call the corresponding walk function from an overridden hook to keep
+// descending. Hooks receive the path of the value from the root, e.g. [MapKey("servers"), ListIndex(0)]
+// for the first server.
+pub trait Visitor<'a, Index: CharIndex> {
+    fn visit_value(&mut self, value: &'a ExpandedValue<Index>, span: Span<Index>, path: &[FindPathItem<'a>]) {
+        walk_value(self, value, span, path);
+    }
+
+    // Also called for compact values nested in expanded ones, e.g. scalars
+    fn visit_compact_value(&mut self, value: &'a CompactValue<Index>, span: Span<Index>, path: &[FindPathItem<'a>]) {
+        walk_compact_value(self, value, span, path);
+    }
+
+    // The path includes the index of the item.
+    fn visit_list_item(&mut self, item: &'a ExpandedListItem<Index>, path: &[FindPathItem<'a>]) {
+        walk_list_item(self, item, path);
+    }
+
+    // The path includes the key of the entry.
+    fn visit_map_entry(&mut self, entry: &'a ExpandedMapEntry<Index>, path: &[FindPathItem<'a>]) {
+        walk_map_entry(self, entry, path);
+    }
+
+    fn visit_compact_map_entry(&mut self, entry: &'a CompactMapEntry<Index>, path: &[FindPathItem<'a>]) {
+        walk_compact_map_entry(self, entry, path);
+    }
+
+    fn visit_key(&mut self, _key: &'a WithSpan<String, Index>, _path: &[FindPathItem<'a>]) {}
+
+    // The comments and blank lines above an item, an entry or a line of a multiline string
+    fn visit_context(&mut self, context: &'a Context<Index>, path: &[FindPathItem<'a>]) {
+        walk_context(self, context, path);
+    }
+
+    fn visit_standalone_comment(&mut self, _comment: &'a StandaloneComment<Index>, _path: &[FindPathItem<'a>]) {}
+
+    // The comment at the end of the line of an item or an entry
+    fn visit_trailing_comment(&mut self, _comment: &'a WithSpan<String, Index>, _path: &[FindPathItem<'a>]) {}
+
+    fn visit_string_line(&mut self, line: &'a MultilineStringLine<Index>, path: &[FindPathItem<'a>]) {
+        walk_string_line(self, line, path);
+    }
+
+    // The comment at the end of a line of a multiline string, which has no span
+    fn visit_string_line_comment(&mut self, _comment: &'a str, _path: &[FindPathItem<'a>]) {}
+}
+
+fn child_path<'a>(path: &[FindPathItem<'a>], path_item: FindPathItem<'a>) -> Vec<FindPathItem<'a>> {
+    let mut child_path = path.to_vec();
+    child_path.push(path_item);
+    child_path
+}
+
+pub fn walk_value<'a, Index: CharIndex, V: Visitor<'a, Index> + ?Sized>(visitor: &mut V, value: &'a ExpandedValue<Index>, span: Span<Index>, path: &[FindPathItem<'a>]) {
+    match value {
+        ExpandedValue::Compact(value) => visitor.visit_compact_value(value, span, path),
+        ExpandedValue::List { items, .. } => {
+            for (index, item) in items.iter().enumerate() {
+                visitor.visit_list_item(item, &child_path(path, FindPathItem::ListIndex(index)));
+            }
+        },
+        ExpandedValue::Map { entries, .. } => {
+            for entry in entries {
+                visitor.visit_map_entry(entry, &child_path(path, FindPathItem::MapKey(&entry.key.value)));
+            }
+        },
+        ExpandedValue::String { lines, .. } => {
+            for line in lines {
+                visitor.visit_string_line(line, path);
+            }
+        },
+    }
+}
+
+pub fn walk_compact_value<'a, Index: CharIndex, V: Visitor<'a, Index> + ?Sized>(visitor: &mut V, value: &'a CompactValue<Index>, _span: Span<Index>, path: &[FindPathItem<'a>]) {
+    match value {
+        CompactValue::List { items, .. } => {
+            for (index, item) in items.iter().enumerate() {
+                visitor.visit_compact_value(&item.value, item.span, &child_path(path, FindPathItem::ListIndex(index)));
+            }
+        },
+        CompactValue::Map { entries, .. } => {
+            for entry in entries {
+                visitor.visit_compact_map_entry(entry, &child_path(path, FindPathItem::MapKey(&entry.key.value)));
+            }
+        },
+        _ => (),
+    }
+}
+
+pub fn walk_list_item<'a, Index: CharIndex, V: Visitor<'a, Index> + ?Sized>(visitor: &mut V, item: &'a ExpandedListItem<Index>, path: &[FindPathItem<'a>]) {
+    visitor.visit_context(&item.context, path);
+
+    if let Some(comment) = &item.comment {
+        visitor.visit_trailing_comment(comment, path);
+    }
+
+    visitor.visit_value(&item.value.value, item.value.span, path);
+}
+
+pub fn walk_map_entry<'a, Index: CharIndex, V: Visitor<'a, Index> + ?Sized>(visitor: &mut V, entry: &'a ExpandedMapEntry<Index>, path: &[FindPathItem<'a>]) {
+    visitor.visit_context(&entry.context, path);
+    visitor.visit_key(&entry.key, path);
+
+    if let Some(comment) = &entry.comment {
+        visitor.visit_trailing_comment(comment, path);
+    }
+
+    visitor.visit_value(&entry.value.value, entry.value.span, path);
+}
+
+pub fn walk_compact_map_entry<'a, Index: CharIndex, V: Visitor<'a, Index> + ?Sized>(visitor: &mut V, entry: &'a CompactMapEntry<Index>, path: &[FindPathItem<'a>]) {
+    visitor.visit_key(&entry.key, path);
+    visitor.visit_compact_value(&entry.value.value, entry.value.span, path);
+}
+
+pub fn walk_context<'a, Index: CharIndex, V: Visitor<'a, Index> + ?Sized>(visitor: &mut V, context: &'a Context<Index>, path: &[FindPathItem<'a>]) {
+    for comment in &context.comments {
+        visitor.visit_standalone_comment(comment, path);
+    }
+}
+
+pub fn walk_string_line<'a, Index: CharIndex, V: Visitor<'a, Index> + ?Sized>(visitor: &mut V, line: &'a MultilineStringLine<Index>, path: &[FindPathItem<'a>]) {
+    visitor.visit_context(&line.context, path);
+
+    if let Some(comment) = &line.comment {
+        visitor.visit_string_line_comment(comment, path);
+    }
+}
+
+
+// As Visitor, for modifying the tree in place. The paths borrow copies of the keys, as the keys themselves
+// may be modified.
+pub trait VisitorMut<Index: CharIndex> {
+    fn visit_value_mut(&mut self, value: &mut ExpandedValue<Index>, span: Span<Index>, path: &[FindPathItem<'_>]) {
+        walk_value_mut(self, value, span, path);
+    }
+
+    fn visit_compact_value_mut(&mut self, value: &mut CompactValue<Index>, span: Span<Index>, path: &[FindPathItem<'_>]) {
+        walk_compact_value_mut(self, value, span, path);
+    }
+
+    fn visit_list_item_mut(&mut self, item: &mut ExpandedListItem<Index>, path: &[FindPathItem<'_>]) {
+        walk_list_item_mut(self, item, path);
+    }
+
+    fn visit_map_entry_mut(&mut self, entry: &mut ExpandedMapEntry<Index>, path: &[FindPathItem<'_>]) {
+        walk_map_entry_mut(self, entry, path);
+    }
+
+    fn visit_compact_map_entry_mut(&mut self, entry: &mut CompactMapEntry<Index>, path: &[FindPathItem<'_>]) {
+        walk_compact_map_entry_mut(self, entry, path);
+    }
+
+    fn visit_key_mut(&mut self, _key: &mut WithSpan<String, Index>, _path: &[FindPathItem<'_>]) {}
+
+    fn visit_context_mut(&mut self, context: &mut Context<Index>, path: &[FindPathItem<'_>]) {
+        walk_context_mut(self, context, path);
+    }
+
+    fn visit_standalone_comment_mut(&mut self, _comment: &mut StandaloneComment<Index>, _path: &[FindPathItem<'_>]) {}
+
+    fn visit_trailing_comment_mut(&mut self, _comment: &mut WithSpan<String, Index>, _path: &[FindPathItem<'_>]) {}
+
+    fn visit_string_line_mut(&mut self, line: &mut MultilineStringLine<Index>, path: &[FindPathItem<'_>]) {
+        walk_string_line_mut(self, line, path);
+    }
+
+    fn visit_string_line_comment_mut(&mut self, _comment: &mut String, _path: &[FindPathItem<'_>]) {}
+}
+
+pub fn walk_value_mut<Index: CharIndex, V: VisitorMut<Index> + ?Sized>(visitor: &mut V, value: &mut ExpandedValue<Index>, span: Span<Index>, path: &[FindPathItem<'_>]) {
+    match value {
+        ExpandedValue::Compact(value) => visitor.visit_compact_value_mut(value, span, path),
+        ExpandedValue::List { items, .. } => {
+            for (index, item) in items.iter_mut().enumerate() {
+                visitor.visit_list_item_mut(item, &child_path(path, FindPathItem::ListIndex(index)));
+            }
+        },
+        ExpandedValue::Map { entries, .. } => {
+            for entry in entries {
+                let key = entry.key.value.clone();
+                visitor.visit_map_entry_mut(entry, &child_path(path, FindPathItem::MapKey(&key)));
+            }
+        },
+        ExpandedValue::String { lines, .. } => {
+            for line in lines {
+                visitor.visit_string_line_mut(line, path);
+            }
+        },
+    }
+}
+
+pub fn walk_compact_value_mut<Index: CharIndex, V: VisitorMut<Index> + ?Sized>(visitor: &mut V, value: &mut CompactValue<Index>, _span: Span<Index>, path: &[FindPathItem<'_>]) {
+    match value {
+        CompactValue::List { items, .. } => {
+            for (index, item) in items.iter_mut().enumerate() {
+                visitor.visit_compact_value_mut(&mut item.value, item.span, &child_path(path, FindPathItem::ListIndex(index)));
+            }
+        },
+        CompactValue::Map { entries, .. } => {
+            for entry in entries {
+                let key = entry.key.value.clone();
+                visitor.visit_compact_map_entry_mut(entry, &child_path(path, FindPathItem::MapKey(&key)));
+            }
+        },
+        _ => (),
+    }
+}
+
+pub fn walk_list_item_mut<Index: CharIndex, V: VisitorMut<Index> + ?Sized>(visitor: &mut V, item: &mut ExpandedListItem<Index>, path: &[FindPathItem<'_>]) {
+    visitor.visit_context_mut(&mut item.context, path);
+
+    if let Some(comment) = &mut item.comment {
+        visitor.visit_trailing_comment_mut(comment, path);
+    }
+
+    visitor.visit_value_mut(&mut item.value.value, item.value.span, path);
+}
+
+pub fn walk_map_entry_mut<Index: CharIndex, V: VisitorMut<Index> + ?Sized>(visitor: &mut V, entry: &mut ExpandedMapEntry<Index>, path: &[FindPathItem<'_>]) {
+    visitor.visit_context_mut(&mut entry.context, path);
+    visitor.visit_key_mut(&mut entry.key, path);
+
+    if let Some(comment) = &mut entry.comment {
+        visitor.visit_trailing_comment_mut(comment, path);
+    }
+
+    visitor.visit_value_mut(&mut entry.value.value, entry.value.span, path);
+}
+
+pub fn walk_compact_map_entry_mut<Index: CharIndex, V: VisitorMut<Index> + ?Sized>(visitor: &mut V, entry: &mut CompactMapEntry<Index>, path: &[FindPathItem<'_>]) {
+    visitor.visit_key_mut(&mut entry.key, path);
+    visitor.visit_compact_value_mut(&mut entry.value.value, entry.value.span, path);
+}
+
+pub fn walk_context_mut<Index: CharIndex, V: VisitorMut<Index> + ?Sized>(visitor: &mut V, context: &mut Context<Index>, path: &[FindPathItem<'_>]) {
+    for comment in &mut context.comments {
+        visitor.visit_standalone_comment_mut(comment, path);
+    }
+}
+
+pub fn walk_string_line_mut<Index: CharIndex, V: VisitorMut<Index> + ?Sized>(visitor: &mut V, line: &mut MultilineStringLine<Index>, path: &[FindPathItem<'_>]) {
+    visitor.visit_context_mut(&mut line.context, path);
+
+    if let Some(comment) = &mut line.comment {
+        visitor.visit_string_line_comment_mut(comment, path);
+    }
+}
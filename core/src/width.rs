@@ -0,0 +1,29 @@
+use unicode_width::UnicodeWidthStr;
+
+
+// Width of the text at the start of a line, with tabs advancing to the next multiple of the tab width. The text
+// between tabs is measured as a whole, so that e.g. emoji ZWJ sequences take two cells.
+pub(crate) fn display_width(text: &str, tab_width: usize) -> usize {
+    let tab_width = tab_width.max(1);
+    let mut segments = text.split('\t');
+    let first_width = segments.next().map(UnicodeWidthStr::width).unwrap_or(0);
+
+    segments.fold(first_width, |width, segment| (width / tab_width + 1) * tab_width + segment.width())
+}
+
+// Replaces the tabs of a line with spaces, consistently with display_width().
+pub(crate) fn expand_tabs(line: &str, tab_width: usize) -> String {
+    let tab_width = tab_width.max(1);
+    let mut output = String::new();
+
+    for (index, segment) in line.split('\t').enumerate() {
+        if index > 0 {
+            let width = display_width(&output, tab_width);
+            output.push_str(&" ".repeat(tab_width - width % tab_width));
+        }
+
+        output.push_str(segment);
+    }
+
+    output
+}
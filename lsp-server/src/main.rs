@@ -1,46 +1,334 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
 use dashmap::DashMap;
+use pcrl::indexers::LineColumnIndex;
+use pcrl::schema::{ConstType, Schema, SchemaType};
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService, Server};
 
+fn span_range(span: &pcrl::Span<LineColumnIndex>) -> Range {
+    Range {
+        end: Position {
+            character: span.1.index.column as u32,
+            line: span.1.index.line as u32,
+        },
+        start: Position {
+            character: span.0.index.column as u32,
+            line: span.0.index.line as u32,
+        },
+    }
+}
+
+fn key_text(key: &str) -> String {
+    let mut text = String::new();
+    pcrl::write_key(key, &mut text);
+    text
+}
+
+// Escapes the characters with a meaning in snippets.
+fn escape_snippet(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('$', "\\$").replace('}', "\\}")
+}
+
+// Suggests the properties of the maps described by the schemas, except those already present. Maps with
+// required properties are inserted as a template listing them, indented by the document's indent width.
+fn property_completions(schemas: &[&Schema], existing_keys: &[&str], indent_width: usize) -> Vec<CompletionItem> {
+    let mut items = Vec::<CompletionItem>::new();
+
+    for kind in schemas.iter().flat_map(|schema| schema.kinds()) {
+        let SchemaType::Map { properties, required, .. } = kind else {
+            continue;
+        };
+
+        for (key, schema) in properties {
+            if existing_keys.contains(&key.as_str()) || items.iter().any(|item| item.label == *key) {
+                continue;
+            }
+
+            let variants = schema.variants();
+
+            let documentation = variants.iter().find_map(|variant| variant.description.as_ref()).map(|description| {
+                Documentation::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: description.clone(),
+                })
+            });
+
+            let nested_required = variants.iter().flat_map(|variant| variant.kinds()).find_map(|kind| match kind {
+                SchemaType::Map { required, .. } if !required.is_empty() => Some(required),
+                _ => None,
+            });
+
+            let (insert_text, insert_text_format) = match nested_required {
+                Some(nested_required) => {
+                    let lines = nested_required
+                        .iter()
+                        .enumerate()
+                        .map(|(index, nested_key)| format!("\n{}{}: ${}", " ".repeat(indent_width), escape_snippet(&key_text(nested_key)), index + 1))
+                        .collect::<String>();
+
+                    (format!("{}:{}", escape_snippet(&key_text(key)), lines), InsertTextFormat::SNIPPET)
+                },
+                None => (format!("{}: ", key_text(key)), InsertTextFormat::PLAIN_TEXT),
+            };
+
+            items.push(CompletionItem {
+                detail: required.contains(key).then(|| "required".to_string()),
+                documentation,
+                insert_text: Some(insert_text),
+                insert_text_format: Some(insert_text_format),
+                insert_text_mode: Some(InsertTextMode::ADJUST_INDENTATION),
+                kind: Some(CompletionItemKind::PROPERTY),
+                label: key.clone(),
+                sort_text: Some(format!("{:04}", items.len())),
+                ..Default::default()
+            });
+        }
+    }
+
+    items
+}
+
+// Suggests the constants, enum values and booleans allowed by the schemas.
+fn value_completions(schemas: &[&Schema]) -> Vec<CompletionItem> {
+    let mut values = Vec::new();
+
+    for kind in schemas.iter().flat_map(|schema| schema.kinds()) {
+        match kind {
+            SchemaType::Boolean => values.extend([(ConstType::Boolean(true), CompletionItemKind::KEYWORD), (ConstType::Boolean(false), CompletionItemKind::KEYWORD)]),
+            SchemaType::Const(value) => values.push((value.clone(), CompletionItemKind::ENUM_MEMBER)),
+            SchemaType::Enum(variants) => values.extend(variants.iter().filter_map(|variant| match variant {
+                SchemaType::Const(value) => Some((value.clone(), CompletionItemKind::ENUM_MEMBER)),
+                _ => None,
+            })),
+            SchemaType::Null => values.push((ConstType::Null, CompletionItemKind::KEYWORD)),
+            _ => (),
+        }
+    }
+
+    let mut items = Vec::<CompletionItem>::new();
+
+    for (value, kind) in values {
+        let Some(label) = value.to_compact_string() else {
+            continue;
+        };
+
+        if !items.iter().any(|item| item.label == label) {
+            items.push(CompletionItem {
+                kind: Some(kind),
+                label,
+                sort_text: Some(format!("{:04}", items.len())),
+                ..Default::default()
+            });
+        }
+    }
+
+    items
+}
+
+// Canonicalizes the path so that different spellings share a cache entry, or keeps it as is if the file is missing.
+fn resolve_path(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn map_keys(map: &pcrl::ExpandedValue<LineColumnIndex>) -> Vec<&str> {
+    match map {
+        pcrl::ExpandedValue::Map { entries, .. } => entries.iter().map(|entry| entry.key.value.as_str()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+// Keywords shown as constraints in hovers, as written by Schema::to_json()
+const CONSTRAINT_KEYWORDS: &[&str] = &[
+    "const", "enum", "format", "pattern", "minLength", "maxLength", "minimum", "exclusiveMinimum", "maximum",
+    "exclusiveMaximum", "multipleOf", "minItems", "maxItems", "uniqueItems", "required", "minProperties", "maxProperties",
+];
+
+fn compact_type_name(value: &pcrl::CompactValue<LineColumnIndex>) -> &'static str {
+    match value {
+        pcrl::CompactValue::Bool(_) => "boolean",
+        pcrl::CompactValue::Float(_) => "number",
+        pcrl::CompactValue::Integer(_) => "integer",
+        pcrl::CompactValue::List { .. } => "list",
+        pcrl::CompactValue::Map { .. } => "map",
+        pcrl::CompactValue::Null => "null",
+        pcrl::CompactValue::String(_) => "string",
+    }
+}
+
+fn type_name(value: &pcrl::ExpandedValue<LineColumnIndex>) -> &'static str {
+    match value {
+        pcrl::ExpandedValue::Compact(value) => compact_type_name(value),
+        pcrl::ExpandedValue::List { .. } => "list",
+        pcrl::ExpandedValue::Map { .. } => "map",
+        pcrl::ExpandedValue::String { .. } => "string",
+    }
+}
+
+// Describes the value at the path: its path and type, then the annotations and constraints of the schemas
+// which may describe it, then the doc comment of its key.
+fn hover_markdown(path: &[pcrl::FindPathItem<'_>], type_name: &str, schema: Option<&Schema>, doc_comment: Option<String>) -> String {
+    let path_string = match pcrl::path_to_string(path) {
+        path_string if path_string.is_empty() => "(root)".to_string(),
+        path_string => path_string,
+    };
+
+    let mut sections = vec![format!("`{}`: {}", path_string, type_name)];
+
+    for variant in schema.map(|schema| schema.schemas_at(path)).unwrap_or_default() {
+        if let Some(description) = &variant.description {
+            if !sections.contains(description) {
+                sections.push(description.clone());
+            }
+        }
+
+        let mut lines = Vec::new();
+
+        if let Some(default) = &variant.default {
+            lines.push(format!("Default: `{}`", default.to_json()));
+        }
+
+        if let Some(keywords) = variant.to_json().as_object() {
+            lines.extend(
+                CONSTRAINT_KEYWORDS
+                    .iter()
+                    .filter_map(|keyword| Some(format!("`{}`: `{}`", keyword, keywords.get(*keyword)?)))
+            );
+        }
+
+        if !lines.is_empty() {
+            sections.push(lines.join("  \n"));
+        }
+    }
+
+    if let Some(doc_comment) = doc_comment {
+        sections.push(format!("---\n\n{}", doc_comment));
+    }
+
+    sections.join("\n\n")
+}
+
+
+#[derive(Debug)]
+struct LoadedSchema {
+    // Messages of the errors found while loading, reported as warnings
+    errors: Vec<String>,
+    modified: SystemTime,
+    schema: Arc<Schema>,
+}
+
 #[derive(Debug)]
 struct Backend {
     client: Client,
-    results: DashMap<Url, pcrl::ParseResult<pcrl::indexers::LineColumnIndex>>,
+    // Schema files by resolved path, reloaded when their modification time changes
+    loaded_schemas: DashMap<PathBuf, LoadedSchema>,
+    results: DashMap<Url, pcrl::ParseResult<LineColumnIndex>>,
+    // Schemas of the documents with a `# $schema: <path>` directive
+    schemas: DashMap<Url, Arc<Schema>>,
 }
 
 impl Backend {
     fn new(client: Client) -> Self {
         Self {
             client,
+            loaded_schemas: DashMap::new(),
             results: DashMap::new(),
+            schemas: DashMap::new(),
+        }
+    }
+
+    // Returns the schema at the path along with its load errors, reading the file only if it changed since it was
+    // last loaded.
+    fn load_schema(&self, path: &Path) -> std::result::Result<(Arc<Schema>, Vec<String>), pcrl::schema::LoadError> {
+        let path = &resolve_path(path);
+        let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+
+        if let (Some(loaded), Some(modified)) = (self.loaded_schemas.get(path), modified) {
+            if loaded.modified == modified {
+                return Ok((loaded.schema.clone(), loaded.errors.clone()));
+            }
+        }
+
+        let (schema, errors) = pcrl::schema::load_file(path)?;
+        let schema = Arc::new(schema);
+        let errors = errors.iter().map(|error| error.to_string()).collect::<Vec<_>>();
+
+        if let Some(modified) = modified {
+            self.loaded_schemas.insert(path.to_path_buf(), LoadedSchema {
+                errors: errors.clone(),
+                modified,
+                schema: schema.clone(),
+            });
         }
+
+        Ok((schema, errors))
+    }
+
+    // Loads the schema named by the document's directive, if any, and validates the document against it.
+    fn schema_diagnostics(&self, uri: &Url, result: &pcrl::ParseResult<LineColumnIndex>) -> Vec<Diagnostic> {
+        self.schemas.remove(uri);
+
+        let Some(object) = &result.object else {
+            return Vec::new();
+        };
+
+        let (Some(directive), Ok(path)) = (pcrl::schema::schema_directive(object), uri.to_file_path()) else {
+            return Vec::new();
+        };
+
+        let directive_diagnostic = |message: String, severity| Diagnostic {
+            message,
+            range: span_range(&directive.span),
+            severity: Some(severity),
+            source: Some("schema".to_string()),
+            ..Default::default()
+        };
+
+        let (schema, load_errors) = match self.load_schema(&pcrl::schema::directive_path(&path, &directive.value)) {
+            Ok(loaded) => loaded,
+            Err(error) => return vec![directive_diagnostic(error.to_string(), DiagnosticSeverity::ERROR)],
+        };
+
+        let mut diagnostics = load_errors
+            .into_iter()
+            .map(|message| directive_diagnostic(message, DiagnosticSeverity::WARNING))
+            .collect::<Vec<_>>();
+
+        diagnostics.extend(pcrl::validate(object, &schema).iter().map(|violation| {
+            Diagnostic {
+                code: Some(NumberOrString::String(violation.schema_path.clone())),
+                message: violation.kind.to_string(),
+                range: span_range(&violation.span),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("schema".to_string()),
+                ..Default::default()
+            }
+        }));
+
+        self.schemas.insert(uri.clone(), schema);
+        diagnostics
     }
 
     async fn on_change(&self, uri: &Url, version: i32, text: &str) {
         let result = pcrl::parse::<pcrl::indexers::LspUtf16>(text);
 
-        let diagnostics = result.errors
+        let mut diagnostics = result.errors
             .iter()
             .map(|error| {
                 Diagnostic {
-                    message: format!("{:?}", error.value),
-                    range: Range {
-                        end: Position {
-                            character: error.span.1.index.column as u32,
-                            line: error.span.1.index.line as u32,
-                        },
-                        start: Position {
-                            character: error.span.0.index.column as u32,
-                            line: error.span.0.index.line as u32,
-                        },
-                    },
+                    message: error.value.to_string(),
+                    range: span_range(&error.span),
                     severity: Some(DiagnosticSeverity::ERROR),
                     ..Default::default()
                 }
             })
             .collect::<Vec<_>>();
 
+        diagnostics.extend(self.schema_diagnostics(uri, &result));
+
         self.results.insert(uri.clone(), result);
         self.client.publish_diagnostics(uri.clone(), diagnostics, Some(version)).await;
     }
@@ -53,7 +341,9 @@ impl LanguageServer for Backend {
 
         Ok(InitializeResult {
             capabilities: ServerCapabilities {
+                completion_provider: Some(CompletionOptions::default()),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
+                selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
                 text_document_sync: Some(TextDocumentSyncCapability::Kind(
                     TextDocumentSyncKind::FULL,
                 )),
@@ -73,6 +363,26 @@ impl LanguageServer for Backend {
     }
 
     async fn initialized(&self, _: InitializedParams) {
+        // Schema files are reloaded when the client reports a change, on top of the modification time check.
+        let watchers = DidChangeWatchedFilesRegistrationOptions {
+            watchers: vec![FileSystemWatcher {
+                glob_pattern: GlobPattern::String("**/*.json".to_string()),
+                kind: None,
+            }],
+        };
+
+        let registration = Registration {
+            id: "schema-watcher".to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(watchers).ok(),
+        };
+
+        if self.client.register_capability(vec![registration]).await.is_err() {
+            self.client
+                .log_message(MessageType::WARNING, "schema files are not watched")
+                .await;
+        }
+
         self.client
             .log_message(MessageType::INFO, "server initialized!")
             .await;
@@ -90,65 +400,147 @@ impl LanguageServer for Backend {
         self.on_change(&params.text_document.uri, params.text_document.version, &params.content_changes[0].text).await;
     }
 
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        for change in params.changes {
+            if let Ok(path) = change.uri.to_file_path() {
+                self.loaded_schemas.remove(&resolve_path(&path));
+            }
+        }
+    }
+
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
         self.results.remove(&params.text_document.uri);
+        self.schemas.remove(&params.text_document.uri);
     }
 
-    // TextDocumentPositionParams { text_document: TextDocumentIdentifier { uri: Url { scheme: "file", cannot_be_a_base: false, username: "", password: None, host: None, port: None, path: "/Users/simon/Downloads/Untitled-1.txt", query: None, fragment: None } }, position: Position { line: 6, character: 0 } }
-    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
-        // let contents = HoverContents::Scalar(MarkedString::String("Hello, world!".to_owned()));
-        let result = self.results.get(&params.text_document_position_params.text_document.uri);
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        let uri = &params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
 
-        let position = params.text_document_position_params.position;
+        let (Some(result), Some(schema)) = (self.results.get(uri), self.schemas.get(uri)) else {
+            return Ok(None);
+        };
+
+        let index = LineColumnIndex {
+            line: position.line as usize,
+            column: position.character as usize,
+        };
+
+        let indent_width = pcrl::infer_indent_width(&result);
+
+        let items = match pcrl::find_completion(result.value(), index) {
+            Some(pcrl::Completion::Item { list, mut path }) => {
+                let item_index = match list {
+                    pcrl::ExpandedValue::List { items, .. } => items.iter().filter(|item| item.value.span.0.index < index).count(),
+                    _ => 0,
+                };
+
+                path.push(pcrl::FindPathItem::ListIndex(item_index));
+                let schemas = schema.schemas_at(&path);
 
-        match result {
-            Some(result) => {
-                let find_result = pcrl::find(result.value(), pcrl::indexers::LineColumnIndex {
-                    line: position.line as usize,
-                    column: position.character as usize,
-                }, false);
-
-                match find_result {
-                    Some(pcrl::FindResult::MapKey { entry, path }) => {
-                        let mut contents = vec![MarkedString::String(format!("Key: {}", entry.key.value))];
-                        contents.extend(
-                            entry.context.comments
-                                .iter()
-                                .map(|comment| MarkedString::String(comment.contents.value.clone()))
-                                // .collect::<Vec<_>>()
-                        );
-
-                        Ok(Some(Hover {
-                            // contents: HoverContents::Scalar(MarkedString::String(format!("Key: {}\n{}", entry.key.value, comments))),
-                            contents: HoverContents::Array(contents),
-                            range: None,
-                        }))
-                    },
-                    Some(pcrl::FindResult::Value { object, path }) => {
-                        Ok(Some(Hover {
-                            contents: HoverContents::Scalar(MarkedString::String(format!("Path: {:?}", path))),
-                            range: None,
-                        }))
-                    },
-                    None => {
-                        Ok(Some(Hover {
-                            contents: HoverContents::Scalar(MarkedString::String("Not found".to_string())),
-                            range: None,
-                        }))
-                    },
+                let mut items = value_completions(&schemas);
+                items.extend(property_completions(&schemas, &[], indent_width));
+                items
+            },
+            Some(pcrl::Completion::Key { map, path }) =>
+                property_completions(&schema.schemas_at(&path), &map_keys(map), indent_width),
+            Some(pcrl::Completion::Value { key, nested, mut path, .. }) => {
+                path.push(pcrl::FindPathItem::MapKey(key));
+                let schemas = schema.schemas_at(&path);
+
+                if nested {
+                    property_completions(&schemas, &[], indent_width)
+                } else {
+                    value_completions(&schemas)
                 }
+            },
+            // A scalar value being typed
+            None => match pcrl::find(result.value(), index, true) {
+                Some(pcrl::FindResult::CompactValue { path, .. } | pcrl::FindResult::Value { path, .. }) => value_completions(&schema.schemas_at(&path)),
+                _ => Vec::new(),
+            },
+        };
+
+        Ok(Some(CompletionResponse::Array(items)))
+    }
+
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let uri = &params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+
+        let Some(result) = self.results.get(uri) else {
+            return Ok(None);
+        };
+
+        let schema = self.schemas.get(uri).map(|schema| schema.clone());
 
-                // eprintln!("{:#?}", x);
+        let index = LineColumnIndex {
+            line: position.line as usize,
+            column: position.character as usize,
+        };
 
-                // Ok(Some(Hover {
-                //     contents,
-                //     range: None,
-                // }))
+        let (markdown, span) = match pcrl::find(result.value(), index, false) {
+            Some(pcrl::FindResult::CompactMapKey { entry, mut path, .. }) => {
+                path.push(pcrl::FindPathItem::MapKey(&entry.key.value));
+                (hover_markdown(&path, compact_type_name(&entry.value.value), schema.as_deref(), None), entry.key.span)
             },
-            None => {
-                Ok(None)
+            Some(pcrl::FindResult::CompactValue { object, path, .. }) =>
+                (hover_markdown(&path, compact_type_name(&object.value), schema.as_deref(), None), object.span),
+            Some(pcrl::FindResult::MapKey { entry, mut path, .. }) => {
+                path.push(pcrl::FindPathItem::MapKey(&entry.key.value));
+                (hover_markdown(&path, type_name(&entry.value.value), schema.as_deref(), entry.context.doc_comment()), entry.key.span)
             },
-        }
+            Some(pcrl::FindResult::Value { entry, object, path, .. }) =>
+                (hover_markdown(&path, type_name(&object.value), schema.as_deref(), entry.and_then(|entry| entry.context.doc_comment())), object.span),
+            None => return Ok(None),
+        };
+
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: markdown,
+            }),
+            range: Some(span_range(&span)),
+        }))
+    }
+
+    // Expands from the key or value under the cursor to its entry, then to the enclosing collections.
+    async fn selection_range(&self, params: SelectionRangeParams) -> Result<Option<Vec<SelectionRange>>> {
+        let Some(result) = self.results.get(&params.text_document.uri) else {
+            return Ok(None);
+        };
+
+        let selection_ranges = params.positions.iter().map(|position| {
+            let index = LineColumnIndex {
+                line: position.line as usize,
+                column: position.character as usize,
+            };
+
+            let mut spans = match pcrl::find(result.value(), index, true) {
+                Some(find_result) => [find_result.span()].into_iter().chain(find_result.ancestors().iter().copied()).collect::<Vec<_>>(),
+                None => Vec::new(),
+            };
+
+            spans.dedup();
+
+            let selection_range = spans.iter().rev().fold(None, |parent, span| {
+                Some(Box::new(SelectionRange {
+                    parent,
+                    range: span_range(span),
+                }))
+            });
+
+            // An empty range at the position when there is nothing to select
+            selection_range.map(|selection_range| *selection_range).unwrap_or(SelectionRange {
+                parent: None,
+                range: Range {
+                    end: *position,
+                    start: *position,
+                },
+            })
+        }).collect();
+
+        Ok(Some(selection_ranges))
     }
 }
 